@@ -0,0 +1,306 @@
+use crate::config::ClipboardConfig;
+use crate::terminal::Terminal;
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+
+/// Which system selection a register stands for: `"+` is the clipboard,
+/// `"*` the primary selection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    Clipboard,
+    Primary,
+}
+
+impl Target {
+    pub fn from_register(name: char) -> Option<Self> {
+        match name {
+            '+' => Some(Target::Clipboard),
+            '*' => Some(Target::Primary),
+            _ => None,
+        }
+    }
+}
+
+/// Somewhere the `"+` and `"*` registers can be read from and written to.
+pub trait ClipboardProvider {
+    fn name(&self) -> &'static str;
+
+    fn copy(&mut self, target: Target, text: &str) -> io::Result<()>;
+
+    /// Current contents of `target`, or `None` if the provider can't read it.
+    fn paste(&mut self, target: Target) -> io::Result<Option<String>>;
+
+    /// Sends anything the provider has queued for the terminal.
+    fn flush(&mut self, _terminal: &Terminal) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Picks the provider set in `[clipboard]`: `"command"` runs the configured
+/// `copy`/`paste` commands, `"osc52"` asks the terminal to set the clipboard
+/// (which works over SSH) and `"none"` turns it off. Without a setting,
+/// configured commands win and OSC 52 is the default.
+pub fn from_config(config: &ClipboardConfig) -> Box<dyn ClipboardProvider> {
+    let commands = || CommandProvider {
+        copy: config.copy.clone(),
+        paste: config.paste.clone(),
+        primary_copy: config.primary_copy.clone(),
+        primary_paste: config.primary_paste.clone(),
+    };
+    match config.provider.as_deref() {
+        Some("none") => Box::new(NoClipboard),
+        Some("osc52") => Box::new(Osc52::default()),
+        Some("command") | None if config.copy.is_some() || config.paste.is_some() => Box::new(commands()),
+        None => Box::new(Osc52::default()),
+        Some(_) => Box::new(NoClipboard),
+    }
+}
+
+/// Does nothing; `"+` and `"*` then only hold what was put in them here.
+pub struct NoClipboard;
+
+impl ClipboardProvider for NoClipboard {
+    fn name(&self) -> &'static str {
+        "none"
+    }
+
+    fn copy(&mut self, _target: Target, _text: &str) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn paste(&mut self, _target: Target) -> io::Result<Option<String>> {
+        Ok(None)
+    }
+}
+
+/// Sets the clipboard with the OSC 52 escape sequence. Terminals don't
+/// reliably answer requests for the clipboard, so pasting isn't supported.
+#[derive(Default)]
+pub struct Osc52 {
+    queued: Vec<String>,
+}
+
+impl ClipboardProvider for Osc52 {
+    fn name(&self) -> &'static str {
+        "osc52"
+    }
+
+    fn copy(&mut self, target: Target, text: &str) -> io::Result<()> {
+        let selection = match target {
+            Target::Clipboard => 'c',
+            Target::Primary => 'p',
+        };
+        self.queued.push(format!("\x1b]52;{};{}\x07", selection, base64(text.as_bytes())));
+        Ok(())
+    }
+
+    fn paste(&mut self, _target: Target) -> io::Result<Option<String>> {
+        Ok(None)
+    }
+
+    fn flush(&mut self, terminal: &Terminal) -> io::Result<()> {
+        for sequence in self.queued.drain(..) {
+            terminal.write_escape(&sequence)?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs external programs such as `wl-copy`/`wl-paste` or `xclip`. The
+/// primary selection uses its own commands if set, and the clipboard's
+/// otherwise.
+pub struct CommandProvider {
+    copy: Option<String>,
+    paste: Option<String>,
+    primary_copy: Option<String>,
+    primary_paste: Option<String>,
+}
+
+impl CommandProvider {
+    fn command(line: &str) -> io::Result<Command> {
+        let mut words = line.split_whitespace();
+        let program = words
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "empty clipboard command"))?;
+        let mut command = Command::new(program);
+        command.args(words);
+        Ok(command)
+    }
+
+    fn failed(line: &str, status: std::process::ExitStatus) -> io::Error {
+        io::Error::other(format!("{} failed ({})", line, status))
+    }
+}
+
+impl ClipboardProvider for CommandProvider {
+    fn name(&self) -> &'static str {
+        "command"
+    }
+
+    fn copy(&mut self, target: Target, text: &str) -> io::Result<()> {
+        let line = match target {
+            Target::Primary => self.primary_copy.as_ref().or(self.copy.as_ref()),
+            Target::Clipboard => self.copy.as_ref(),
+        };
+        let Some(line) = line else {
+            return Ok(());
+        };
+        let mut child = Self::command(line)?
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(text.as_bytes())?;
+        }
+        let status = child.wait()?;
+        if status.success() {
+            Ok(())
+        } else {
+            Err(Self::failed(line, status))
+        }
+    }
+
+    fn paste(&mut self, target: Target) -> io::Result<Option<String>> {
+        let line = match target {
+            Target::Primary => self.primary_paste.as_ref().or(self.paste.as_ref()),
+            Target::Clipboard => self.paste.as_ref(),
+        };
+        let Some(line) = line else {
+            return Ok(None);
+        };
+        let output = Self::command(line)?.stdin(Stdio::null()).stderr(Stdio::null()).output()?;
+        if !output.status.success() {
+            return Err(Self::failed(line, output.status));
+        }
+        Ok(Some(String::from_utf8_lossy(&output.stdout).into_owned()))
+    }
+}
+
+/// Standard base64 with padding, as OSC 52 wants.
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(n >> (18 - 6 * i)) as usize & 63] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    /// A stand-in for `wl-copy`/`wl-paste`: a script that keeps each
+    /// selection in a file under a fresh directory.
+    fn stand_in(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("fervim-clipboard-{}-{}", test, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let script = dir.join("clip.sh");
+        let body = "case $1 in\n  copy) cat > \"$0.$2\" ;;\n  paste) cat \"$0.$2\" ;;\n  fail) exit 3 ;;\nesac\n";
+        fs::write(&script, body).unwrap();
+        script
+    }
+
+    fn command(script: &Path, action: &str, selection: &str) -> Option<String> {
+        Some(format!("sh {} {} {}", script.display(), action, selection))
+    }
+
+    #[test]
+    fn command_provider_round_trips_text() {
+        let script = stand_in("round-trip");
+        let mut provider = CommandProvider {
+            copy: command(&script, "copy", "clipboard"),
+            paste: command(&script, "paste", "clipboard"),
+            primary_copy: None,
+            primary_paste: None,
+        };
+        let text = "first line\n  second, with ünïcode\n";
+        provider.copy(Target::Clipboard, text).unwrap();
+        assert_eq!(provider.paste(Target::Clipboard).unwrap().as_deref(), Some(text));
+        // Without commands of its own the primary selection uses the clipboard's.
+        assert_eq!(provider.paste(Target::Primary).unwrap().as_deref(), Some(text));
+        fs::remove_dir_all(script.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn command_provider_keeps_the_primary_selection_apart() {
+        let script = stand_in("primary");
+        let mut provider = CommandProvider {
+            copy: command(&script, "copy", "clipboard"),
+            paste: command(&script, "paste", "clipboard"),
+            primary_copy: command(&script, "copy", "primary"),
+            primary_paste: command(&script, "paste", "primary"),
+        };
+        provider.copy(Target::Clipboard, "clipboard").unwrap();
+        provider.copy(Target::Primary, "primary").unwrap();
+        assert_eq!(provider.paste(Target::Clipboard).unwrap().as_deref(), Some("clipboard"));
+        assert_eq!(provider.paste(Target::Primary).unwrap().as_deref(), Some("primary"));
+        fs::remove_dir_all(script.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn command_provider_reports_failures() {
+        let script = stand_in("fail");
+        let mut provider = CommandProvider {
+            copy: command(&script, "fail", "clipboard"),
+            paste: command(&script, "fail", "clipboard"),
+            primary_copy: None,
+            primary_paste: None,
+        };
+        assert!(provider.copy(Target::Clipboard, "text").is_err());
+        assert!(provider.paste(Target::Clipboard).is_err());
+        let mut unset = CommandProvider { copy: None, paste: None, primary_copy: None, primary_paste: None };
+        assert!(unset.copy(Target::Clipboard, "text").is_ok());
+        assert_eq!(unset.paste(Target::Clipboard).unwrap(), None);
+        fs::remove_dir_all(script.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn picks_the_configured_provider() {
+        let name = |provider: Option<&str>, copy: Option<&str>| {
+            let config = ClipboardConfig {
+                provider: provider.map(str::to_string),
+                copy: copy.map(str::to_string),
+                ..ClipboardConfig::default()
+            };
+            from_config(&config).name()
+        };
+        assert_eq!(name(None, None), "osc52");
+        assert_eq!(name(None, Some("wl-copy")), "command");
+        assert_eq!(name(Some("osc52"), Some("wl-copy")), "osc52");
+        assert_eq!(name(Some("none"), None), "none");
+        assert_eq!(name(Some("command"), None), "none");
+        assert_eq!(name(Some("bogus"), None), "none");
+    }
+
+    #[test]
+    fn osc52_queues_escape_sequences() {
+        let mut osc52 = Osc52::default();
+        osc52.copy(Target::Clipboard, "hi").unwrap();
+        osc52.copy(Target::Primary, "").unwrap();
+        assert_eq!(osc52.queued, vec!["\x1b]52;c;aGk=\x07", "\x1b]52;p;\x07"]);
+    }
+
+    #[test]
+    fn base64_known_answers() {
+        // RFC 4648, section 10.
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"foob"), "Zm9vYg==");
+        assert_eq!(base64(b"fooba"), "Zm9vYmE=");
+        assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+        assert_eq!(base64(&[0xff, 0xfe, 0x00]), "//4A");
+    }
+}
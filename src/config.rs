@@ -11,6 +11,9 @@ pub struct ColorsConfig {
     pub command_box_background: Option<String>,
     pub command_box_border: Option<String>,
     pub message_text: Option<String>,
+    pub visual: Option<String>,
+    pub search_match: Option<String>,
+    pub find_target: Option<String>,
 }
 
 #[derive(Debug, Deserialize, Default)]
@@ -35,6 +38,28 @@ pub struct CommandBoxConfig {
     pub text: Option<String>,
 }
 
+#[derive(Debug, Deserialize, Default)]
+pub struct EditorConfig {
+    pub tabstop: Option<usize>,
+    pub shiftwidth: Option<usize>,
+    pub expandtab: Option<bool>,
+    pub iskeyword: Option<String>,
+    pub ignorecase: Option<bool>,
+    pub smartcase: Option<bool>,
+    pub highlight_find_targets: Option<bool>,
+    pub undofile: Option<bool>,
+    pub undodir: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Default)]
+pub struct ClipboardConfig {
+    pub provider: Option<String>,
+    pub copy: Option<String>,
+    pub paste: Option<String>,
+    pub primary_copy: Option<String>,
+    pub primary_paste: Option<String>,
+}
+
 #[derive(Debug, Deserialize, Default)]
 pub struct Config {
     #[serde(default)]
@@ -43,6 +68,10 @@ pub struct Config {
     pub mode_bar: ModeBarConfig,
     #[serde(rename = "command box", default)]
     pub command_box: CommandBoxConfig,
+    #[serde(default)]
+    pub editor: EditorConfig,
+    #[serde(default)]
+    pub clipboard: ClipboardConfig,
 }
 
 impl Config {
@@ -1,66 +1,284 @@
 use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
 use std::io::{self, Write};
+use std::collections::VecDeque;
 use std::fs;
+use std::ops::Range;
 use crossterm::cursor::SetCursorStyle;
 use crossterm::ExecutableCommand;
-use crossterm::style::{SetForegroundColor, SetBackgroundColor, ResetColor, Color};
+use crossterm::style::{SetForegroundColor, SetBackgroundColor, SetAttribute, Attribute, ResetColor, Color};
+use crate::clipboard::{self, ClipboardProvider};
+use crate::encoding::FileEncoding;
+use crate::fileformat::{FileFormat, LineEnding};
+use crate::motion::{self, IsKeyword};
+use crate::hex::{HexBuffer, BYTES_PER_ROW};
+use crate::layout::{Layout, Rect};
+use crate::text_buffer::{LineShift, TextBuffer};
+use crate::register::{Register, RegisterKind, Registers, Store};
+use crate::textobject::TextObjects;
+use crate::undo::{self, Edit, Travel, UndoDistance, UndoTree};
 
+mod buffers;
+mod command;
+mod global;
+mod macros;
+mod marks;
+mod operator;
+mod repeat;
+mod search;
+mod substitute;
+#[cfg(test)]
+mod tests;
+mod visual;
+mod windows;
+
+use buffers::BufferState;
+use operator::{Operator, PendingKey};
+use global::GlobalRun;
+use macros::Recording;
+use marks::{FileMarks, Marks};
+use repeat::{Change, ChangeRecorder};
+use search::SearchOrigin;
+use substitute::Substitution;
+use windows::Window;
+use visual::{BlockInsert, Selection, VisualKind};
+use crate::unicode;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Mode {
     Normal,
     Insert,
+    /// `R`: typed chars take the place of the ones under the cursor.
+    Replace,
     Command,
+    /// An operator has been typed and is waiting for its motion.
+    OperatorPending(Operator),
+    Visual(VisualKind),
+    /// Typing a pattern after `/`, or `?` if `backward`.
+    Search { backward: bool },
+    /// `:s///c` asking whether to replace a match.
+    Confirm,
 }
 
+/// Counts are capped so a stray run of digits can't hang the editor.
+const MAX_COUNT: usize = 99_999;
+
 pub struct Editor {
-    lines: Vec<String>,
+    /// The current window's number; the layout knows windows by these.
+    window_id: usize,
+    /// The other windows.
+    windows: Vec<Window>,
+    next_window_id: usize,
+    layout: Layout,
+    /// The part of the terminal above the mode bar, which the windows share.
+    text_area: Rect,
+    /// The current buffer's number in the buffer list.
+    buffer_number: usize,
+    /// The other buffers in the list, in order of their numbers.
+    buffers: Vec<BufferState>,
+    next_buffer_number: usize,
+    /// The buffer last switched away from, for `:b#` and `:e #`.
+    alternate_buffer: Option<usize>,
+    buffer: TextBuffer,
+    /// Set while the buffer is being edited as raw bytes; takes the place of
+    /// `buffer` until the hex view is closed.
+    hex: Option<HexBuffer>,
     cursor_x: usize,
     cursor_y: usize,
     scroll_offset_y: usize,
+    /// Display columns scrolled off the left of the window, for lines wider
+    /// than it.
+    scroll_offset_x: usize,
     mode: Mode,
+    pending_key: Option<PendingKey>,
+    /// Count typed before a command, like the 3 in `3dd`.
+    count: Option<usize>,
+    /// Count typed before the pending operator; it multiplies the motion's.
+    operator_count: Option<usize>,
+    /// Char index Insert mode was entered at; the text typed since then is
+    /// what `".` holds and what `3i` repeats.
+    insert_start: usize,
+    /// For `3i`: how many times in all the text typed in Insert mode goes in.
+    insert_repeat: Option<usize>,
+    /// Set by `o` and `O`, whose repeats each go on a line of their own.
+    insert_lines: bool,
+    /// Set by Ctrl-O in Insert mode: the editor goes back to Insert mode
+    /// once the one Normal-mode command has been run.
+    resume_insert: bool,
+    /// The chars Replace mode typed over, for Backspace to put back. `None`
+    /// for chars that went in past the end of the line.
+    replaced: Vec<Option<String>>,
+    /// Where the Visual selection was started; the cursor is its other end.
+    visual_anchor: motion::Pos,
+    /// Set by `$` in Visual mode: a block then reaches the end of every
+    /// line, however long.
+    visual_to_end: bool,
+    /// The last Visual selection, for `gv`.
+    last_visual: Option<Selection>,
+    marks: Marks,
+    file_marks: FileMarks,
+    block_insert: Option<BlockInsert>,
     command_input: String,
+    /// The last `f`, `t`, `F` or `T`, for `;` and `,`.
+    last_find: Option<motion::CharSearch>,
+    /// Whether the last search went up the buffer, for `n` and `N`.
+    search_backward: bool,
+    /// The pattern whose matches are highlighted.
+    search_regex: Option<regex::Regex>,
+    search_origin: Option<SearchOrigin>,
+    /// The last `:s`, for repeating it with a bare `:s`.
+    last_substitute: Option<crate::substitute::Substitute>,
+    /// A `:s` waiting on answers in confirm mode.
+    substitution: Option<Substitution>,
+    /// The `:g` being run, if any.
+    global: Option<GlobalRun>,
     filename: Option<String>,
+    format: FileFormat,
+    encoding: FileEncoding,
+    readonly: bool,
     dirty: bool,
+    undo: UndoTree,
+    /// The line the latest changes were made on and its text before them,
+    /// for `U`.
+    line_undo: Option<(usize, String)>,
+    registers: Registers,
+    /// Where `"+` and `"*` go.
+    clipboard: Box<dyn ClipboardProvider>,
+    /// Set while a command line types keys or edits many lines, so that it
+    /// all undoes in one step.
+    undo_batch: bool,
+    /// Register picked with `"x` for the next command.
+    selected_register: Option<char>,
+    /// The macro being recorded with `q`, if any.
+    recording: Option<Recording>,
+    /// Keys macros have yet to type.
+    typeahead: VecDeque<KeyEvent>,
+    /// The register last played with `@`, for `@@`.
+    last_macro: Option<char>,
+    /// Macros played since the last key was typed.
+    macro_runs: usize,
+    /// Goes up with every insert and delete, so a command can tell whether
+    /// it changed the buffer.
+    changedtick: usize,
+    /// The command being typed, for `.`.
+    change: ChangeRecorder,
+    /// The last change made, which `.` repeats.
+    last_change: Option<Change>,
+    iskeyword: IsKeyword,
+    /// What `i` and `a` select after an operator or in Visual mode.
+    text_objects: TextObjects,
     message: String,
     config: crate::config::Config,
 }
 
 impl Editor {
-    pub fn new(filename: Option<String>, config: crate::config::Config) -> io::Result<Self> {
-        let mut lines = Vec::new();
-        let mut dirty = false;
-        let mut message = String::new();
-
-        if let Some(ref path) = filename {
-            match fs::read_to_string(path) {
-                Ok(content) => {
-                    lines = content.lines().map(|s| s.to_string()).collect();
-                    if lines.is_empty() {
-                        lines.push(String::new());
-                    }
-                }
-                Err(e) => {
-                    lines.push(String::new());
-                    dirty = true;
-                    message = format!("Error reading file {}: {}", path, e);
-                }
+    /// Opens the editor on `filenames`, the first one in the current buffer
+    /// and the rest in the buffer list behind it.
+    pub fn new(filenames: Vec<String>, binary: bool, config: crate::config::Config) -> io::Result<Self> {
+        let mut filenames = filenames.into_iter();
+        let BufferState {
+            number,
+            buffer,
+            hex,
+            cursor_x,
+            cursor_y,
+            scroll_offset_y,
+            scroll_offset_x,
+            last_visual,
+            marks,
+            filename,
+            format,
+            encoding,
+            readonly,
+            dirty,
+            undo,
+            line_undo,
+            mut message,
+        } = BufferState::open(1, filenames.next(), binary, &config);
+
+        let iskeyword = match config.editor.iskeyword.as_deref().map(IsKeyword::parse) {
+            Some(Some(iskeyword)) => iskeyword,
+            Some(None) => {
+                message = "Invalid iskeyword in config; using the default".to_string();
+                IsKeyword::default()
             }
-        } else {
-            lines.push(String::new());
-            dirty = true;
-        }
+            None => IsKeyword::default(),
+        };
 
-        Ok(Editor {
-            lines,
-            cursor_x: 0,
-            cursor_y: 0,
-            scroll_offset_y: 0,
+        let mut editor = Editor {
+            window_id: 1,
+            windows: Vec::new(),
+            next_window_id: 2,
+            layout: Layout::Window(1),
+            // Until `resize` says how big the terminal is.
+            text_area: Rect { x: 0, y: 0, width: 80, height: 22 },
+            buffer_number: number,
+            buffers: Vec::new(),
+            next_buffer_number: number + 1,
+            alternate_buffer: None,
+            buffer,
+            hex,
+            cursor_x,
+            cursor_y,
+            scroll_offset_y,
+            scroll_offset_x,
             mode: Mode::Normal,
+            pending_key: None,
+            count: None,
+            operator_count: None,
+            insert_start: 0,
+            insert_repeat: None,
+            insert_lines: false,
+            resume_insert: false,
+            replaced: Vec::new(),
+            visual_anchor: motion::Pos::new(0, 0),
+            visual_to_end: false,
+            last_visual,
+            marks,
+            file_marks: FileMarks::default(),
+            block_insert: None,
             command_input: String::new(),
+            last_find: None,
+            search_backward: false,
+            search_regex: None,
+            search_origin: None,
+            last_substitute: None,
+            substitution: None,
+            global: None,
             filename,
+            format,
+            encoding,
+            readonly,
             dirty,
+            undo,
+            line_undo,
+            registers: Registers::default(),
+            clipboard: clipboard::from_config(&config.clipboard),
+            undo_batch: false,
+            selected_register: None,
+            recording: None,
+            typeahead: VecDeque::new(),
+            last_macro: None,
+            macro_runs: 0,
+            changedtick: 0,
+            change: ChangeRecorder::default(),
+            last_change: None,
+            iskeyword,
+            text_objects: TextObjects::default(),
             message,
             config,
-        })
+        };
+        editor.open_more_files(filenames, binary);
+        Ok(editor)
+    }
+
+    fn undo_dir(config: &crate::config::Config) -> std::path::PathBuf {
+        match config.editor.undodir {
+            Some(ref dir) => match (dir.strip_prefix("~/"), dirs::home_dir()) {
+                (Some(rest), Some(home)) => home.join(rest),
+                _ => std::path::PathBuf::from(dir),
+            },
+            None => undo::default_undo_dir(),
+        }
     }
 
     fn parse_color(color_str: &Option<String>) -> Option<Color> {
@@ -112,38 +330,44 @@ impl Editor {
     }
 
 
-    pub fn draw(&self, terminal: &mut crate::terminal::Terminal) -> io::Result<()> {
-        terminal.clear_screen()?;
-        let (width, height) = terminal.size()?;
-
-        let text_bg_color = Self::parse_color(&self.config.colors.background);
-        let text_fg_color = Self::parse_color(&self.config.colors.text);
-
-        if let Some(bg) = text_bg_color {
-            std::io::stdout().execute(SetBackgroundColor(bg))?;
+    /// Shows the message just above the mode bar. Messages of several lines,
+    /// like the `:registers` listing, grow upwards over the text.
+    fn draw_message(&self, terminal: &crate::terminal::Terminal, width: u16, height: u16) -> io::Result<()> {
+        if self.message.is_empty() {
+            return Ok(());
         }
-        if let Some(fg) = text_fg_color {
+        let message_fg_color = Self::parse_color(&self.config.colors.message_text);
+        if let Some(fg) = message_fg_color {
             std::io::stdout().execute(SetForegroundColor(fg))?;
         }
+        let lines: Vec<&str> = self.message.lines().collect();
+        let bottom = height.saturating_sub(3);
+        let top = bottom.saturating_sub(lines.len() as u16 - 1);
+        for (y, line) in (top..=bottom).zip(&lines) {
+            let line = unicode::truncate_graphemes(line, width as usize);
+            if lines.len() > 1 {
+                let padding = (width as usize).saturating_sub(unicode::grapheme_count(line));
+                terminal.print_line(0, y, &format!("{}{}", line, " ".repeat(padding)))?;
+            } else {
+                terminal.print_line(0, y, line)?;
+            }
+        }
+        std::io::stdout().execute(ResetColor)?;
+        Ok(())
+    }
+
+    pub fn draw(&self, terminal: &mut crate::terminal::Terminal) -> io::Result<()> {
+        terminal.clear_screen()?;
+        let (width, height) = terminal.size()?;
 
-        let mut display_y = 0;
         let mode_bar_height = self.config.mode_bar.height.unwrap_or(2);
         let text_area_height = height.saturating_sub(mode_bar_height);
-
-        for line_index in self.scroll_offset_y..self.lines.len() {
-            if display_y >= text_area_height as usize {
-                break;
-            }
-            let line = &self.lines[line_index];
-            let display_line = if line.len() > width as usize {
-                &line[..width as usize]
-            } else {
-                line
-            };
-            terminal.print_line(0, display_y as u16, display_line)?;
-            display_y += 1;
+        let area = Rect { x: 0, y: 0, width, height: text_area_height };
+        let windows = self.window_rects(area);
+        for &(id, rect) in &windows {
+            self.draw_window(terminal, id, rect, area)?;
         }
-        std::io::stdout().execute(ResetColor)?;
+        let window = windows.iter().find(|(id, _)| *id == self.window_id).map_or(area, |&(_, rect)| rect);
 
         let status_bar_y = height.saturating_sub(mode_bar_height);
         let status_bar_start_color = Self::parse_color(&self.config.mode_bar.primary_color)
@@ -181,14 +405,37 @@ impl Editor {
         let mut status_parts = Vec::new();
         if self.config.mode_bar.show_mode.unwrap_or(true) {
             status_parts.push(match self.mode {
+                Mode::Normal if self.resume_insert => "(INSERT)",
                 Mode::Normal => "NORMAL",
                 Mode::Insert => "INSERT",
+                Mode::Replace => "REPLACE",
                 Mode::Command => "COMMAND",
+                Mode::OperatorPending(_) => "O-PENDING",
+                Mode::Visual(kind) => kind.name(),
+                Mode::Search { .. } => "SEARCH",
+                Mode::Confirm => "CONFIRM",
             }.to_string());
         }
+        if let Some(recording) = &self.recording {
+            status_parts.push(format!("recording @{}", recording.register));
+        }
+        if let Some(count) = self.pending_count() {
+            status_parts.push(count.to_string());
+        }
         if self.config.mode_bar.show_filename.unwrap_or(true) {
             status_parts.push(self.filename.as_deref().unwrap_or("[No Name]").to_string());
         }
+        if self.readonly {
+            status_parts.push("[RO]".to_string());
+        }
+        if self.hex.is_some() {
+            status_parts.push("hex".to_string());
+        } else {
+            status_parts.push(self.encoding.name());
+        }
+        if self.format.line_ending != LineEnding::Lf {
+            status_parts.push(format!("[{}]", self.format.line_ending.name()));
+        }
         if self.config.mode_bar.show_dirty_indicator.unwrap_or(true) && self.dirty {
             status_parts.push("[Modified]".to_string());
         }
@@ -205,8 +452,8 @@ impl Editor {
 
 
         match self.mode {
-            Mode::Command => {
-                let box_width = self.config.command_box.width.unwrap_or((width as f32 * 0.6).min(80.0).max(40.0) as u16);
+            Mode::Command | Mode::Search { .. } | Mode::Confirm => {
+                let box_width = self.config.command_box.width.unwrap_or((width as f32 * 0.6).clamp(40.0, 80.0) as u16);
                 let box_height = self.config.command_box.height.unwrap_or(5);
 
                 let start_x = (width / 2).saturating_sub(box_width / 2);
@@ -249,26 +496,28 @@ impl Editor {
                     std::io::stdout().execute(SetForegroundColor(fg))?;
                 }
 
-                let label_text = self.config.command_box.text.as_deref().unwrap_or(" Command box ");
-                let label_x = start_x + (box_width / 2).saturating_sub((label_text.len() / 2) as u16);
+                let default_label = match self.mode {
+                    Mode::Search { .. } => " Search ",
+                    Mode::Confirm => " Substitute ",
+                    _ => " Command box ",
+                };
+                let label_text = self.config.command_box.text.as_deref().unwrap_or(default_label);
+                let label_x = start_x + (box_width / 2).saturating_sub((unicode::grapheme_count(label_text) / 2) as u16);
                 terminal.print_line(label_x, start_y, label_text)?;
 
                 let command_line_y = start_y + 2;
-                let command_prompt = format!(":{}", self.command_input);
-                let display_command = if command_prompt.len() > (box_width as usize).saturating_sub(2) {
-                    &command_prompt[..=(box_width as usize).saturating_sub(2)]
-                } else {
-                    &command_prompt
+                let command_prompt = match self.mode {
+                    Mode::Search { backward: true } => format!("?{}", self.command_input),
+                    Mode::Search { backward: false } => format!("/{}", self.command_input),
+                    Mode::Confirm => self.confirm_prompt(),
+                    _ => format!(":{}", self.command_input),
                 };
+                let display_command = unicode::truncate_graphemes(&command_prompt, (box_width as usize).saturating_sub(2));
                 terminal.print_line(start_x + 1, command_line_y, display_command)?;
 
                 if !self.message.is_empty() {
                     let message_y = start_y + 3;
-                    let display_message = if self.message.len() > (box_width as usize).saturating_sub(2) {
-                        &self.message[..=(box_width as usize).saturating_sub(2)]
-                    } else {
-                        &self.message
-                    };
+                    let display_message = unicode::truncate_graphemes(&self.message, (box_width as usize).saturating_sub(2));
                     let message_fg_color = Self::parse_color(&self.config.colors.message_text);
                     if let Some(fg) = message_fg_color {
                         std::io::stdout().execute(SetForegroundColor(fg))?;
@@ -279,136 +528,601 @@ impl Editor {
                 std::io::stdout().execute(ResetColor)?;
 
                 std::io::stdout().execute(SetCursorStyle::BlinkingBar)?;
-                terminal.cursor_position(start_x + 1 + self.command_input.len() as u16, command_line_y)?;
+                terminal.cursor_position(start_x + 1 + unicode::grapheme_count(&self.command_input) as u16, command_line_y)?;
 
             }
-            Mode::Normal => {
+            Mode::Normal | Mode::OperatorPending(_) | Mode::Visual(_) => {
                 std::io::stdout().execute(SetCursorStyle::BlinkingBlock)?;
-                terminal.cursor_position(self.cursor_x as u16, (self.cursor_y - self.scroll_offset_y) as u16)?;
-                if !self.message.is_empty() {
-                    let message_fg_color = Self::parse_color(&self.config.colors.message_text);
-                    if let Some(fg) = message_fg_color {
-                        std::io::stdout().execute(SetForegroundColor(fg))?;
-                    }
-                    terminal.print_line(0, height - 3, &self.message)?;
-                    std::io::stdout().execute(ResetColor)?;
-                }
+                self.draw_message(terminal, width, height)?;
+                let (cursor_x, cursor_y) = self.cursor_screen_pos(window);
+                terminal.cursor_position(cursor_x, cursor_y)?;
             }
             Mode::Insert => {
                 std::io::stdout().execute(SetCursorStyle::BlinkingBar)?;
-                terminal.cursor_position(self.cursor_x as u16, (self.cursor_y - self.scroll_offset_y) as u16)?;
-                if !self.message.is_empty() {
-                    let message_fg_color = Self::parse_color(&self.config.colors.message_text);
-                    if let Some(fg) = message_fg_color {
-                        std::io::stdout().execute(SetForegroundColor(fg))?;
+                self.draw_message(terminal, width, height)?;
+                let (cursor_x, cursor_y) = self.cursor_screen_pos(window);
+                terminal.cursor_position(cursor_x, cursor_y)?;
+            }
+            Mode::Replace => {
+                std::io::stdout().execute(SetCursorStyle::BlinkingUnderScore)?;
+                self.draw_message(terminal, width, height)?;
+                let (cursor_x, cursor_y) = self.cursor_screen_pos(window);
+                terminal.cursor_position(cursor_x, cursor_y)?;
+            }
+        }
+
+        terminal.flush()?;
+        Ok(())
+    }
+
+    /// Draws window `id` in `rect`: its text, and once the screen is split,
+    /// its status line and the separator to its right.
+    fn draw_window(&self, terminal: &crate::terminal::Terminal, id: usize, rect: Rect, area: Rect) -> io::Result<()> {
+        if rect.width == 0 || rect.height == 0 {
+            return Ok(());
+        }
+        let view = self.view(id);
+        let current = id == self.window_id;
+        let rows = self.text_rows(rect);
+        let width = rect.width;
+
+        let text_bg_color = Self::parse_color(&self.config.colors.background);
+        let text_fg_color = Self::parse_color(&self.config.colors.text);
+        if let Some(bg) = text_bg_color {
+            std::io::stdout().execute(SetBackgroundColor(bg))?;
+        }
+        if let Some(fg) = text_fg_color {
+            std::io::stdout().execute(SetForegroundColor(fg))?;
+        }
+
+        if let Some(hex) = view.hex {
+            let visible_rows = hex.scroll_row()..hex.row_count();
+            for (display_y, row) in visible_rows.take(rows as usize).enumerate() {
+                let display_row = hex.render_row(row);
+                terminal.print_line(rect.x, rect.y + display_y as u16, unicode::truncate_graphemes(&display_row, width as usize))?;
+            }
+        } else {
+            let visible_lines = view.scroll_offset_y..view.buffer.len_lines();
+            for (display_y, line_index) in visible_lines.take(rows as usize).enumerate() {
+                let display_y = rect.y + display_y as u16;
+                let line = view.buffer.line(line_index);
+                let left = view.scroll_offset_x;
+                let display_line = unicode::render_line(&line, left, width as usize, self.tabstop());
+                terminal.print_line(rect.x, display_y, &display_line)?;
+                let search_matches = self.search_match_cols(&line);
+                if !search_matches.is_empty() {
+                    match Self::parse_color(&self.config.colors.search_match) {
+                        Some(bg) => std::io::stdout().execute(SetBackgroundColor(bg))?,
+                        None => std::io::stdout()
+                            .execute(SetBackgroundColor(Color::Yellow))?
+                            .execute(SetForegroundColor(Color::Black))?,
+                    };
+                    for cols in search_matches {
+                        let (x, matched) = unicode::render_cols(&line, cols, left, width as usize, self.tabstop());
+                        terminal.print_line(rect.x + x as u16, display_y, &matched)?;
                     }
-                    terminal.print_line(0, height - 3, &self.message)?;
                     std::io::stdout().execute(ResetColor)?;
+                    if let Some(bg) = text_bg_color {
+                        std::io::stdout().execute(SetBackgroundColor(bg))?;
+                    }
+                    if let Some(fg) = text_fg_color {
+                        std::io::stdout().execute(SetForegroundColor(fg))?;
+                    }
                 }
+                if current && line_index == self.cursor_y {
+                    let targets = self.find_target_cols();
+                    if !targets.is_empty() {
+                        let fg = Self::parse_color(&self.config.colors.find_target).unwrap_or(Color::Red);
+                        std::io::stdout().execute(SetForegroundColor(fg))?.execute(SetAttribute(Attribute::Underlined))?;
+                        for col in targets {
+                            let (x, target) = unicode::render_cols(&line, col..col + 1, left, width as usize, self.tabstop());
+                            terminal.print_line(rect.x + x as u16, display_y, &target)?;
+                        }
+                        std::io::stdout().execute(SetAttribute(Attribute::NoUnderline))?;
+                        match text_fg_color {
+                            Some(fg) => std::io::stdout().execute(SetForegroundColor(fg))?,
+                            None => std::io::stdout().execute(SetForegroundColor(Color::Reset))?,
+                        };
+                    }
+                }
+                if let Some(cols) = self.visual_cols(line_index).filter(|_| current) {
+                    let (x, selected) = unicode::render_cols(&line, cols, left, width as usize, self.tabstop());
+                    match Self::parse_color(&self.config.colors.visual) {
+                        Some(bg) => std::io::stdout().execute(SetBackgroundColor(bg))?,
+                        None => std::io::stdout().execute(SetAttribute(Attribute::Reverse))?,
+                    };
+                    terminal.print_line(rect.x + x as u16, display_y, &selected)?;
+                    std::io::stdout().execute(SetAttribute(Attribute::NoReverse))?;
+                    match text_bg_color {
+                        Some(bg) => std::io::stdout().execute(SetBackgroundColor(bg))?,
+                        None => std::io::stdout().execute(SetBackgroundColor(Color::Reset))?,
+                    };
+                }
+            }
+        }
+        if rect.x + rect.width < area.x + area.width {
+            for y in rect.y..rect.y + rect.height {
+                terminal.print_line(rect.x + rect.width, y, "│")?;
             }
         }
+        std::io::stdout().execute(ResetColor)?;
+        if !self.windows.is_empty() {
+            self.draw_status_line(terminal, &view, rect, current)?;
+        }
+        Ok(())
+    }
 
-        terminal.flush()?;
+    /// A split window's status line: its file, whether it's been changed
+    /// and where the cursor is. The current window's stands out.
+    fn draw_status_line(&self, terminal: &crate::terminal::Terminal, view: &windows::View, rect: Rect, current: bool) -> io::Result<()> {
+        let mut name = view.filename.unwrap_or("[No Name]").to_string();
+        if view.dirty {
+            name.push_str(" [+]");
+        }
+        if view.readonly {
+            name.push_str(" [RO]");
+        }
+        let position = format!("{},{}", view.cursor_y + 1, view.cursor_x + 1);
+        let width = rect.width as usize;
+        let gap = width.saturating_sub(unicode::grapheme_count(&name) + position.len()).max(1);
+        let status = format!("{}{}{}", name, " ".repeat(gap), position);
+        let status = unicode::truncate_graphemes(&status, width);
+        let padding = width.saturating_sub(unicode::grapheme_count(status));
+        std::io::stdout().execute(SetAttribute(Attribute::Reverse))?;
+        if current {
+            std::io::stdout().execute(SetAttribute(Attribute::Bold))?;
+        }
+        terminal.print_line(rect.x, rect.y + rect.height - 1, &format!("{}{}", status, " ".repeat(padding)))?;
+        std::io::stdout().execute(SetAttribute(Attribute::Reset))?;
         Ok(())
     }
 
     pub fn handle_key_event(&mut self, event: KeyEvent, terminal: &mut crate::terminal::Terminal) -> io::Result<bool> {
-        let (_, height) = terminal.size()?;
-        let text_area_height = height.saturating_sub(self.config.mode_bar.height.unwrap_or(2));
+        let result = self.type_key(event);
+        self.clipboard.flush(terminal)?;
+        result
+    }
 
-        match self.mode {
+    /// Handles a key typed by the user, along with any keys it queues up.
+    fn type_key(&mut self, event: KeyEvent) -> io::Result<bool> {
+        self.record_key(event);
+        self.macro_runs = 0;
+        let result = self.dispatch_key(event);
+        match result {
+            Ok(true) => self.run_typeahead(),
+            result => result,
+        }
+    }
+
+    /// Handles one key in whatever mode the editor is in.
+    fn dispatch_key(&mut self, event: KeyEvent) -> io::Result<bool> {
+        // Asked for each key, as the one before may have gone to another
+        // window.
+        let text_area_height = self.window_height();
+        self.record_change_key(event);
+        let after_ctrl_o = self.resume_insert && self.mode != Mode::Insert;
+        let result = match self.mode {
+            Mode::Normal if self.hex.is_some() => self.handle_hex_normal_mode_key(event, text_area_height),
+            Mode::Insert if self.hex.is_some() => self.handle_hex_insert_mode_key(event, text_area_height),
             Mode::Normal => self.handle_normal_mode_key(event, text_area_height),
             Mode::Insert => self.handle_insert_mode_key(event, text_area_height),
-            Mode::Command => self.handle_command_mode_key(event),
+            Mode::Replace => self.handle_replace_mode_key(event, text_area_height),
+            Mode::Command => self.handle_command_mode_key(event, text_area_height),
+            Mode::OperatorPending(op) => {
+                self.handle_operator_pending_key(op, event, text_area_height);
+                Ok(true)
+            }
+            Mode::Visual(kind) => {
+                self.handle_visual_key(kind, event, text_area_height);
+                Ok(true)
+            }
+            Mode::Search { backward } => {
+                self.handle_search_key(backward, event, text_area_height);
+                Ok(true)
+            }
+            Mode::Confirm => {
+                self.handle_confirm_key(event, text_area_height);
+                Ok(true)
+            }
+        };
+        // Everything done since the editor was last idle in Normal mode is
+        // one undo step.
+        if self.mode == Mode::Normal && !self.undo_batch {
+            self.undo_tree().commit();
         }
+        self.finish_change();
+        if after_ctrl_o && (self.is_idle() || self.mode == Mode::Insert) {
+            self.resume_insert = false;
+            if self.mode == Mode::Normal {
+                self.start_insert();
+            }
+        }
+        // Not every key scrolls, but any may have moved the cursor along its
+        // line, or to another window.
+        self.adjust_scroll(self.window_height());
+        result
     }
 
     fn handle_normal_mode_key(&mut self, event: KeyEvent, text_area_height: u16) -> io::Result<bool> {
         self.message.clear();
+        if let Some(pending) = self.pending_key.take() {
+            if pending == PendingKey::Window {
+                return Ok(self.handle_window_key(event));
+            }
+            self.handle_pending_key(pending, event, text_area_height);
+            return Ok(true);
+        }
+        if self.push_count_key(&event) {
+            return Ok(true);
+        }
+        if let Some(motion) = Self::motion_for_key(&event) {
+            self.run_motion(motion, text_area_height);
+            return Ok(true);
+        }
+        // Commands that hand the count on to a motion or operator.
         match event.code {
-            KeyCode::Char('i') => self.mode = Mode::Insert,
-            KeyCode::Char('h') | KeyCode::Left => self.move_cursor_left(),
-            KeyCode::Char('l') | KeyCode::Right => self.move_cursor_right(),
-            KeyCode::Char('k') | KeyCode::Up => self.move_cursor_up(),
-            KeyCode::Char('j') | KeyCode::Down => self.move_cursor_down(),
-            KeyCode::Char('^') => self.cursor_x = 0,
-            KeyCode::Char('$') => self.cursor_x = self.lines[self.cursor_y].len(),
-            KeyCode::Char('G') => {
-                self.cursor_y = self.lines.len() - 1;
-                self.cursor_x = self.lines[self.cursor_y].len();
-                self.adjust_scroll(text_area_height);
-            },
-            KeyCode::Char('g') => {
-                if event.modifiers.contains(KeyModifiers::CONTROL) {
-                    self.cursor_y = 0;
-                    self.cursor_x = 0;
-                    self.adjust_scroll(text_area_height);
+            KeyCode::Char(c @ ('d' | 'c' | 'y' | '>' | '<')) => {
+                self.start_operator(c);
+                return Ok(true);
+            }
+            KeyCode::Char('g') if !event.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.pending_key = Some(PendingKey::G);
+                return Ok(true);
+            }
+            KeyCode::Char('w') if event.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.pending_key = Some(PendingKey::Window);
+                return Ok(true);
+            }
+            KeyCode::Char(c @ ('f' | 't' | 'F' | 'T')) => {
+                self.pending_key = Some(PendingKey::find(c));
+                return Ok(true);
+            }
+            KeyCode::Char(c @ (';' | ',')) => {
+                self.repeat_find(c == ',', text_area_height);
+                return Ok(true);
+            }
+            KeyCode::Char('"') => {
+                self.pending_key = Some(PendingKey::Register);
+                return Ok(true);
+            }
+            KeyCode::Char(c @ ('\'' | '`')) => {
+                self.pending_key = Some(PendingKey::GotoMark { linewise: c == '\'' });
+                return Ok(true);
+            }
+            KeyCode::Char('@') => {
+                self.pending_key = Some(PendingKey::Macro);
+                return Ok(true);
+            }
+            KeyCode::Char('.') => {
+                let count = self.take_count();
+                self.repeat_change(count);
+                return Ok(true);
+            }
+            KeyCode::Char('x') => {
+                self.start_operator('d');
+                self.run_motion(motion::Motion::Right, text_area_height);
+                return Ok(true);
+            }
+            KeyCode::Char('D') => {
+                self.start_operator('d');
+                self.run_motion(motion::Motion::LineEnd, text_area_height);
+                return Ok(true);
+            }
+            KeyCode::Char('s') if self.cursor_x < self.buffer.line_len(self.cursor_y) => {
+                self.start_operator('c');
+                self.run_motion(motion::Motion::Right, text_area_height);
+                return Ok(true);
+            }
+            KeyCode::Char('C') => {
+                self.start_operator('c');
+                self.run_motion(motion::Motion::LineEnd, text_area_height);
+                return Ok(true);
+            }
+            KeyCode::Char('S') => {
+                self.start_operator('c');
+                self.apply_to_lines(Operator::Change, text_area_height);
+                return Ok(true);
+            }
+            _ => {}
+        }
+
+        // Everything else uses up the count itself.
+        let count = self.take_count().unwrap_or(1);
+        if self.start_visual(&event) {
+            return Ok(true);
+        }
+        let ctrl = event.modifiers.contains(KeyModifiers::CONTROL);
+        match event.code {
+            KeyCode::Char('o') if ctrl => self.step_jumps(count, false, text_area_height),
+            // Terminals send Ctrl-I as Tab.
+            KeyCode::Tab => self.step_jumps(count, true, text_area_height),
+            KeyCode::Char('i') if ctrl => self.step_jumps(count, true, text_area_height),
+            KeyCode::Char('m') => self.pending_key = Some(PendingKey::SetMark),
+            KeyCode::Char(c @ ('i' | 'a' | 'A' | 'I' | 's')) => {
+                let line = self.buffer.line(self.cursor_y);
+                match c {
+                    'a' => self.cursor_x = unicode::next_grapheme_boundary(&line, self.cursor_x),
+                    'A' => self.cursor_x = self.buffer.line_len(self.cursor_y),
+                    'I' => self.cursor_x = motion::first_non_blank(&self.buffer, self.cursor_y),
+                    _ => {}
                 }
+                self.start_insert();
+                if count > 1 {
+                    self.insert_repeat = Some(count);
+                }
+            }
+            KeyCode::Char(c @ ('o' | 'O')) => {
+                self.open_line(c == 'O');
+                if count > 1 {
+                    self.insert_repeat = Some(count);
+                    self.insert_lines = true;
+                }
+            }
+            KeyCode::Char('R') => {
+                self.replaced.clear();
+                self.insert_start = self.cursor_char();
+                self.mode = Mode::Replace;
+            }
+            KeyCode::Char('g') if event.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.cursor_y = 0;
+                self.cursor_x = 0;
+                self.adjust_scroll(text_area_height);
             },
             KeyCode::PageUp => self.page_up(text_area_height),
             KeyCode::PageDown => self.page_down(text_area_height),
+            KeyCode::Char('u') => (0..count).for_each(|_| self.undo()),
+            KeyCode::Char('r') if event.modifiers.contains(KeyModifiers::CONTROL) => (0..count).for_each(|_| self.redo()),
+            KeyCode::Char('U') => self.undo_line(),
+            KeyCode::Char('q') if self.recording.is_some() => self.stop_recording(),
+            KeyCode::Char('q') => self.pending_key = Some(PendingKey::Record),
             KeyCode::Char(':') => {
                 self.mode = Mode::Command;
                 self.command_input.clear();
             },
-            KeyCode::Char('d') => {
-                // Placeholder for delete line (dd)
-                // Need to read next key for 'd'
-            },
+            KeyCode::Char(c @ ('/' | '?')) => self.start_search(c == '?', count),
+            KeyCode::Char('n') => self.search_next(false, count, text_area_height),
+            KeyCode::Char('N') => self.search_next(true, count, text_area_height),
+            KeyCode::Char(c @ ('*' | '#')) => self.search_word(c == '#', count, text_area_height),
+            KeyCode::Char('~') => (0..count).for_each(|_| self.toggle_case_under_cursor()),
+            KeyCode::Char('p') => self.put(true, count),
+            KeyCode::Char('P') => self.put(false, count),
             KeyCode::Esc => {
                 self.command_input.clear();
                 self.mode = Mode::Normal;
             }
             _ => {}
         }
+        self.selected_register = None;
         Ok(true)
     }
 
     fn handle_insert_mode_key(&mut self, event: KeyEvent, text_area_height: u16) -> io::Result<bool> {
         self.message.clear();
+        if let Some(PendingKey::InsertRegister) = self.pending_key.take() {
+            if let Some(register) = self.register_for_ctrl_r(&event) {
+                let at = self.cursor_char();
+                self.insert_text(at, &register.text);
+                self.set_cursor_char(at + register.text.chars().count());
+            }
+            self.adjust_scroll(text_area_height);
+            return Ok(true);
+        }
+        let ctrl = event.modifiers.contains(KeyModifiers::CONTROL);
         match event.code {
+            KeyCode::Char('r') if ctrl => {
+                self.pending_key = Some(PendingKey::InsertRegister);
+            }
+            KeyCode::Char('w') if ctrl => {
+                let at = self.cursor_char();
+                let words = motion::Words { iskeyword: &self.iskeyword, big: false };
+                let start = motion::word_backward(&self.buffer, words, at).max(at - self.cursor_x);
+                self.delete_back_to(self.cursor_x - (at - start));
+            }
+            KeyCode::Char('u') if ctrl => {
+                // The text typed since entering Insert mode goes first, then
+                // the rest of the line up to its indent, then the indent.
+                let line_start = self.cursor_char() - self.cursor_x;
+                let indent = motion::first_non_blank(&self.buffer, self.cursor_y);
+                let from = match self.insert_start.checked_sub(line_start) {
+                    Some(typed) if typed < self.cursor_x && self.insert_start <= self.cursor_char() => typed,
+                    _ if indent < self.cursor_x => indent,
+                    _ => 0,
+                };
+                self.delete_back_to(from);
+            }
+            KeyCode::Char(c @ ('t' | 'd')) if ctrl => self.shift_insert_line(c == 't'),
+            KeyCode::Char('o') if ctrl => {
+                self.insert_repeat = None;
+                self.record_insert_end();
+                self.resume_insert = true;
+                self.mode = Mode::Normal;
+            }
+            KeyCode::Char(_) if ctrl => {}
             KeyCode::Char(c) => {
-                self.lines[self.cursor_y].insert(self.cursor_x, c);
+                let at = self.cursor_char();
+                self.insert_text(at, c.encode_utf8(&mut [0; 4]));
                 self.cursor_x += 1;
-                self.dirty = true;
+            }
+            KeyCode::Tab => {
+                let at = self.cursor_char();
+                let text = if self.config.editor.expandtab.unwrap_or(false) {
+                    let ts = self.tabstop();
+                    " ".repeat(ts - self.cursor_display_col() % ts)
+                } else {
+                    "\t".to_string()
+                };
+                self.insert_text(at, &text);
+                self.cursor_x += text.len();
             }
             KeyCode::Backspace => {
-                if self.cursor_x > 0 {
-                    self.cursor_x -= 1;
-                    self.lines[self.cursor_y].remove(self.cursor_x);
-                    self.dirty = true;
-                } else if self.cursor_y > 0 {
-                    let current_line = self.lines.remove(self.cursor_y);
-                    self.cursor_y -= 1;
-                    self.cursor_x = self.lines[self.cursor_y].len();
-                    self.lines[self.cursor_y].push_str(&current_line);
-                    self.dirty = true;
-                }
+                let line = self.buffer.line(self.cursor_y);
+                self.delete_back_to(unicode::prev_grapheme_boundary(&line, self.cursor_x));
             }
             KeyCode::Delete => {
-                if self.cursor_x < self.lines[self.cursor_y].len() {
-                    self.lines[self.cursor_y].remove(self.cursor_x);
-                    self.dirty = true;
-                } else if self.cursor_y + 1 < self.lines.len() {
-                    let next_line = self.lines.remove(self.cursor_y + 1);
-                    self.lines[self.cursor_y].push_str(&next_line);
-                    self.dirty = true;
+                let at = self.cursor_char();
+                let line_len = self.buffer.line_len(self.cursor_y);
+                if self.cursor_x < line_len {
+                    let line = self.buffer.line(self.cursor_y);
+                    let next = unicode::next_grapheme_boundary(&line, self.cursor_x);
+                    self.delete_text(at..at + (next - self.cursor_x));
+                } else if at < self.buffer.len_chars() {
+                    self.delete_text(at..at + 1);
                 }
             }
             KeyCode::Enter => {
-                let new_line = self.lines[self.cursor_y].split_off(self.cursor_x);
+                let at = self.cursor_char();
+                self.insert_text(at, "\n");
                 self.cursor_y += 1;
-                self.lines.insert(self.cursor_y, new_line);
                 self.cursor_x = 0;
-                self.dirty = true;
             }
+            KeyCode::Left | KeyCode::Right | KeyCode::Up | KeyCode::Down | KeyCode::Home | KeyCode::End => {
+                // Moving around ends the text that `3i` would repeat.
+                self.insert_repeat = None;
+                self.move_cursor_in_insert(event.code);
+            }
+            KeyCode::Esc => {
+                let end = self.cursor_char();
+                if end >= self.insert_start {
+                    self.registers.last_inserted = self.buffer.slice(self.insert_start..end);
+                }
+                self.repeat_insert();
+                self.finish_block_insert();
+                self.leave_insert();
+            }
+            _ => {}
+        }
+        self.adjust_scroll(text_area_height);
+        Ok(true)
+    }
+
+    /// The arrow keys, Home and End in Insert and Replace mode.
+    fn move_cursor_in_insert(&mut self, code: KeyCode) {
+        match code {
             KeyCode::Left => self.move_cursor_left(),
             KeyCode::Right => self.move_cursor_right(),
             KeyCode::Up => self.move_cursor_up(),
             KeyCode::Down => self.move_cursor_down(),
+            KeyCode::Home => self.cursor_x = 0,
+            KeyCode::End => self.cursor_x = self.buffer.line_len(self.cursor_y),
+            _ => {}
+        }
+    }
+
+    /// Backspace, Ctrl-W and Ctrl-U: deletes from column `from` up to the
+    /// cursor. At the start of a line they join it to the line above.
+    fn delete_back_to(&mut self, from: usize) {
+        let at = self.cursor_char();
+        if self.cursor_x > 0 {
+            self.delete_text(at - (self.cursor_x - from)..at);
+            self.cursor_x = from;
+        } else if at > 0 {
+            let (y, x) = self.buffer.char_to_pos(at - 1);
+            self.delete_text(at - 1..at);
+            self.cursor_y = y;
+            self.cursor_x = x;
+        }
+    }
+
+    /// Ctrl-T and Ctrl-D: indents or unindents the line to the next multiple
+    /// of `shiftwidth`, keeping the cursor on the same char.
+    fn shift_insert_line(&mut self, indent: bool) {
+        let line = self.cursor_y;
+        let width = self.indent_width(line);
+        let sw = self.shiftwidth();
+        let new_width = if indent { (width / sw + 1) * sw } else { width.saturating_sub(1) / sw * sw };
+        let old_len = self.buffer.line_len(line);
+        self.set_indent(line, new_width);
+        let new_len = self.buffer.line_len(line);
+        self.cursor_x = (self.cursor_x + new_len).saturating_sub(old_len);
+    }
+
+    /// Back to Normal mode from Insert or Replace mode.
+    fn leave_insert(&mut self) {
+        self.marks.set('^', self.cursor_pos());
+        self.mode = Mode::Normal;
+        self.adjust_cursor_to_line_end();
+    }
+
+    /// Enters Insert mode at the cursor.
+    fn start_insert(&mut self) {
+        self.insert_start = self.cursor_char();
+        self.insert_lines = false;
+        self.mode = Mode::Insert;
+    }
+
+    /// `o` and `O`: opens a new line below or above the cursor's line and
+    /// starts Insert mode on it.
+    fn open_line(&mut self, above: bool) {
+        let at = if above {
+            self.cursor_char() - self.cursor_x
+        } else {
+            self.buffer.line_to_char(self.cursor_y) + self.buffer.line_len(self.cursor_y)
+        };
+        self.insert_text(at, "\n");
+        if !above {
+            self.cursor_y += 1;
+        }
+        self.cursor_x = 0;
+        self.start_insert();
+    }
+
+    /// `gi`: Insert mode again where it was last left, at the `^` mark.
+    pub(super) fn insert_at_last(&mut self) {
+        if let Ok(pos) = self.mark_pos('^') {
+            self.cursor_y = pos.line;
+            self.cursor_x = pos.col;
+        }
+        self.start_insert();
+    }
+
+    fn handle_replace_mode_key(&mut self, event: KeyEvent, text_area_height: u16) -> io::Result<bool> {
+        self.message.clear();
+        match event.code {
+            KeyCode::Char(c) if !event.modifiers.contains(KeyModifiers::CONTROL) => {
+                let at = self.cursor_char();
+                let line = self.buffer.line(self.cursor_y);
+                let original = (self.cursor_x < line.chars().count()).then(|| {
+                    let next = unicode::next_grapheme_boundary(&line, self.cursor_x);
+                    self.buffer.slice(at..at + (next - self.cursor_x))
+                });
+                if let Some(original) = &original {
+                    self.delete_text(at..at + original.chars().count());
+                }
+                self.insert_text(at, c.encode_utf8(&mut [0; 4]));
+                self.cursor_x += 1;
+                self.replaced.push(original);
+            }
+            KeyCode::Enter => {
+                let at = self.cursor_char();
+                self.insert_text(at, "\n");
+                self.cursor_y += 1;
+                self.cursor_x = 0;
+                self.replaced.push(None);
+            }
+            // Past what was typed, Backspace only moves.
+            KeyCode::Backspace => match self.replaced.pop() {
+                Some(Some(original)) => {
+                    let at = self.cursor_char() - 1;
+                    self.delete_text(at..at + 1);
+                    self.insert_text(at, &original);
+                    self.cursor_x -= 1;
+                }
+                Some(None) => {
+                    let line = self.buffer.line(self.cursor_y);
+                    self.delete_back_to(unicode::prev_grapheme_boundary(&line, self.cursor_x));
+                }
+                None => self.move_cursor_left(),
+            },
+            KeyCode::Left | KeyCode::Right | KeyCode::Up | KeyCode::Down | KeyCode::Home | KeyCode::End => {
+                self.replaced.clear();
+                self.move_cursor_in_insert(event.code);
+            }
             KeyCode::Esc => {
-                self.mode = Mode::Normal;
-                self.adjust_cursor_to_line_end();
+                let end = self.cursor_char();
+                if end >= self.insert_start {
+                    self.registers.last_inserted = self.buffer.slice(self.insert_start..end);
+                }
+                self.leave_insert();
             }
             _ => {}
         }
@@ -416,20 +1130,135 @@ impl Editor {
         Ok(true)
     }
 
-    fn handle_command_mode_key(&mut self, event: KeyEvent) -> io::Result<bool> {
+    /// Types the text just inserted again for `3i` and the like.
+    fn repeat_insert(&mut self) {
+        let Some(count) = self.insert_repeat.take() else {
+            return;
+        };
+        let start = self.insert_start;
+        let end = self.cursor_char();
+        if end <= start {
+            return;
+        }
+        let mut text = self.buffer.slice(start..end);
+        if self.insert_lines {
+            text.insert(0, '\n');
+        }
+        let text = text.repeat(count - 1);
+        self.insert_text(end, &text);
+        self.set_cursor_char(end + text.chars().count());
+    }
+
+    fn handle_hex_normal_mode_key(&mut self, event: KeyEvent, text_area_height: u16) -> io::Result<bool> {
         self.message.clear();
+        if self.push_count_key(&event) {
+            return Ok(true);
+        }
+        let count = self.take_count();
+        let n = count.unwrap_or(1);
+        let Some(hex) = self.hex.as_mut() else {
+            return Ok(true);
+        };
+        match event.code {
+            KeyCode::Char('i') => self.mode = Mode::Insert,
+            KeyCode::Char('h') | KeyCode::Left => hex.set_cursor(hex.cursor().saturating_sub(n)),
+            KeyCode::Char('l') | KeyCode::Right => hex.set_cursor(hex.cursor() + n),
+            KeyCode::Char('k') | KeyCode::Up => hex.move_up(n),
+            KeyCode::Char('j') | KeyCode::Down => hex.move_down(n),
+            KeyCode::Char('^') => hex.move_row_start(),
+            KeyCode::Char('$') => {
+                hex.move_down(n - 1);
+                hex.move_row_end();
+            }
+            KeyCode::Char('G') => match count {
+                Some(row) => hex.set_cursor(row.saturating_sub(1) * BYTES_PER_ROW),
+                None => hex.move_to_end(),
+            },
+            KeyCode::Char('g') if event.modifiers.contains(KeyModifiers::CONTROL) => hex.move_to_start(),
+            KeyCode::PageUp => hex.move_up(n * text_area_height as usize),
+            KeyCode::PageDown => hex.move_down(n * text_area_height as usize),
+            KeyCode::Char('u') => (0..n).for_each(|_| self.undo()),
+            KeyCode::Char('r') if event.modifiers.contains(KeyModifiers::CONTROL) => (0..n).for_each(|_| self.redo()),
+            KeyCode::Char(':') => {
+                self.mode = Mode::Command;
+                self.command_input.clear();
+            }
+            _ => {}
+        }
+        if let Some(ref mut hex) = self.hex {
+            hex.adjust_scroll(text_area_height);
+        }
+        Ok(true)
+    }
+
+    /// Insert mode in the hex view overwrites nibbles in place, and past the
+    /// last byte adds new ones.
+    fn handle_hex_insert_mode_key(&mut self, event: KeyEvent, text_area_height: u16) -> io::Result<bool> {
+        self.message.clear();
+        let Some(hex) = self.hex.as_mut() else {
+            return Ok(true);
+        };
         match event.code {
+            KeyCode::Char(c) => {
+                if let Some(edit) = hex.nibble_edit(c) {
+                    let cursor = hex.cursor();
+                    self.apply_edit(&edit);
+                    let Some(hex) = self.hex.as_mut() else {
+                        return Ok(true);
+                    };
+                    hex.advance_nibble();
+                    hex.undo().record(edit, cursor);
+                    self.dirty = true;
+                }
+            }
+            KeyCode::Left => hex.move_left(),
+            KeyCode::Right => hex.move_right(),
+            KeyCode::Up => hex.move_up(1),
+            KeyCode::Down => hex.move_down(1),
+            KeyCode::Esc => {
+                hex.clamp_cursor();
+                self.mode = Mode::Normal;
+            }
+            _ => {}
+        }
+        if let Some(ref mut hex) = self.hex {
+            hex.adjust_scroll(text_area_height);
+        }
+        Ok(true)
+    }
+
+    fn handle_command_mode_key(&mut self, event: KeyEvent, text_area_height: u16) -> io::Result<bool> {
+        self.message.clear();
+        if let Some(PendingKey::InsertRegister) = self.pending_key.take() {
+            if let Some(register) = self.register_for_ctrl_r(&event) {
+                self.command_input.push_str(register.text.trim_end_matches('\n'));
+            }
+            return Ok(true);
+        }
+        match event.code {
+            KeyCode::Char('r') if event.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.pending_key = Some(PendingKey::InsertRegister);
+            }
             KeyCode::Char(c) => {
                 self.command_input.push(c);
             }
             KeyCode::Backspace => {
-                self.command_input.pop();
+                let len = self.command_input.chars().count();
+                let prev = unicode::prev_grapheme_boundary(&self.command_input, len);
+                self.command_input = self.command_input.chars().take(prev).collect();
             }
             KeyCode::Enter => {
                 let command = self.command_input.trim().to_string();
-                let should_continue = self.execute_command(&command)?;
+                let should_continue = self.execute_command(&command, text_area_height)?;
+                self.forget_change();
+                if !command.is_empty() {
+                    self.registers.last_command = command;
+                }
                 self.command_input.clear();
-                self.mode = Mode::Normal;
+                // `:s///c` goes on to ask about each match.
+                if self.mode == Mode::Command {
+                    self.mode = Mode::Normal;
+                }
                 return Ok(should_continue);
             }
             KeyCode::Esc => {
@@ -441,58 +1270,475 @@ impl Editor {
         Ok(true)
     }
 
-    fn execute_command(&mut self, command: &str) -> io::Result<bool> {
-        match command {
-            "q" => {
-                if self.dirty {
-                    self.message = "No write since last change (add ! to override)".to_string();
-                    Ok(true)
-                } else {
-                    Ok(false)
+    /// `:registers`: lists the registers named in `names`, or all of them.
+    fn list_registers(&mut self, names: &str) {
+        let mut lines = vec!["Type Name Content".to_string()];
+        for (name, register) in self.registers.list(self.filename.as_deref()) {
+            if !names.trim().is_empty() && !names.contains(name) {
+                continue;
+            }
+            let kind = match register.kind {
+                RegisterKind::Charwise => 'c',
+                RegisterKind::Linewise => 'l',
+                RegisterKind::Blockwise => 'b',
+            };
+            let content = register.text.replace('\n', "^J").replace('\t', "^I");
+            lines.push(format!("  {}  \"{}   {}", kind, name, content));
+        }
+        self.message = lines.join("\n");
+    }
+
+    /// The register named by the key after Ctrl-R.
+    fn register_for_ctrl_r(&mut self, event: &KeyEvent) -> Option<Register> {
+        match event.code {
+            KeyCode::Char(name) if Registers::is_valid(name) => self.read_register(Some(name)),
+            _ => None,
+        }
+    }
+
+    /// Contents of register `name`, or the unnamed one. `"+` and `"*` come
+    /// from the clipboard provider when it can read them; what was last
+    /// stored in them here is used otherwise.
+    fn read_register(&mut self, name: Option<char>) -> Option<Register> {
+        let stored = self.registers.get(name, self.filename.as_deref());
+        let Some(target) = name.and_then(clipboard::Target::from_register) else {
+            return stored;
+        };
+        match self.clipboard.paste(target) {
+            Ok(Some(text)) => match stored {
+                // Keep the kind of text that was copied from here.
+                Some(register) if register.text == text => Some(register),
+                _ if text.ends_with('\n') => Some(Register { text, kind: RegisterKind::Linewise }),
+                _ => Some(Register::charwise(text)),
+            },
+            Ok(None) => stored,
+            Err(e) => {
+                self.message = format!("Clipboard ({}): {}", self.clipboard.name(), e);
+                stored
+            }
+        }
+    }
+
+    /// Puts text from a delete, change or yank into the register picked with
+    /// `"x`, or the unnamed one.
+    fn store_register(&mut self, register: Register, store: Store) {
+        let mut name = self.selected_register.take();
+        if let Some(read_only) = name.filter(|&c| !Registers::is_writable(c)) {
+            self.message = format!("Register {} is read-only", read_only);
+            name = None;
+        }
+        if let Some(target) = name.and_then(clipboard::Target::from_register) {
+            if let Err(e) = self.clipboard.copy(target, &register.text) {
+                self.message = format!("Clipboard ({}): {}", self.clipboard.name(), e);
+            }
+        }
+        self.registers.store(name, register, store);
+    }
+
+    /// Handles `:set`. Takes `name=value`, `name`, `noname` and `name?`.
+    fn set_option(&mut self, arg: &str) {
+        let (name, value) = match arg.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None => (arg, None),
+        };
+        let (name, query) = match name.strip_suffix('?') {
+            Some(name) => (name, true),
+            None => (name, false),
+        };
+        let (name, enable) = match name.strip_prefix("no") {
+            Some(rest) if matches!(rest, "bomb" | "endofline" | "eol" | "ignorecase" | "ic" | "smartcase" | "scs") => (rest, false),
+            _ => (name, true),
+        };
+
+        match (name, value) {
+            ("fileformat" | "ff", None) if query || enable => {
+                self.message = format!("fileformat={}", self.format.line_ending.name());
+            }
+            ("fileformat" | "ff", Some(value)) => match LineEnding::from_name(value) {
+                Some(ending) => {
+                    if ending != self.format.line_ending {
+                        self.format.line_ending = ending;
+                        self.undo.forget_saved();
+                        self.dirty = true;
+                    }
                 }
+                None => self.message = format!("Invalid fileformat: {}", value),
+            },
+            ("bomb", None) if query => {
+                self.message = if self.format.bom { "bomb" } else { "nobomb" }.to_string();
             }
-            "q!" => Ok(false),
-            "w" => {
-                self.save_file()?;
-                self.message = "File written.".to_string();
-                Ok(true)
+            ("bomb", None) => {
+                if self.format.bom != enable {
+                    self.format.bom = enable;
+                    self.undo.forget_saved();
+                    self.dirty = true;
+                }
             }
-            "wq" => {
-                self.save_file()?;
-                Ok(false)
+            ("endofline" | "eol", None) if query => {
+                self.message = if self.format.final_newline { "endofline" } else { "noendofline" }.to_string();
             }
-            _ => {
-                self.message = format!("Unknown command: {}", command);
-                Ok(true)
+            ("endofline" | "eol", None) => {
+                if self.format.final_newline != enable {
+                    self.format.final_newline = enable;
+                    self.undo.forget_saved();
+                    self.dirty = true;
+                }
+            }
+            ("fileencoding" | "fenc", None) => {
+                self.message = format!("fileencoding={}", self.encoding.name());
+            }
+            ("fileencoding" | "fenc", Some(value)) => match FileEncoding::from_name(value) {
+                Some(encoding) => {
+                    if encoding != self.encoding {
+                        self.encoding = encoding;
+                        if !encoding.is_unicode() {
+                            self.format.bom = false;
+                        }
+                        self.undo.forget_saved();
+                        self.dirty = true;
+                    }
+                }
+                None => self.message = format!("Unknown encoding: {}", value),
+            },
+            ("tabstop" | "ts", None) => {
+                self.message = format!("tabstop={}", self.tabstop());
+            }
+            ("tabstop" | "ts", Some(value)) => match value.parse::<usize>() {
+                Ok(n) if n > 0 => self.config.editor.tabstop = Some(n),
+                _ => self.message = format!("Invalid tabstop: {}", value),
+            },
+            ("iskeyword" | "isk", None) => {
+                self.message = format!("iskeyword={}", self.config.editor.iskeyword.as_deref().unwrap_or(IsKeyword::DEFAULT));
+            }
+            ("iskeyword" | "isk", Some(value)) => match IsKeyword::parse(value) {
+                Some(iskeyword) => {
+                    self.iskeyword = iskeyword;
+                    self.config.editor.iskeyword = Some(value.to_string());
+                }
+                None => self.message = format!("Invalid iskeyword: {}", value),
+            },
+            ("ignorecase" | "ic", None) if query => {
+                self.message = if self.case_options().ignorecase { "ignorecase" } else { "noignorecase" }.to_string();
+            }
+            ("ignorecase" | "ic", None) => self.config.editor.ignorecase = Some(enable),
+            ("smartcase" | "scs", None) if query => {
+                self.message = if self.case_options().smartcase { "smartcase" } else { "nosmartcase" }.to_string();
+            }
+            ("smartcase" | "scs", None) => self.config.editor.smartcase = Some(enable),
+            _ => self.message = format!("Unknown option: {}", name),
+        }
+    }
+
+    /// Switches between editing the text and editing the bytes it's saved as.
+    fn toggle_hex(&mut self) {
+        if let Some(ref hex) = self.hex {
+            let Some(content) = self.encoding.decode(hex.bytes()) else {
+                self.message = format!("Bytes are not valid {}; staying in hex mode", self.encoding.name());
+                return;
+            };
+            let (format, text) = FileFormat::detect(&content);
+            self.format = format;
+            self.hex = None;
+            if self.buffer.to_string() != text {
+                self.delete_text(0..self.buffer.len_chars());
+                self.insert_text(0, &text);
+                self.undo.commit();
+            }
+            self.cursor_y = self.cursor_y.min(self.buffer.len_lines() - 1);
+            self.scroll_offset_y = self.scroll_offset_y.min(self.cursor_y);
+            self.adjust_cursor_to_line_end();
+        } else {
+            let content = self.format.apply(&self.buffer.to_string());
+            match self.encoding.encode(&content) {
+                Some(bytes) => {
+                    let mut hex = HexBuffer::new(bytes);
+                    if self.dirty {
+                        hex.undo().forget_saved();
+                    }
+                    self.hex = Some(hex);
+                }
+                None => self.message = format!("Cannot convert to {}", self.encoding.name()),
+            }
+        }
+    }
+
+    /// Runs a write command, reporting any failure in `message`.
+    fn write_file(&mut self, force: bool) -> bool {
+        match self.save_file(force) {
+            Ok(()) => true,
+            Err(e) => {
+                self.message = e.to_string();
+                false
+            }
+        }
+    }
+
+    /// The buffer as it's written to disk.
+    fn file_bytes(&self) -> io::Result<Vec<u8>> {
+        match self.hex {
+            Some(ref hex) => Ok(hex.bytes().to_vec()),
+            None => {
+                let content = self.format.apply(&self.buffer.to_string());
+                self.encoding.encode(&content).ok_or_else(|| {
+                    io::Error::other(format!("Cannot convert to {}; use :set fileencoding= to pick another", self.encoding.name()))
+                })
             }
         }
     }
 
-    fn save_file(&mut self) -> io::Result<()> {
-        if let Some(ref path) = self.filename {
-            let content = self.lines.join("\n");
-            fs::write(path, content)?;
-            self.dirty = false;
-            Ok(())
+    fn save_file(&mut self, force: bool) -> io::Result<()> {
+        let Some(ref path) = self.filename else {
+            #[allow(clippy::io_other_error)]
+            return Err(io::Error::new(io::ErrorKind::Other, "No filename. Use :w <filename> to save."));
+        };
+        if self.readonly && !force {
+            return Err(io::Error::other("File is read-only (add ! to override)"));
+        }
+        fs::write(path, self.file_bytes()?)?;
+        self.readonly = false;
+        self.dirty = false;
+
+        if let Some(ref mut hex) = self.hex {
+            // The text side hasn't seen the hex edits yet.
+            hex.undo().mark_saved();
+            self.undo.forget_saved();
         } else {
-            self.message = "No filename. Use :w <filename> to save.".to_string();
-            Err(io::Error::new(io::ErrorKind::Other, "No filename"))
+            self.undo.mark_saved();
+            if self.config.editor.undofile.unwrap_or(false) {
+                let undo_path = undo::undo_file_path(&Self::undo_dir(&self.config), path);
+                if let Err(e) = self.undo.save(&undo_path, &self.buffer.to_string()) {
+                    self.message = format!("Could not write undo file {}: {}", undo_path.display(), e);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn tabstop(&self) -> usize {
+        self.config.editor.tabstop.unwrap_or(8)
+    }
+
+    /// Where the terminal cursor goes, with the current window in `rect`.
+    /// It never leaves the window, even where the hex view is cut short.
+    fn cursor_screen_pos(&self, rect: Rect) -> (u16, u16) {
+        let (x, y) = match self.hex {
+            Some(ref hex) => (hex.cursor_col(), hex.cursor_row() - hex.scroll_row()),
+            None => (self.cursor_display_col().saturating_sub(self.scroll_offset_x), self.cursor_y - self.scroll_offset_y),
+        };
+        let x = (x as u16).min(rect.width.saturating_sub(1));
+        (rect.x + x, rect.y + y as u16)
+    }
+
+    /// Screen column the cursor's grapheme starts at.
+    fn cursor_display_col(&self) -> usize {
+        let line = self.buffer.line(self.cursor_y);
+        unicode::display_col(&line, self.cursor_x, self.tabstop())
+    }
+
+    /// Adds a digit typed in Normal or Operator-pending mode to the count.
+    /// `0` only counts after another digit; on its own it's a motion.
+    fn push_count_key(&mut self, event: &KeyEvent) -> bool {
+        let KeyCode::Char(c @ '0'..='9') = event.code else {
+            return false;
+        };
+        if event.modifiers.contains(KeyModifiers::CONTROL) || (c == '0' && self.count.is_none()) {
+            return false;
+        }
+        let digit = c as usize - '0' as usize;
+        self.count = Some((self.count.unwrap_or(0) * 10 + digit).min(MAX_COUNT));
+        self.record_count_key();
+        true
+    }
+
+    /// The count for the command being typed, counting one typed before its
+    /// operator. Only `Some` if there was a count.
+    fn pending_count(&self) -> Option<usize> {
+        match (self.operator_count, self.count) {
+            (None, None) => None,
+            (a, b) => Some((a.unwrap_or(1) * b.unwrap_or(1)).min(MAX_COUNT)),
+        }
+    }
+
+    /// Uses up the pending count.
+    fn take_count(&mut self) -> Option<usize> {
+        let count = self.pending_count();
+        self.count = None;
+        self.operator_count = None;
+        self.record_change_count(count);
+        count
+    }
+
+    /// Char index of the cursor in the buffer.
+    fn cursor_char(&self) -> usize {
+        self.buffer.pos_to_char(self.cursor_y, self.cursor_x)
+    }
+
+    /// Inserts `text` at char index `at`. All insertions go through here.
+    fn insert_text(&mut self, at: usize, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        let cursor = self.cursor_char();
+        self.track_line_undo(at, text);
+        let edit = Edit::Insert { at, text: text.to_string() };
+        self.apply_edit(&edit);
+        let (line, col) = self.buffer.char_to_pos(at);
+        let (end_line, end_col) = self.buffer.char_to_pos(at + text.chars().count() - 1);
+        self.mark_edit(motion::Pos::new(line, col), motion::Pos::new(end_line, end_col));
+        self.undo.record(edit, cursor);
+        self.dirty = true;
+    }
+
+    /// Removes the chars in `range`. All deletions go through here.
+    fn delete_text(&mut self, range: Range<usize>) {
+        if range.is_empty() {
+            return;
+        }
+        let cursor = self.cursor_char();
+        let text = self.buffer.slice(range.clone());
+        self.track_line_undo(range.start, &text);
+        let edit = Edit::Delete { at: range.start, text };
+        self.apply_edit(&edit);
+        let (line, col) = self.buffer.char_to_pos(range.start);
+        self.mark_edit(motion::Pos::new(line, col), motion::Pos::new(line, col));
+        self.undo.record(edit, cursor);
+        self.dirty = true;
+    }
+
+    /// Makes `edit` to the buffer, keeping marks and other windows on their
+    /// lines. Undo and redo make their edits through here too.
+    fn apply_edit(&mut self, edit: &Edit) {
+        match edit {
+            Edit::Insert { at, text } => {
+                if let Some(shift) = self.buffer.insert_shift(*at, text) {
+                    self.shift_lines(shift);
+                }
+                self.buffer.insert(*at, text);
+            }
+            Edit::Delete { at, text } => {
+                let range = *at..*at + text.chars().count();
+                if let Some(shift) = self.buffer.remove_shift(range.clone()) {
+                    self.shift_lines(shift);
+                }
+                self.buffer.remove(range);
+            }
+            Edit::Byte { at, new, .. } => {
+                if let Some(ref mut hex) = self.hex {
+                    hex.set_byte(*at, *new);
+                }
+            }
+        }
+        self.changedtick += 1;
+    }
+
+    /// Keeps `line_undo` pointing at the line being changed. Changes spanning
+    /// lines can't be undone with `U`.
+    fn track_line_undo(&mut self, at: usize, text: &str) {
+        let line = self.buffer.char_to_line(at);
+        if text.contains('\n') {
+            self.line_undo = None;
+        } else if self.line_undo.as_ref().is_none_or(|(changed, _)| *changed != line) {
+            self.line_undo = Some((line, self.buffer.line(line)));
+        }
+    }
+
+    /// Keeps line numbers that should follow the text pointing at the same
+    /// lines after an edit adds or takes some away.
+    fn shift_lines(&mut self, shift: LineShift) {
+        if let Some(global) = &mut self.global {
+            global.shift_lines(shift);
+        }
+        self.marks.shift_lines(shift);
+        self.file_marks.shift_lines(shift, self.filename.as_deref());
+        self.shift_windows(shift);
+    }
+
+    fn set_cursor_char(&mut self, at: usize) {
+        let (y, x) = self.buffer.char_to_pos(at.min(self.buffer.len_chars()));
+        self.cursor_y = y;
+        self.cursor_x = x;
+        self.adjust_cursor_to_line_end();
+    }
+
+    /// The history `u` and friends work on: the bytes' in the hex view, the
+    /// text's otherwise.
+    fn undo_tree(&mut self) -> &mut UndoTree {
+        match self.hex {
+            Some(ref mut hex) => hex.undo(),
+            None => &mut self.undo,
         }
     }
 
+    fn undo(&mut self) {
+        match self.undo_tree().undo() {
+            Some(travel) => self.after_undo(travel),
+            None => self.message = "Already at oldest change".to_string(),
+        }
+    }
+
+    fn redo(&mut self) {
+        match self.undo_tree().redo() {
+            Some(travel) => self.after_undo(travel),
+            None => self.message = "Already at newest change".to_string(),
+        }
+    }
+
+    fn time_travel(&mut self, forward: bool, distance: UndoDistance) {
+        let undo = self.undo_tree();
+        let travel = if forward { undo.later(distance) } else { undo.earlier(distance) };
+        if let Some(travel) = travel {
+            self.after_undo(travel);
+        }
+        self.message = format!("At change #{}", self.undo_tree().seq());
+    }
+
+    fn after_undo(&mut self, travel: Travel) {
+        for edit in &travel.edits {
+            self.apply_edit(edit);
+        }
+        self.forget_undo();
+        self.line_undo = None;
+        match self.hex {
+            Some(ref mut hex) => hex.set_cursor(travel.cursor),
+            None => self.set_cursor_char(travel.cursor),
+        }
+        self.dirty = !self.undo_tree().is_saved();
+    }
+
+    /// `U`: puts the last changed line back the way it was before the latest
+    /// run of changes to it. This is itself a change, so `U` again redoes them.
+    fn undo_line(&mut self) {
+        let Some((line, original)) = self.line_undo.take() else {
+            return;
+        };
+        if line >= self.buffer.len_lines() {
+            return;
+        }
+        self.forget_change();
+        let current = self.buffer.line(line);
+        let start = self.buffer.line_to_char(line);
+        self.cursor_y = line;
+        self.cursor_x = 0;
+        self.delete_text(start..start + self.buffer.line_len(line));
+        self.insert_text(start, &original);
+        self.line_undo = Some((line, current));
+    }
+
     fn move_cursor_left(&mut self) {
         if self.cursor_x > 0 {
-            self.cursor_x -= 1;
+            let line = self.buffer.line(self.cursor_y);
+            self.cursor_x = unicode::prev_grapheme_boundary(&line, self.cursor_x);
         } else if self.cursor_y > 0 {
             self.cursor_y -= 1;
-            self.cursor_x = self.lines[self.cursor_y].len();
+            self.cursor_x = self.buffer.line_len(self.cursor_y);
         }
     }
 
     fn move_cursor_right(&mut self) {
-        if self.cursor_x < self.lines[self.cursor_y].len() {
-            self.cursor_x += 1;
-        } else if self.cursor_y + 1 < self.lines.len() {
+        let line = self.buffer.line(self.cursor_y);
+        if self.cursor_x < self.last_cursor_col(&line) {
+            self.cursor_x = unicode::next_grapheme_boundary(&line, self.cursor_x);
+        } else if self.cursor_y + 1 < self.buffer.len_lines() {
             self.cursor_y += 1;
             self.cursor_x = 0;
         }
@@ -506,7 +1752,7 @@ impl Editor {
     }
 
     fn move_cursor_down(&mut self) {
-        if self.cursor_y + 1 < self.lines.len() {
+        if self.cursor_y + 1 < self.buffer.len_lines() {
             self.cursor_y += 1;
             self.adjust_cursor_to_line_end();
         }
@@ -519,25 +1765,39 @@ impl Editor {
     }
 
     fn page_down(&mut self, text_area_height: u16) {
-        self.cursor_y = (self.cursor_y + text_area_height as usize).min(self.lines.len() - 1);
+        self.cursor_y = (self.cursor_y + text_area_height as usize).min(self.buffer.len_lines() - 1);
         self.adjust_scroll(text_area_height);
         self.adjust_cursor_to_line_end();
     }
 
-    fn adjust_cursor_to_line_end(&mut self) {
-        let current_line_len = self.lines[self.cursor_y].len();
-        if self.cursor_x > current_line_len {
-            self.cursor_x = current_line_len;
+    /// The furthest column the cursor goes on `line`: the last grapheme, or
+    /// just past it in Insert and Replace mode, where text goes before the
+    /// cursor, and in Visual mode, where `$` takes in the line break.
+    fn last_cursor_col(&self, line: &str) -> usize {
+        let len = line.chars().count();
+        match self.mode {
+            Mode::Insert | Mode::Replace | Mode::Visual(_) => len,
+            _ => unicode::prev_grapheme_boundary(line, len),
         }
     }
 
+    fn adjust_cursor_to_line_end(&mut self) {
+        let line = self.buffer.line(self.cursor_y);
+        self.cursor_x = unicode::floor_grapheme_boundary(&line, self.cursor_x.min(self.last_cursor_col(&line)));
+    }
+
     pub fn adjust_scroll(&mut self, text_area_height: u16) {
+        if let Some(ref mut hex) = self.hex {
+            hex.adjust_scroll(text_area_height);
+        }
         if self.cursor_y < self.scroll_offset_y {
             self.scroll_offset_y = self.cursor_y;
         }
         if self.cursor_y >= self.scroll_offset_y + text_area_height as usize {
             self.scroll_offset_y = self.cursor_y - text_area_height as usize + 1;
         }
+        let cursor = unicode::display_span(&self.buffer.line(self.cursor_y), self.cursor_x, self.tabstop());
+        self.scroll_offset_x = windows::scroll_to_col(cursor, self.scroll_offset_x, self.current_rect().width);
     }
 }
 
@@ -0,0 +1,458 @@
+use super::marks::Marks;
+use super::visual::Selection;
+use super::Editor;
+use crate::config::Config;
+use crate::encoding::FileEncoding;
+use crate::ex::CommandLine;
+use crate::fileformat::FileFormat;
+use crate::hex::HexBuffer;
+use crate::text_buffer::TextBuffer;
+use crate::undo::{self, UndoTree};
+use std::{fs, io, mem};
+
+/// What each buffer in the buffer list keeps of its own. The current
+/// buffer's is spread over the editor's fields, and `swap_buffer` trades it
+/// for another's.
+pub struct BufferState {
+    pub(super) number: usize,
+    pub(super) buffer: TextBuffer,
+    pub(super) hex: Option<HexBuffer>,
+    pub(super) cursor_x: usize,
+    pub(super) cursor_y: usize,
+    pub(super) scroll_offset_y: usize,
+    pub(super) scroll_offset_x: usize,
+    pub(super) last_visual: Option<Selection>,
+    pub(super) marks: Marks,
+    pub(super) filename: Option<String>,
+    pub(super) format: FileFormat,
+    pub(super) encoding: FileEncoding,
+    pub(super) readonly: bool,
+    pub(super) dirty: bool,
+    pub(super) undo: UndoTree,
+    pub(super) line_undo: Option<(usize, String)>,
+    pub(super) message: String,
+}
+
+impl BufferState {
+    /// An empty buffer with no file, as `:bdelete` leaves when the last
+    /// buffer goes.
+    fn empty(number: usize) -> Self {
+        BufferState {
+            number,
+            buffer: TextBuffer::default(),
+            hex: None,
+            cursor_x: 0,
+            cursor_y: 0,
+            scroll_offset_y: 0,
+            scroll_offset_x: 0,
+            last_visual: None,
+            marks: Marks::default(),
+            filename: None,
+            format: FileFormat::default(),
+            encoding: FileEncoding::default(),
+            readonly: false,
+            dirty: false,
+            undo: UndoTree::new(true),
+            line_undo: None,
+            message: String::new(),
+        }
+    }
+
+    /// Reads `filename` into a buffer, as raw bytes if `binary`. Problems
+    /// reading it end up in `message`.
+    pub(super) fn open(number: usize, filename: Option<String>, binary: bool, config: &Config) -> Self {
+        let mut state = BufferState::empty(number);
+        if let Some(ref path) = filename {
+            match fs::read(path) {
+                Ok(bytes) if binary => {
+                    state.hex = Some(HexBuffer::new(bytes));
+                }
+                Ok(bytes) => {
+                    let encoding = FileEncoding::detect(&bytes);
+                    let content = match encoding.decode(&bytes) {
+                        Some(content) => content,
+                        None => {
+                            // Never let a bad guess get written back over the original.
+                            state.readonly = true;
+                            state.message = format!("{} is not valid {}; opened read-only", path, encoding.name());
+                            encoding.decode_lossy(&bytes)
+                        }
+                    };
+                    let (detected, text) = FileFormat::detect(&content);
+                    state.buffer = TextBuffer::from(text.as_str());
+                    state.format = detected;
+                    state.encoding = encoding;
+                }
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                    state.message = format!("\"{}\" [New]", path);
+                }
+                Err(e) => {
+                    // A file that exists but can't be read must not be clobbered by `:w`.
+                    state.readonly = true;
+                    state.message = format!("Error reading file {}: {}", path, e);
+                }
+            }
+        }
+        if binary && state.hex.is_none() {
+            state.hex = Some(HexBuffer::new(Vec::new()));
+        }
+
+        state.undo = UndoTree::new(!state.dirty);
+        if let (Some(path), true, None) = (&filename, config.editor.undofile.unwrap_or(false), &state.hex) {
+            let undo_path = undo::undo_file_path(&Editor::undo_dir(config), path);
+            if let Some(tree) = UndoTree::load(&undo_path, &state.buffer.to_string()) {
+                state.undo = tree;
+            }
+        }
+        state.filename = filename;
+        state
+    }
+
+    fn name(&self) -> &str {
+        self.filename.as_deref().unwrap_or("[No Name]")
+    }
+}
+
+/// Whether `a` and `b` name the same file, going by the paths as written or,
+/// if the files exist, where they lead.
+fn same_file(a: &str, b: &str) -> bool {
+    a == b || fs::canonicalize(a).is_ok_and(|a| fs::canonicalize(b).is_ok_and(|b| a == b))
+}
+
+impl Editor {
+    /// Makes `state` the current buffer, handing back the one it replaces.
+    fn swap_buffer(&mut self, mut state: BufferState) -> BufferState {
+        mem::swap(&mut self.buffer_number, &mut state.number);
+        mem::swap(&mut self.buffer, &mut state.buffer);
+        mem::swap(&mut self.hex, &mut state.hex);
+        mem::swap(&mut self.cursor_x, &mut state.cursor_x);
+        mem::swap(&mut self.cursor_y, &mut state.cursor_y);
+        mem::swap(&mut self.scroll_offset_y, &mut state.scroll_offset_y);
+        mem::swap(&mut self.scroll_offset_x, &mut state.scroll_offset_x);
+        mem::swap(&mut self.last_visual, &mut state.last_visual);
+        mem::swap(&mut self.marks, &mut state.marks);
+        mem::swap(&mut self.filename, &mut state.filename);
+        mem::swap(&mut self.format, &mut state.format);
+        mem::swap(&mut self.encoding, &mut state.encoding);
+        mem::swap(&mut self.readonly, &mut state.readonly);
+        mem::swap(&mut self.dirty, &mut state.dirty);
+        mem::swap(&mut self.undo, &mut state.undo);
+        mem::swap(&mut self.line_undo, &mut state.line_undo);
+        mem::swap(&mut self.message, &mut state.message);
+        state
+    }
+
+    /// Puts a buffer in the list, in order of its number.
+    fn hide_buffer(&mut self, state: BufferState) {
+        let at = self.buffers.partition_point(|other| other.number < state.number);
+        self.buffers.insert(at, state);
+    }
+
+    /// Makes `state` the current buffer and keeps the one it replaces in the
+    /// list, as the alternate buffer.
+    fn show_buffer(&mut self, state: BufferState, text_area_height: u16) {
+        let previous = self.swap_buffer(state);
+        self.alternate_buffer = Some(previous.number);
+        self.hide_buffer(previous);
+        self.adjust_cursor_to_line_end();
+        self.adjust_scroll(text_area_height);
+    }
+
+    /// Makes buffer `number` the current one.
+    pub(super) fn switch_to_buffer(&mut self, number: usize, text_area_height: u16) {
+        if let Some(at) = self.buffers.iter().position(|state| state.number == number) {
+            let state = self.buffers.remove(at);
+            self.show_buffer(state, text_area_height);
+        }
+    }
+
+    /// Switches to the buffer for `path`, reading the file into a new one
+    /// if it isn't in the list yet.
+    pub(super) fn edit_file(&mut self, path: &str, text_area_height: u16) {
+        if self.filename.as_deref().is_some_and(|name| same_file(name, path)) {
+            return;
+        }
+        let existing = self.buffers.iter().find(|state| state.filename.as_deref().is_some_and(|name| same_file(name, path)));
+        match existing.map(|state| state.number) {
+            Some(number) => self.switch_to_buffer(number, text_area_height),
+            None => {
+                let state = BufferState::open(self.next_buffer_number, Some(path.to_string()), false, &self.config);
+                self.next_buffer_number += 1;
+                self.show_buffer(state, text_area_height);
+            }
+        }
+    }
+
+    /// Switches to a new buffer with no file, for `:new`.
+    pub(super) fn new_buffer(&mut self, text_area_height: u16) {
+        let state = BufferState::empty(self.next_buffer_number);
+        self.next_buffer_number += 1;
+        self.show_buffer(state, text_area_height);
+    }
+
+    /// The buffer an argument to `:buffer` or `:bdelete` names: a number,
+    /// `%` or `#`, or part of a file name. No argument means the current one.
+    fn buffer_for_arg(&self, arg: &str) -> Result<usize, String> {
+        let arg = arg.trim();
+        let exists = |number: usize| number == self.buffer_number || self.buffers.iter().any(|state| state.number == number);
+        let number = match arg {
+            "" | "%" => Some(self.buffer_number),
+            "#" => match self.alternate_buffer {
+                Some(number) => Some(number),
+                None => return Err("No alternate file".to_string()),
+            },
+            _ => arg.parse().ok(),
+        };
+        if let Some(number) = number {
+            return if exists(number) { Ok(number) } else { Err(format!("Buffer {} does not exist", number)) };
+        }
+        let current = (self.buffer_number, self.filename.as_deref().unwrap_or(""));
+        let all: Vec<_> = std::iter::once(current)
+            .chain(self.buffers.iter().map(|state| (state.number, state.filename.as_deref().unwrap_or(""))))
+            .collect();
+        // A whole file name wins over ones it's only a part of.
+        let exact: Vec<_> = all.iter().filter(|(_, name)| *name == arg).collect();
+        let matches: Vec<_> = if exact.is_empty() {
+            all.iter().filter(|(_, name)| name.contains(arg)).collect()
+        } else {
+            exact
+        };
+        match matches.as_slice() {
+            [(number, _)] => Ok(*number),
+            [] => Err(format!("No matching buffer for {}", arg)),
+            _ => Err(format!("More than one match for {}", arg)),
+        }
+    }
+
+    /// A hidden buffer with changes that haven't been written, if any.
+    fn modified_hidden_buffer(&self) -> Option<&BufferState> {
+        self.buffers.iter().find(|state| state.dirty)
+    }
+
+    /// Whether the editor can quit without losing changes, saying why not in
+    /// `message`. With `force` it always can.
+    pub(super) fn can_quit(&mut self, force: bool) -> bool {
+        if force {
+            return true;
+        }
+        if self.dirty {
+            self.message = "No write since last change (add ! to override)".to_string();
+            return false;
+        }
+        if let Some(state) = self.modified_hidden_buffer() {
+            self.message = format!("No write since last change for buffer \"{}\"", state.name());
+            return false;
+        }
+        true
+    }
+
+    /// `:e[dit] [file]`: edits `file`, in a buffer of its own. With no file
+    /// it reads the current one in again, which `!` does even over changes.
+    pub(super) fn ex_edit(&mut self, command: &CommandLine, text_area_height: u16) -> bool {
+        let path = command.args.trim();
+        if path == "#" {
+            match self.alternate_buffer {
+                Some(number) => self.switch_to_buffer(number, text_area_height),
+                None => self.message = "No alternate file".to_string(),
+            }
+            return true;
+        }
+        if !path.is_empty() {
+            self.edit_file(path, text_area_height);
+            return true;
+        }
+        if self.filename.is_none() {
+            self.message = "No file name".to_string();
+        } else if self.dirty && !command.bang {
+            self.message = "No write since last change (add ! to override)".to_string();
+        } else {
+            let state = BufferState::open(self.buffer_number, self.filename.clone(), self.hex.is_some(), &self.config);
+            let old = self.swap_buffer(state);
+            self.marks = old.marks;
+            self.cursor_y = old.cursor_y.min(self.buffer.len_lines() - 1);
+            self.cursor_x = old.cursor_x;
+            self.adjust_cursor_to_line_end();
+            self.adjust_scroll(text_area_height);
+        }
+        true
+    }
+
+    /// `:b[uffer] {N|name}`: switches to another buffer.
+    pub(super) fn ex_buffer(&mut self, command: &CommandLine, text_area_height: u16) -> bool {
+        match self.buffer_for_arg(command.args) {
+            Ok(number) if number != self.buffer_number => self.switch_to_buffer(number, text_area_height),
+            Ok(_) => {}
+            Err(message) => self.message = message,
+        }
+        true
+    }
+
+    /// `:bn[ext] [N]` and `:bp[revious] [N]`/`:bN[ext] [N]`: goes `N` buffers
+    /// on or back through the list, going round at the ends.
+    pub(super) fn ex_bnext(&mut self, command: &CommandLine, text_area_height: u16) -> bool {
+        let count = match command.args.trim() {
+            "" => 1,
+            arg => match arg.parse::<usize>() {
+                Ok(count) if count > 0 => count,
+                _ => {
+                    self.message = format!("Invalid argument: {}", arg);
+                    return true;
+                }
+            },
+        };
+        if self.buffers.is_empty() {
+            return true;
+        }
+        let mut numbers: Vec<usize> = self.buffers.iter().map(|state| state.number).collect();
+        let at = numbers.partition_point(|&number| number < self.buffer_number);
+        numbers.insert(at, self.buffer_number);
+        let len = numbers.len();
+        let to = if command.name.starts_with("bn") {
+            (at + count) % len
+        } else {
+            (at + len - count % len) % len
+        };
+        self.switch_to_buffer(numbers[to], text_area_height);
+        true
+    }
+
+    /// `:ls`: lists the buffers, flagging the current one `%`, the alternate
+    /// one `#`, ones in a window `a`, read-only ones `=` and changed ones `+`.
+    pub(super) fn ex_ls(&mut self, _command: &CommandLine, _text_area_height: u16) -> bool {
+        let current = (self.buffer_number, self.filename.as_deref(), self.readonly, self.dirty, self.cursor_y);
+        let mut all: Vec<_> = self
+            .buffers
+            .iter()
+            .map(|state| (state.number, state.filename.as_deref(), state.readonly, state.dirty, state.cursor_y))
+            .collect();
+        let at = all.partition_point(|buffer| buffer.0 < self.buffer_number);
+        all.insert(at, current);
+        let lines: Vec<String> = all
+            .into_iter()
+            .map(|(number, filename, readonly, dirty, line)| {
+                let which = if number == self.buffer_number {
+                    '%'
+                } else if Some(number) == self.alternate_buffer {
+                    '#'
+                } else {
+                    ' '
+                };
+                let active = if number == self.buffer_number || self.in_other_window(number) { 'a' } else { ' ' };
+                let readonly = if readonly { '=' } else { ' ' };
+                let dirty = if dirty { '+' } else { ' ' };
+                let name = format!("\"{}\"", filename.unwrap_or("[No Name]"));
+                format!("{:>3} {}{}{}{} {:<30} line {}", number, which, active, readonly, dirty, name, line + 1)
+            })
+            .collect();
+        self.message = lines.join("\n");
+        true
+    }
+
+    /// `:bd[elete][!] [N|name]` and `:bw[ipeout][!]`: takes a buffer out of
+    /// the list, refusing if it has changes unless `!` is given. `:bw` also
+    /// forgets the file marks in it. Deleting the current buffer switches to
+    /// the alternate one, or the next, or an empty one if it was the last;
+    /// other windows showing it are closed.
+    pub(super) fn ex_bdelete(&mut self, command: &CommandLine, text_area_height: u16) -> bool {
+        let number = match self.buffer_for_arg(command.args) {
+            Ok(number) => number,
+            Err(message) => {
+                self.message = message;
+                return true;
+            }
+        };
+        let state = if number == self.buffer_number {
+            if self.dirty && !command.bang {
+                self.message = format!("No write since last change for buffer {} (add ! to override)", number);
+                return true;
+            }
+            let next = self.alternate_buffer.or_else(|| {
+                let after = self.buffers.iter().find(|state| state.number > number);
+                after.or(self.buffers.last()).map(|state| state.number)
+            });
+            let replacement = match next.and_then(|next| self.buffers.iter().position(|state| state.number == next)) {
+                Some(at) => self.buffers.remove(at),
+                None => {
+                    self.next_buffer_number += 1;
+                    BufferState::empty(self.next_buffer_number - 1)
+                }
+            };
+            let deleted = self.swap_buffer(replacement);
+            self.alternate_buffer = None;
+            self.adjust_cursor_to_line_end();
+            self.adjust_scroll(text_area_height);
+            deleted
+        } else {
+            let at = self.buffers.iter().position(|state| state.number == number).expect("buffer_for_arg found it");
+            if self.buffers[at].dirty && !command.bang {
+                self.message = format!("No write since last change for buffer {} (add ! to override)", number);
+                return true;
+            }
+            if self.alternate_buffer == Some(number) {
+                self.alternate_buffer = None;
+            }
+            self.buffers.remove(at)
+        };
+        self.close_windows_showing(number);
+        if command.name.starts_with("bw") {
+            self.file_marks.forget(state.filename.as_deref());
+        }
+        true
+    }
+
+    /// `:wa[ll]`: writes every buffer with changes.
+    pub(super) fn ex_wall(&mut self, command: &CommandLine, text_area_height: u16) -> bool {
+        self.write_all(command.bang, text_area_height);
+        true
+    }
+
+    /// `:qa[ll][!]`: quits, unless a buffer has changes and there's no `!`.
+    pub(super) fn ex_qall(&mut self, command: &CommandLine, _text_area_height: u16) -> bool {
+        !self.can_quit(command.bang)
+    }
+
+    /// `:wqa[ll]` and `:xa[ll]`: writes every buffer with changes and quits,
+    /// unless one of them couldn't be written.
+    pub(super) fn ex_wqall(&mut self, command: &CommandLine, text_area_height: u16) -> bool {
+        !(self.write_all(command.bang, text_area_height) && self.can_quit(false))
+    }
+
+    /// Writes every buffer with changes, reporting the ones that couldn't
+    /// be. Returns whether they all were.
+    fn write_all(&mut self, force: bool, text_area_height: u16) -> bool {
+        let current = self.buffer_number;
+        let alternate = self.alternate_buffer;
+        let mut failures = Vec::new();
+        if self.dirty && !self.write_file(force) {
+            failures.push(mem::take(&mut self.message));
+        }
+        let hidden: Vec<usize> = self.buffers.iter().filter(|state| state.dirty).map(|state| state.number).collect();
+        for number in hidden {
+            self.switch_to_buffer(number, text_area_height);
+            let message = mem::take(&mut self.message);
+            if self.filename.is_none() {
+                failures.push(format!("No file name for buffer {}", number));
+            } else if !self.write_file(force) {
+                failures.push(mem::replace(&mut self.message, message));
+            } else {
+                self.message = message;
+            }
+        }
+        self.switch_to_buffer(current, text_area_height);
+        self.alternate_buffer = alternate;
+        let written = failures.is_empty();
+        self.message = failures.join("\n");
+        written
+    }
+
+    /// The file names given on the command line after the first, read into
+    /// buffers of their own behind it.
+    pub(super) fn open_more_files(&mut self, filenames: impl Iterator<Item = String>, binary: bool) {
+        for filename in filenames {
+            let state = BufferState::open(self.next_buffer_number, Some(filename), binary, &self.config);
+            self.next_buffer_number += 1;
+            self.hide_buffer(state);
+        }
+    }
+}
+
@@ -0,0 +1,516 @@
+use super::operator::{Operator, OperatorRange};
+use super::{Editor, Mode};
+use crate::ex::{self, CommandLine, LineRange};
+use crate::keys;
+use crate::motion::{self, Pos};
+use crate::register::Registers;
+use crate::search;
+use crate::undo::UndoDistance;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::{fs, io};
+
+/// A command-line command and what it accepts.
+struct Command {
+    name: &'static str,
+    /// How short the name can be abbreviated, like the `d` in `:d[elete]`.
+    short: usize,
+    range: bool,
+    bang: bool,
+    args: bool,
+    /// Changes the text, so it can't be used in the hex view.
+    edits: bool,
+    /// Runs the command. Returns false to quit the editor.
+    run: fn(&mut Editor, &CommandLine, u16) -> bool,
+}
+
+/// Every command, in the order abbreviations are looked up in.
+const COMMANDS: &[Command] = &[
+    Command { name: "bNext", short: 2, range: false, bang: false, args: true, edits: false, run: Editor::ex_bnext },
+    Command { name: "bdelete", short: 2, range: false, bang: true, args: true, edits: false, run: Editor::ex_bdelete },
+    Command { name: "bnext", short: 2, range: false, bang: false, args: true, edits: false, run: Editor::ex_bnext },
+    Command { name: "bprevious", short: 2, range: false, bang: false, args: true, edits: false, run: Editor::ex_bnext },
+    Command { name: "buffer", short: 1, range: false, bang: false, args: true, edits: false, run: Editor::ex_buffer },
+    Command { name: "buffers", short: 7, range: false, bang: false, args: false, edits: false, run: Editor::ex_ls },
+    Command { name: "bwipeout", short: 2, range: false, bang: true, args: true, edits: false, run: Editor::ex_bdelete },
+    Command { name: "close", short: 3, range: false, bang: true, args: false, edits: false, run: Editor::ex_close },
+    Command { name: "copy", short: 2, range: true, bang: false, args: true, edits: true, run: Editor::ex_copy },
+    Command { name: "delete", short: 1, range: true, bang: false, args: true, edits: true, run: Editor::ex_delete },
+    Command { name: "display", short: 2, range: false, bang: false, args: true, edits: false, run: Editor::ex_registers },
+    Command { name: "earlier", short: 2, range: false, bang: false, args: true, edits: false, run: Editor::ex_earlier },
+    Command { name: "edit", short: 1, range: false, bang: true, args: true, edits: false, run: Editor::ex_edit },
+    Command { name: "files", short: 5, range: false, bang: false, args: false, edits: false, run: Editor::ex_ls },
+    Command { name: "global", short: 1, range: true, bang: true, args: true, edits: true, run: Editor::ex_global },
+    Command { name: "hex", short: 3, range: false, bang: false, args: false, edits: false, run: Editor::ex_hex },
+    Command { name: "join", short: 1, range: true, bang: true, args: true, edits: true, run: Editor::ex_join },
+    Command { name: "later", short: 3, range: false, bang: false, args: true, edits: false, run: Editor::ex_later },
+    Command { name: "ls", short: 2, range: false, bang: false, args: false, edits: false, run: Editor::ex_ls },
+    Command { name: "mark", short: 2, range: true, bang: false, args: true, edits: false, run: Editor::ex_mark },
+    Command { name: "marks", short: 5, range: false, bang: false, args: true, edits: false, run: Editor::ex_marks },
+    Command { name: "move", short: 1, range: true, bang: false, args: true, edits: true, run: Editor::ex_move },
+    Command { name: "new", short: 3, range: false, bang: false, args: false, edits: false, run: Editor::ex_new },
+    Command { name: "nohlsearch", short: 3, range: false, bang: false, args: false, edits: false, run: Editor::ex_nohlsearch },
+    Command { name: "normal", short: 4, range: true, bang: true, args: true, edits: true, run: Editor::ex_normal },
+    Command { name: "only", short: 2, range: false, bang: true, args: false, edits: false, run: Editor::ex_only },
+    Command { name: "print", short: 1, range: true, bang: false, args: false, edits: false, run: Editor::ex_print },
+    Command { name: "qall", short: 2, range: false, bang: true, args: false, edits: false, run: Editor::ex_qall },
+    Command { name: "quit", short: 1, range: false, bang: true, args: false, edits: false, run: Editor::ex_quit },
+    Command { name: "registers", short: 3, range: false, bang: false, args: true, edits: false, run: Editor::ex_registers },
+    Command { name: "resize", short: 3, range: false, bang: false, args: true, edits: false, run: Editor::ex_resize },
+    Command { name: "substitute", short: 1, range: true, bang: false, args: true, edits: true, run: Editor::ex_substitute },
+    Command { name: "set", short: 2, range: false, bang: false, args: true, edits: false, run: Editor::ex_set },
+    Command { name: "split", short: 2, range: false, bang: false, args: true, edits: false, run: Editor::ex_split },
+    Command { name: "t", short: 1, range: true, bang: false, args: true, edits: true, run: Editor::ex_copy },
+    Command { name: "vglobal", short: 1, range: true, bang: false, args: true, edits: true, run: Editor::ex_global },
+    Command { name: "vnew", short: 3, range: false, bang: false, args: false, edits: false, run: Editor::ex_new },
+    Command { name: "vsplit", short: 2, range: false, bang: false, args: true, edits: false, run: Editor::ex_split },
+    Command { name: "wall", short: 2, range: false, bang: true, args: false, edits: false, run: Editor::ex_wall },
+    Command { name: "write", short: 1, range: false, bang: true, args: true, edits: false, run: Editor::ex_write },
+    Command { name: "wq", short: 2, range: false, bang: true, args: true, edits: false, run: Editor::ex_wq },
+    Command { name: "wqall", short: 3, range: false, bang: true, args: false, edits: false, run: Editor::ex_wqall },
+    Command { name: "xall", short: 2, range: false, bang: true, args: false, edits: false, run: Editor::ex_wqall },
+    Command { name: "xit", short: 1, range: false, bang: true, args: true, edits: false, run: Editor::ex_wq },
+    Command { name: "yank", short: 1, range: true, bang: false, args: true, edits: false, run: Editor::ex_yank },
+    Command { name: "&", short: 1, range: true, bang: false, args: true, edits: true, run: Editor::ex_substitute },
+    Command { name: ">", short: 1, range: true, bang: false, args: true, edits: true, run: Editor::ex_shift },
+    Command { name: "<", short: 1, range: true, bang: false, args: true, edits: true, run: Editor::ex_shift },
+];
+
+fn find_command(name: &str) -> Option<&'static Command> {
+    // `:>>>` is `:>` three times over.
+    let name = match name.chars().next() {
+        Some(c @ ('>' | '<')) => &name[..c.len_utf8()],
+        _ => name,
+    };
+    COMMANDS.iter().find(|command| name.len() >= command.short && command.name.starts_with(name))
+}
+
+impl Editor {
+    /// Runs a line typed after `:`.
+    pub(super) fn execute_command(&mut self, input: &str, text_area_height: u16) -> io::Result<bool> {
+        let command = match self.with_addresses(|addresses| ex::parse(input, addresses)) {
+            Ok(command) => command,
+            Err(e) => {
+                self.message = e;
+                return Ok(true);
+            }
+        };
+        if command.name.is_empty() {
+            match command.range {
+                _ if !command.args.is_empty() => self.message = format!("Trailing characters: {}", command.args),
+                Some(range) => self.goto_line(range.end, text_area_height),
+                None => {}
+            }
+            return Ok(true);
+        }
+        let Some(spec) = find_command(command.name) else {
+            self.message = format!("Unknown command: {}", input.trim());
+            return Ok(true);
+        };
+        if command.range.is_some() && !spec.range {
+            self.message = "No range allowed".to_string();
+        } else if command.bang && !spec.bang {
+            self.message = "No ! allowed".to_string();
+        } else if !command.args.is_empty() && !spec.args {
+            self.message = format!("Trailing characters: {}", command.args);
+        } else if spec.edits && self.hex.is_some() {
+            self.message = format!("Not allowed in hex mode: {}", spec.name);
+        } else {
+            return Ok((spec.run)(self, &command, text_area_height));
+        }
+        Ok(true)
+    }
+
+    /// Calls `f` with what command-line addresses are worked out against.
+    fn with_addresses<R>(&self, f: impl FnOnce(&ex::Addresses) -> R) -> R {
+        let mark = |name| self.mark_line(name);
+        let search = |pattern: &str, from, backward| self.search_line(pattern, from, backward);
+        f(&ex::Addresses {
+            current: self.cursor_y,
+            last: self.buffer.len_lines() - 1,
+            mark: &mark,
+            search: &search,
+        })
+    }
+
+    /// Line mark `name` is on, for `'x` in an address.
+    fn mark_line(&self, name: char) -> Option<usize> {
+        self.mark_pos(name).ok().map(|pos| pos.line)
+    }
+
+    /// The next line after `from` that matches `pattern`, or the one before
+    /// it if `backward`, going round the end of the buffer.
+    fn search_line(&self, pattern: &str, from: usize, backward: bool) -> Result<usize, String> {
+        let pattern = match pattern {
+            "" => self.registers.last_search.as_str(),
+            pattern => pattern,
+        };
+        if pattern.is_empty() {
+            return Err("No previous regular expression".to_string());
+        }
+        let regex = search::compile(pattern, self.case_options()).map_err(|_| format!("Invalid pattern: {}", pattern))?;
+        let lines = self.buffer.len_lines();
+        (1..=lines)
+            .map(|i| if backward { (from + lines - i) % lines } else { (from + i) % lines })
+            .find(|&line| regex.is_match(&self.buffer.line(line)))
+            .ok_or_else(|| format!("Pattern not found: {}", pattern))
+    }
+
+    /// The address argument of `:m` and `:t`.
+    fn address_arg(&self, args: &str) -> Result<usize, String> {
+        match self.with_addresses(|addresses| ex::parse_address(args, addresses))? {
+            (Some(line), "") => Ok(line),
+            (Some(_), rest) => Err(format!("Trailing characters: {}", rest)),
+            (None, _) => Err("Invalid address".to_string()),
+        }
+    }
+
+    /// The lines a command with `[x] [count]` arguments works on, and the
+    /// register if `register` and one was given. A count takes that many
+    /// lines starting at the end of the range.
+    fn lines_and_register(&mut self, command: &CommandLine, register: bool) -> Option<(LineRange, Option<char>)> {
+        let mut range = command.range.unwrap_or(LineRange::line(self.cursor_y));
+        let mut args = command.args;
+        let mut name = None;
+        if let Some(c) = args.chars().next().filter(|c| register && !c.is_ascii_digit() && Registers::is_valid(*c)) {
+            name = Some(c);
+            args = args[c.len_utf8()..].trim_start();
+        }
+        if !args.is_empty() {
+            match args.parse::<usize>() {
+                Ok(0) => {
+                    self.message = "Positive count required".to_string();
+                    return None;
+                }
+                Ok(count) => {
+                    range = LineRange {
+                        start: range.end,
+                        end: (range.end + count - 1).min(self.buffer.len_lines() - 1),
+                    };
+                }
+                Err(_) => {
+                    self.message = format!("Trailing characters: {}", args);
+                    return None;
+                }
+            }
+        }
+        Some((range, name))
+    }
+
+    /// `:N`: moves to line `line`.
+    fn goto_line(&mut self, line: usize, text_area_height: u16) {
+        if line != self.cursor_y {
+            self.push_jump(self.cursor_pos());
+        }
+        self.cursor_y = line;
+        self.cursor_x = motion::first_non_blank(&self.buffer, line);
+        self.adjust_cursor_to_line_end();
+        self.adjust_scroll(text_area_height);
+    }
+
+    fn line_operator(&mut self, op: Operator, range: LineRange) {
+        let range = OperatorRange { start: Pos::new(range.start, 0), end: Pos::new(range.end, 0), linewise: true };
+        self.apply_operator(op, range);
+    }
+
+    fn ex_delete(&mut self, command: &CommandLine, text_area_height: u16) -> bool {
+        if let Some((range, register)) = self.lines_and_register(command, true) {
+            self.selected_register = register;
+            self.line_operator(Operator::Delete, range);
+            self.adjust_scroll(text_area_height);
+        }
+        true
+    }
+
+    fn ex_yank(&mut self, command: &CommandLine, _text_area_height: u16) -> bool {
+        if let Some((range, register)) = self.lines_and_register(command, true) {
+            let (cursor_y, cursor_x) = (self.cursor_y, self.cursor_x);
+            self.selected_register = register;
+            self.line_operator(Operator::Yank, range);
+            self.cursor_y = cursor_y;
+            self.cursor_x = cursor_x;
+        }
+        true
+    }
+
+    /// `:>` and `:<`, once for each `>` or `<`.
+    fn ex_shift(&mut self, command: &CommandLine, text_area_height: u16) -> bool {
+        let op = if command.name.starts_with('>') { Operator::Indent } else { Operator::Outdent };
+        if let Some((range, _)) = self.lines_and_register(command, false) {
+            for _ in 0..command.name.len() {
+                self.line_operator(op, range);
+            }
+            self.goto_line(range.end, text_area_height);
+        }
+        true
+    }
+
+    /// `:j`: joins the lines in the range, or the cursor's line and the one
+    /// after it. Lines are joined with a space unless `!` is given.
+    fn ex_join(&mut self, command: &CommandLine, text_area_height: u16) -> bool {
+        let Some((mut range, _)) = self.lines_and_register(command, false) else {
+            return true;
+        };
+        if range.start == range.end {
+            range.end += 1;
+        }
+        range.end = range.end.min(self.buffer.len_lines() - 1);
+        self.join_lines(range, !command.bang);
+        self.adjust_scroll(text_area_height);
+        true
+    }
+
+    /// Joins the lines in `range` into one. With `spaces`, leading
+    /// whitespace is dropped from each joined line and one space goes
+    /// between them, as Vim's `J` does.
+    fn join_lines(&mut self, range: LineRange, spaces: bool) {
+        let line = range.start;
+        for _ in range.start..range.end {
+            let line_end = self.buffer.pos_to_char(line, self.buffer.line_len(line));
+            let current = self.buffer.line(line);
+            let next = self.buffer.line(line + 1);
+            let indent = if spaces { next.chars().take_while(|&c| c == ' ' || c == '\t').count() } else { 0 };
+            let rest = &next[next.char_indices().nth(indent).map_or(next.len(), |(i, _)| i)..];
+            self.delete_text(line_end..line_end + 1 + indent);
+            let space = spaces && !current.is_empty() && !current.ends_with([' ', '\t']) && !rest.is_empty() && !rest.starts_with(')');
+            if space {
+                self.insert_text(line_end, " ");
+            }
+            self.cursor_y = line;
+            self.cursor_x = self.buffer.char_to_pos(line_end).1;
+        }
+        self.adjust_cursor_to_line_end();
+    }
+
+    /// `:m {address}`: moves the lines to below line `address`.
+    fn ex_move(&mut self, command: &CommandLine, text_area_height: u16) -> bool {
+        let range = command.range.unwrap_or(LineRange::line(self.cursor_y));
+        let target = match self.address_arg(command.args) {
+            Ok(target) => target,
+            Err(e) => {
+                self.message = e;
+                return true;
+            }
+        };
+        if target > range.start && target <= range.end {
+            self.message = "Cannot move a range of lines into itself".to_string();
+            return true;
+        }
+        let count = range.end - range.start + 1;
+        let text = self.buffer.slice(self.line_span(range.start, range.end));
+        let last = if target > range.end {
+            self.insert_lines(target, &text);
+            self.delete_lines(range);
+            target - 1
+        } else if target < range.start {
+            self.delete_lines(range);
+            self.insert_lines(target, &text);
+            target + count - 1
+        } else {
+            range.end
+        };
+        self.goto_line(last, text_area_height);
+        true
+    }
+
+    /// `:t {address}` and `:co`: copies the lines to below line `address`.
+    fn ex_copy(&mut self, command: &CommandLine, text_area_height: u16) -> bool {
+        let range = command.range.unwrap_or(LineRange::line(self.cursor_y));
+        match self.address_arg(command.args) {
+            Ok(target) => {
+                let text = self.buffer.slice(self.line_span(range.start, range.end));
+                self.insert_lines(target, &text);
+                self.goto_line(target + range.end - range.start, text_area_height);
+            }
+            Err(e) => self.message = e,
+        }
+        true
+    }
+
+    /// Puts `text` in as whole lines below line `after`, counting from 1, or
+    /// above the first line if `after` is 0.
+    fn insert_lines(&mut self, after: usize, text: &str) {
+        if after == 0 {
+            self.insert_text(0, &format!("{}\n", text));
+        } else {
+            let end = self.buffer.pos_to_char(after - 1, self.buffer.line_len(after - 1));
+            self.insert_text(end, &format!("\n{}", text));
+        }
+    }
+
+    /// Removes the lines in `range` along with a line break.
+    fn delete_lines(&mut self, range: LineRange) {
+        let mut chars = self.line_span(range.start, range.end);
+        if chars.end < self.buffer.len_chars() {
+            chars.end += 1;
+        } else if chars.start > 0 {
+            chars.start -= 1;
+        }
+        self.delete_text(chars);
+        self.cursor_y = self.cursor_y.min(self.buffer.len_lines() - 1);
+        self.adjust_cursor_to_line_end();
+    }
+
+    /// `:normal {keys}`: types `keys` in Normal mode, on each line of the
+    /// range if there is one. Anything left unfinished is ended as if with
+    /// Esc.
+    fn ex_normal(&mut self, command: &CommandLine, _text_area_height: u16) -> bool {
+        if command.args.is_empty() {
+            self.message = "Argument required".to_string();
+            return true;
+        }
+        let batching = std::mem::replace(&mut self.undo_batch, true);
+        self.mode = Mode::Normal;
+        let mut keep_running = true;
+        match command.range {
+            Some(range) => {
+                for line in range.start..=range.end {
+                    if line >= self.buffer.len_lines() {
+                        break;
+                    }
+                    self.cursor_y = line;
+                    self.cursor_x = 0;
+                    keep_running &= self.type_keys(command.args);
+                }
+            }
+            None => keep_running = self.type_keys(command.args),
+        }
+        self.undo_batch = batching;
+        keep_running
+    }
+
+    /// Runs `keys` as if typed. Returns false if they quit the editor.
+    fn type_keys(&mut self, keys: &str) -> bool {
+        // Keys a macro running `:normal` has still to type wait until these
+        // are done.
+        let outer = std::mem::take(&mut self.typeahead);
+        for c in keys.chars() {
+            let mut result = self.dispatch_key(keys::from_char(c));
+            if let Ok(true) = result {
+                result = self.run_typeahead();
+            }
+            match result {
+                Ok(true) => {}
+                Ok(false) => return false,
+                Err(e) => self.message = e.to_string(),
+            }
+        }
+        self.typeahead = outer;
+        let escape = KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE);
+        for _ in 0..3 {
+            if self.mode == Mode::Normal && self.pending_key.is_none() && self.count.is_none() {
+                break;
+            }
+            let _ = self.dispatch_key(escape);
+        }
+        true
+    }
+
+    /// `:p`: shows the lines. Under `:g` each one is added to the last.
+    fn ex_print(&mut self, command: &CommandLine, text_area_height: u16) -> bool {
+        let range = command.range.unwrap_or(LineRange::line(self.cursor_y));
+        for line in range.start..=range.end {
+            if !self.message.is_empty() {
+                self.message.push('\n');
+            }
+            self.message.push_str(&self.buffer.line(line));
+        }
+        self.goto_line(range.end, text_area_height);
+        true
+    }
+
+    fn ex_substitute(&mut self, command: &CommandLine, text_area_height: u16) -> bool {
+        self.substitute(command.range, command.args, text_area_height);
+        true
+    }
+
+    fn ex_quit(&mut self, command: &CommandLine, _text_area_height: u16) -> bool {
+        self.quit_window(command.bang)
+    }
+
+    /// `:w [file]`. Writing to a file other than the buffer's own leaves the
+    /// buffer as it is, unless it has no name yet.
+    fn ex_write(&mut self, command: &CommandLine, _text_area_height: u16) -> bool {
+        let path = command.args.trim();
+        if !path.is_empty() && self.filename.as_deref().is_some_and(|name| name != path) {
+            let written = self.file_bytes().and_then(|bytes| fs::write(path, bytes));
+            self.message = match written {
+                Ok(()) => format!("\"{}\" written", path),
+                Err(e) => e.to_string(),
+            };
+            return true;
+        }
+        if !path.is_empty() {
+            self.filename = Some(path.to_string());
+        }
+        if self.write_file(command.bang) {
+            self.message = "File written.".to_string();
+        }
+        true
+    }
+
+    fn ex_wq(&mut self, command: &CommandLine, text_area_height: u16) -> bool {
+        self.ex_write(command, text_area_height);
+        // A failed write has already said why.
+        self.dirty || self.quit_window(false)
+    }
+
+    fn ex_hex(&mut self, _command: &CommandLine, _text_area_height: u16) -> bool {
+        self.toggle_hex();
+        true
+    }
+
+    fn ex_earlier(&mut self, command: &CommandLine, _text_area_height: u16) -> bool {
+        self.undo_distance_command(false, command.args);
+        true
+    }
+
+    fn ex_later(&mut self, command: &CommandLine, _text_area_height: u16) -> bool {
+        self.undo_distance_command(true, command.args);
+        true
+    }
+
+    fn undo_distance_command(&mut self, forward: bool, args: &str) {
+        match UndoDistance::parse(args) {
+            Some(distance) => self.time_travel(forward, distance),
+            None => self.message = format!("Invalid argument: {}", args.trim()),
+        }
+    }
+
+    fn ex_registers(&mut self, command: &CommandLine, _text_area_height: u16) -> bool {
+        self.list_registers(command.args);
+        true
+    }
+
+    /// `:mark x`: puts mark `x` on the last line of the range.
+    fn ex_mark(&mut self, command: &CommandLine, _text_area_height: u16) -> bool {
+        let mut names = command.args.trim().chars();
+        let (Some(name), None) = (names.next(), names.next()) else {
+            self.message = "Argument required".to_string();
+            return true;
+        };
+        let (cursor_y, cursor_x) = (self.cursor_y, self.cursor_x);
+        self.cursor_y = command.range.map_or(cursor_y, |range| range.end);
+        self.cursor_x = 0;
+        self.set_mark(name);
+        self.cursor_y = cursor_y;
+        self.cursor_x = cursor_x;
+        true
+    }
+
+    fn ex_marks(&mut self, command: &CommandLine, _text_area_height: u16) -> bool {
+        self.list_marks(command.args);
+        true
+    }
+
+    fn ex_set(&mut self, command: &CommandLine, _text_area_height: u16) -> bool {
+        if !command.args.is_empty() {
+            self.set_option(command.args.trim());
+        }
+        true
+    }
+
+    fn ex_nohlsearch(&mut self, _command: &CommandLine, _text_area_height: u16) -> bool {
+        self.search_regex = None;
+        true
+    }
+}
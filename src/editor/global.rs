@@ -0,0 +1,114 @@
+use super::substitute::substitution_report;
+use super::Editor;
+use crate::ex::{CommandLine, LineRange};
+use crate::search;
+use crate::text_buffer::LineShift;
+
+/// A `:g` in progress.
+pub struct GlobalRun {
+    /// The lines marked for the command, kept up to date as it edits the
+    /// buffer. `None` once a line has been deleted.
+    lines: Vec<Option<usize>>,
+    /// Totals over every `:s` the command runs, reported once at the end.
+    pub(super) substitutions: usize,
+    pub(super) substituted_lines: usize,
+    pub(super) count_only: bool,
+}
+
+impl GlobalRun {
+    pub(super) fn shift_lines(&mut self, shift: LineShift) {
+        for line in &mut self.lines {
+            *line = line.and_then(|line| shift.apply(line));
+        }
+    }
+}
+
+impl Editor {
+    /// `:g/pattern/command` runs `command` on every line matching `pattern`;
+    /// `:g!` and `:v` on every line that doesn't. The lines are all marked
+    /// first, so the command can add and delete lines as it goes. The
+    /// command defaults to `:p`.
+    pub(super) fn ex_global(&mut self, command: &CommandLine, text_area_height: u16) -> bool {
+        if self.global.is_some() {
+            self.message = "Cannot do :global recursive".to_string();
+            return true;
+        }
+        let invert = command.bang || command.name.starts_with('v');
+        let mut chars = command.args.chars();
+        let delimiter = match chars.next() {
+            Some(c) if !c.is_alphanumeric() && !matches!(c, '\\' | '"' | '|' | ' ') => c,
+            _ => {
+                self.message = "Regular expression missing from :global".to_string();
+                return true;
+            }
+        };
+        let (pattern, rest) = search::split_pattern(chars.as_str(), delimiter);
+        let to_run = match rest.map(str::trim) {
+            Some("") | None => "p",
+            Some(to_run) => to_run,
+        };
+        let pattern = match pattern.as_str() {
+            "" => self.registers.last_search.clone(),
+            pattern => pattern.to_string(),
+        };
+        if pattern.is_empty() {
+            self.message = "No previous regular expression".to_string();
+            return true;
+        }
+        let regex = match search::compile(&pattern, self.case_options()) {
+            Ok(regex) => regex,
+            Err(_) => {
+                self.message = format!("Invalid pattern: {}", pattern);
+                return true;
+            }
+        };
+        self.registers.last_search = pattern.clone();
+        self.search_regex = Some(regex.clone());
+
+        let range = command.range.unwrap_or(LineRange { start: 0, end: self.buffer.len_lines() - 1 });
+        let lines: Vec<_> = (range.start..=range.end)
+            .filter(|&line| regex.is_match(&self.buffer.line(line)) != invert)
+            .map(Some)
+            .collect();
+        if lines.is_empty() {
+            self.message = if invert {
+                format!("Pattern found in every line: {}", pattern)
+            } else {
+                format!("Pattern not found: {}", pattern)
+            };
+            return true;
+        }
+
+        self.global = Some(GlobalRun { lines, substitutions: 0, substituted_lines: 0, count_only: false });
+        let batching = std::mem::replace(&mut self.undo_batch, true);
+        let mut keep_running = true;
+        let mut next = 0;
+        while let Some(marked) = self.global.as_ref().and_then(|global| global.lines.get(next).copied()) {
+            next += 1;
+            let Some(line) = marked else {
+                continue;
+            };
+            self.cursor_y = line;
+            self.cursor_x = 0;
+            match self.execute_command(to_run, text_area_height) {
+                Ok(true) => {}
+                Ok(false) => {
+                    keep_running = false;
+                    break;
+                }
+                Err(e) => self.message = e.to_string(),
+            }
+        }
+        self.undo_batch = batching;
+
+        if let Some(global) = self.global.take() {
+            if global.substitutions > 0 {
+                self.message = substitution_report(global.count_only, global.substitutions, global.substituted_lines);
+            }
+        }
+        self.cursor_y = self.cursor_y.min(self.buffer.len_lines() - 1);
+        self.adjust_cursor_to_line_end();
+        self.adjust_scroll(text_area_height);
+        keep_running
+    }
+}
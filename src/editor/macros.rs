@@ -0,0 +1,117 @@
+use super::{Editor, Mode, MAX_COUNT};
+use crate::keys;
+use crate::register::Registers;
+use crossterm::event::{KeyCode, KeyEvent};
+use std::io;
+
+/// A macro being recorded with `q`.
+pub struct Recording {
+    pub(super) register: char,
+    keys: Vec<KeyEvent>,
+}
+
+impl Editor {
+    /// `q{register}`: starts recording the keys typed into `register`.
+    pub(super) fn start_recording(&mut self, register: char) {
+        if !Registers::can_record(register) {
+            self.cancel_pending();
+            return;
+        }
+        self.recording = Some(Recording { register, keys: Vec::new() });
+    }
+
+    /// Adds a key typed while recording. Keys a macro types aren't added.
+    pub(super) fn record_key(&mut self, event: KeyEvent) {
+        if let Some(recording) = &mut self.recording {
+            recording.keys.push(event);
+        }
+    }
+
+    /// `q` while recording: stores the keys, without that `q`, in the register
+    /// as text that can be put, edited and yanked back.
+    pub(super) fn stop_recording(&mut self) {
+        let Some(mut recording) = self.recording.take() else {
+            return;
+        };
+        if recording.keys.last().is_some_and(|key| key.code == KeyCode::Char('q')) {
+            recording.keys.pop();
+        }
+        self.registers.record(recording.register, keys::to_notation(&recording.keys));
+    }
+
+    /// `@{register}`: types the keys in `register` `count` times. `@@` plays
+    /// the last one again and `@:` repeats the last command line.
+    pub(super) fn play_macro(&mut self, register: char, count: usize) {
+        let register = match register {
+            '@' => match self.last_macro {
+                Some(last) => last,
+                None => {
+                    self.message = "No previously used register".to_string();
+                    self.fail();
+                    return;
+                }
+            },
+            register => register,
+        };
+        let text = match register {
+            ':' if !self.registers.last_command.is_empty() => format!(":{}\r", self.registers.last_command),
+            ':' => String::new(),
+            _ => match self.read_register(Some(register)) {
+                Some(contents) => contents.text,
+                None => String::new(),
+            },
+        };
+        if text.is_empty() {
+            self.fail();
+            return;
+        }
+        self.last_macro = Some(register);
+        self.macro_runs += 1;
+        if self.macro_runs > MAX_COUNT {
+            self.message = "Macro stopped: it kept calling itself".to_string();
+            self.fail();
+            return;
+        }
+        // Going in front of what's already queued is what lets a macro
+        // call itself without piling up on the stack.
+        let keys = keys::parse(&text);
+        for _ in 0..count {
+            for &key in keys.iter().rev() {
+                self.typeahead.push_front(key);
+            }
+        }
+    }
+
+    /// Types the keys macros have queued up. They all undo in one step.
+    /// Returns false if they quit the editor.
+    pub(super) fn run_typeahead(&mut self) -> io::Result<bool> {
+        if self.typeahead.is_empty() {
+            return Ok(true);
+        }
+        let batching = std::mem::replace(&mut self.undo_batch, true);
+        let mut keep_running = true;
+        while let Some(key) = self.typeahead.pop_front() {
+            match self.dispatch_key(key) {
+                Ok(true) => {}
+                Ok(false) => {
+                    keep_running = false;
+                    break;
+                }
+                Err(e) => self.message = e.to_string(),
+            }
+        }
+        self.typeahead.clear();
+        self.undo_batch = batching;
+        if self.mode == Mode::Normal && !self.undo_batch {
+            self.undo_tree().commit();
+        }
+        Ok(keep_running)
+    }
+
+    /// For a command that couldn't be carried out, like a motion with
+    /// nowhere to go: drops what's left of any macro being played, which is
+    /// how a macro that calls itself comes to an end.
+    pub(super) fn fail(&mut self) {
+        self.typeahead.clear();
+    }
+}
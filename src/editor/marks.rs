@@ -0,0 +1,278 @@
+use super::{Editor, Mode};
+use crate::motion::{Motion, Pos};
+use crate::text_buffer::LineShift;
+use std::collections::HashMap;
+
+/// How many places the jump and change lists hold.
+const MAX_PLACES: usize = 100;
+
+/// Places to step back and forth through, like the jump list.
+#[derive(Default)]
+struct PlaceList {
+    places: Vec<Pos>,
+    /// How far back the list has been stepped through: `places.len()` when
+    /// it hasn't been.
+    index: usize,
+}
+
+impl PlaceList {
+    /// Adds `pos` to the end, where stepping back starts again from.
+    fn push(&mut self, pos: Pos) {
+        self.places.push(pos);
+        if self.places.len() > MAX_PLACES {
+            self.places.remove(0);
+        }
+        self.index = self.places.len();
+    }
+
+    /// The place `count` steps older, or newer if `newer`.
+    fn step(&mut self, count: usize, newer: bool) -> Option<Pos> {
+        let index = if newer { self.index + count } else { self.index.checked_sub(count)? };
+        let pos = *self.places.get(index)?;
+        self.index = index;
+        Some(pos)
+    }
+
+    fn shift_lines(&mut self, shift: LineShift) {
+        let mut index = self.index;
+        let mut i = 0;
+        self.places.retain_mut(|pos| {
+            let kept = match shift.apply(pos.line) {
+                Some(line) => {
+                    pos.line = line;
+                    true
+                }
+                None => false,
+            };
+            if !kept && i < self.index {
+                index -= 1;
+            }
+            i += 1;
+            kept
+        });
+        self.index = index;
+    }
+}
+
+/// A buffer's marks, and its jump and change lists. Each place keeps to its
+/// text as lines are added and deleted above it; ones on deleted lines are
+/// dropped.
+#[derive(Default)]
+pub struct Marks {
+    /// `a`-`z`, and the ones the editor sets itself: `.` (the last change),
+    /// `^` (where Insert mode was left), `[` and `]` (the start and end of
+    /// the last change or yank) and `'` (where the last jump was made from).
+    local: HashMap<char, Pos>,
+    jumps: PlaceList,
+    changes: PlaceList,
+}
+
+/// `A`-`Z`, which remember the file they're in as well, so they can be gone
+/// to from any buffer.
+#[derive(Default)]
+pub struct FileMarks {
+    marks: HashMap<char, (Option<String>, Pos)>,
+}
+
+impl FileMarks {
+    /// Keeps the marks in `filename` on their text, like
+    /// [`Marks::shift_lines`].
+    pub(super) fn shift_lines(&mut self, shift: LineShift, filename: Option<&str>) {
+        self.marks.retain(|_, (file, pos)| {
+            if file.as_deref() != filename {
+                return true;
+            }
+            match shift.apply(pos.line) {
+                Some(line) => {
+                    pos.line = line;
+                    true
+                }
+                None => false,
+            }
+        });
+    }
+
+    /// Drops the marks in `filename`, for `:bwipeout`.
+    pub(super) fn forget(&mut self, filename: Option<&str>) {
+        self.marks.retain(|_, (file, _)| file.as_deref() != filename);
+    }
+}
+
+impl Marks {
+    /// Puts one of the marks the editor keeps itself, like `^`, at `pos`.
+    pub(super) fn set(&mut self, name: char, pos: Pos) {
+        self.local.insert(name, pos);
+    }
+
+    /// Keeps every place on its text after an edit added or deleted lines.
+    pub(super) fn shift_lines(&mut self, shift: LineShift) {
+        self.local.retain(|_, pos| match shift.apply(pos.line) {
+            Some(line) => {
+                pos.line = line;
+                true
+            }
+            None => false,
+        });
+        self.jumps.shift_lines(shift);
+        self.changes.shift_lines(shift);
+    }
+}
+
+impl Editor {
+    /// `m{name}`: puts mark `name` at the cursor.
+    pub(super) fn set_mark(&mut self, name: char) {
+        let pos = self.cursor_pos();
+        match name {
+            'a'..='z' | '[' | ']' => {
+                self.marks.local.insert(name, pos);
+            }
+            '\'' | '`' => {
+                self.marks.local.insert('\'', pos);
+            }
+            'A'..='Z' => {
+                self.file_marks.marks.insert(name, (self.filename.clone(), pos));
+            }
+            _ => {
+                self.cancel_pending();
+                self.fail();
+            }
+        }
+    }
+
+    /// Where mark `name` is, or why it can't be gone to.
+    pub(super) fn mark_pos(&self, name: char) -> Result<Pos, String> {
+        let pos = match name {
+            'a'..='z' | '.' | '^' | '[' | ']' => self.marks.local.get(&name).copied(),
+            // With no jumps made yet, `''` goes to the top.
+            '\'' | '`' => Some(self.marks.local.get(&'\'').copied().unwrap_or(Pos::new(0, 0))),
+            'A'..='Z' => match self.file_marks.marks.get(&name) {
+                Some((file, _)) if *file != self.filename => return Err("Mark is in another file".to_string()),
+                Some((_, pos)) => Some(*pos),
+                None => None,
+            },
+            '<' | '>' => self.visual_mark(name == '>'),
+            _ => return Err("Unknown mark".to_string()),
+        };
+        let pos = pos.ok_or_else(|| "Mark not set".to_string())?;
+        let line = pos.line.min(self.buffer.len_lines() - 1);
+        Ok(Pos::new(line, pos.col.min(self.buffer.line_len(line))))
+    }
+
+    /// `` `{name} `` and `'{name}` (`linewise`): goes to mark `name`, or
+    /// applies the pending operator up to it. A file mark in another file
+    /// opens that file first, unless there's an operator waiting.
+    pub(super) fn goto_mark(&mut self, name: char, linewise: bool, text_area_height: u16) {
+        if let (Some((Some(file), _)), Mode::Normal | Mode::Visual(_)) = (self.file_marks.marks.get(&name), self.mode) {
+            if self.filename.as_ref() != Some(file) {
+                let file = file.clone();
+                self.exit_visual();
+                self.edit_file(&file, text_area_height);
+            }
+        }
+        match self.mark_pos(name) {
+            Ok(pos) => self.run_motion(Motion::Mark { pos, linewise }, text_area_height),
+            Err(message) => {
+                self.message = message;
+                self.cancel_pending();
+                self.fail();
+            }
+        }
+    }
+
+    /// Notes a jump away from `from`, for `''` and Ctrl-O. The jump list
+    /// keeps one place per line.
+    pub(super) fn push_jump(&mut self, from: Pos) {
+        self.marks.local.insert('\'', from);
+        let jumps = &mut self.marks.jumps;
+        jumps.places.retain(|pos| pos.line != from.line);
+        jumps.push(from);
+    }
+
+    /// Ctrl-O, or Ctrl-I with `newer`: goes `count` places back (or on)
+    /// through the jump list.
+    pub(super) fn step_jumps(&mut self, count: usize, newer: bool, text_area_height: u16) {
+        let here = self.cursor_pos();
+        let jumps = &mut self.marks.jumps;
+        if !newer && jumps.index == jumps.places.len() {
+            // Keep where the cursor is, so Ctrl-I can come back to it.
+            jumps.places.retain(|pos| pos.line != here.line);
+            jumps.push(here);
+            jumps.index -= 1;
+        }
+        match self.marks.jumps.step(count, newer) {
+            Some(pos) => self.move_to_place(pos, text_area_height),
+            None => self.fail(),
+        }
+    }
+
+    /// `g;`, or `g,` with `newer`: goes `count` places back (or on) through
+    /// the change list.
+    pub(super) fn step_changes(&mut self, count: usize, newer: bool, text_area_height: u16) {
+        let changes = &mut self.marks.changes;
+        if changes.places.is_empty() {
+            self.message = "Changelist is empty".to_string();
+            self.fail();
+            return;
+        }
+        match changes.step(count, newer) {
+            Some(pos) => self.move_to_place(pos, text_area_height),
+            None => {
+                self.message = if newer { "At end of changelist" } else { "At start of changelist" }.to_string();
+                self.fail();
+            }
+        }
+    }
+
+    fn move_to_place(&mut self, pos: Pos, text_area_height: u16) {
+        self.cursor_y = pos.line.min(self.buffer.len_lines() - 1);
+        self.cursor_x = pos.col;
+        self.adjust_cursor_to_line_end();
+        self.adjust_scroll(text_area_height);
+    }
+
+    /// Sets `.`, `[` and `]` for an edit from `start` to `end`. `[` and `]`
+    /// take in all the edits one command makes.
+    pub(super) fn mark_edit(&mut self, start: Pos, end: Pos) {
+        let first_edit = !self.changed_in_command();
+        let marks = &mut self.marks.local;
+        marks.insert('.', start);
+        let (first, last) = match (marks.get(&'['), marks.get(&']')) {
+            (Some(&first), Some(&last)) if !first_edit => (first.min(start), last.max(end)),
+            _ => (start, end),
+        };
+        marks.insert('[', first);
+        marks.insert(']', last);
+    }
+
+    /// Once a command has changed the buffer, adds where to the change
+    /// list. A change on the same line as the last one takes its place.
+    pub(super) fn push_change(&mut self) {
+        let Some(&pos) = self.marks.local.get(&'.') else {
+            return;
+        };
+        let changes = &mut self.marks.changes;
+        if changes.places.last().is_some_and(|last| last.line == pos.line) {
+            changes.places.pop();
+        }
+        changes.push(pos);
+    }
+
+    /// `:marks`: lists the marks set, or only the ones in `names`.
+    pub(super) fn list_marks(&mut self, names: &str) {
+        let mut lines = vec!["mark line  col file/text".to_string()];
+        let all = "'abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ[]^.<>";
+        for name in all.chars().filter(|&name| names.trim().is_empty() || names.contains(name)) {
+            let (pos, file) = match self.file_marks.marks.get(&name) {
+                Some((file, pos)) if *file != self.filename => (*pos, file.clone().unwrap_or_default()),
+                _ => match self.mark_pos(name) {
+                    Ok(pos) if name != '\'' || self.marks.local.contains_key(&'\'') => {
+                        (pos, self.buffer.line(pos.line).trim().to_string())
+                    }
+                    _ => continue,
+                },
+            };
+            lines.push(format!(" {} {:>6} {:>4} {}", name, pos.line + 1, pos.col, file));
+        }
+        self.message = lines.join("\n");
+    }
+}
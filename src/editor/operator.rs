@@ -0,0 +1,646 @@
+use super::{Editor, Mode};
+use crate::motion::{self, CharSearch, Motion, MotionKind, Pos};
+use crate::register::{Register, RegisterKind, Registers, Store};
+use crate::textobject::{self, ObjectRange};
+use crate::unicode;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::ops::Range;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    Delete,
+    Change,
+    Yank,
+    Indent,
+    Outdent,
+    Lowercase,
+    Uppercase,
+    ToggleCase,
+}
+
+impl Operator {
+    fn from_key(c: char) -> Option<Self> {
+        match c {
+            'd' => Some(Operator::Delete),
+            'c' => Some(Operator::Change),
+            'y' => Some(Operator::Yank),
+            '>' => Some(Operator::Indent),
+            '<' => Some(Operator::Outdent),
+            _ => None,
+        }
+    }
+
+    /// Operators typed after `g`.
+    fn from_g_key(c: char) -> Option<Self> {
+        match c {
+            'u' => Some(Operator::Lowercase),
+            'U' => Some(Operator::Uppercase),
+            '~' => Some(Operator::ToggleCase),
+            _ => None,
+        }
+    }
+
+    /// The last key of the operator; typing it again (`dd`, `>>`, `gUU` or
+    /// `gUgU`) applies the operator to whole lines.
+    fn line_key(self) -> char {
+        match self {
+            Operator::Delete => 'd',
+            Operator::Change => 'c',
+            Operator::Yank => 'y',
+            Operator::Indent => '>',
+            Operator::Outdent => '<',
+            Operator::Lowercase => 'u',
+            Operator::Uppercase => 'U',
+            Operator::ToggleCase => '~',
+        }
+    }
+}
+
+/// A key that needs the next key to mean anything.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PendingKey {
+    /// `g`, the prefix for `gg`, `ge`, `gE`, `gv`, `gi`, `g;`, `g,`, `gu`,
+    /// `gU` and `g~`.
+    G,
+    /// `f`, `t`, `F` or `T`, waiting for the char to find.
+    Find { till: bool, backward: bool },
+    /// `"`, waiting for a register name.
+    Register,
+    /// Ctrl-R in Insert or Command mode, waiting for a register to paste.
+    InsertRegister,
+    /// `q`, waiting for the register to record a macro into.
+    Record,
+    /// `@`, waiting for the register of the macro to play.
+    Macro,
+    /// `i` or `a` after an operator or in Visual mode, waiting for the
+    /// text object.
+    TextObject { inner: bool },
+    /// `m`, waiting for the name of the mark to set.
+    SetMark,
+    /// `` ` `` or `'` (`linewise`), waiting for the name of the mark to go to.
+    GotoMark { linewise: bool },
+    /// Ctrl-W, waiting for what to do with the windows.
+    Window,
+}
+
+impl PendingKey {
+    /// The `Find` for `f`, `t`, `F` or `T`.
+    pub(super) fn find(c: char) -> Self {
+        PendingKey::Find { till: matches!(c, 't' | 'T'), backward: c.is_ascii_uppercase() }
+    }
+}
+
+/// The text an operator acts on. For linewise ranges only the lines of
+/// `start` and `end` matter; otherwise `end` is exclusive.
+#[derive(Debug, Clone, Copy)]
+pub(super) struct OperatorRange {
+    pub(super) start: Pos,
+    pub(super) end: Pos,
+    pub(super) linewise: bool,
+}
+
+impl OperatorRange {
+    /// Where a delete of this range goes besides the unnamed register: small
+    /// deletes within a line to `"-`, the rest to `"1`.
+    fn store(&self) -> Store {
+        Store::Delete { small: !self.linewise && self.start.line == self.end.line }
+    }
+}
+
+impl Editor {
+    /// Single-key motions, shared by Normal and Operator-pending mode.
+    pub(super) fn motion_for_key(event: &KeyEvent) -> Option<Motion> {
+        if event.modifiers.contains(KeyModifiers::CONTROL) {
+            return None;
+        }
+        match event.code {
+            KeyCode::Char('h') | KeyCode::Left => Some(Motion::Left),
+            KeyCode::Char('l') | KeyCode::Right => Some(Motion::Right),
+            KeyCode::Char('k') | KeyCode::Up => Some(Motion::Up),
+            KeyCode::Char('j') | KeyCode::Down => Some(Motion::Down),
+            KeyCode::Char('w') => Some(Motion::WordForward { big: false }),
+            KeyCode::Char('W') => Some(Motion::WordForward { big: true }),
+            KeyCode::Char('b') => Some(Motion::WordBackward { big: false }),
+            KeyCode::Char('B') => Some(Motion::WordBackward { big: true }),
+            KeyCode::Char('e') => Some(Motion::WordEnd { big: false }),
+            KeyCode::Char('E') => Some(Motion::WordEnd { big: true }),
+            KeyCode::Char('}') => Some(Motion::ParagraphForward),
+            KeyCode::Char('{') => Some(Motion::ParagraphBackward),
+            KeyCode::Char(')') => Some(Motion::SentenceForward),
+            KeyCode::Char('(') => Some(Motion::SentenceBackward),
+            KeyCode::Char('%') => Some(Motion::MatchPair),
+            KeyCode::Char('0') | KeyCode::Home => Some(Motion::LineStart),
+            KeyCode::Char('^') => Some(Motion::FirstNonBlank),
+            KeyCode::Char('_') => Some(Motion::FirstNonBlankDown),
+            KeyCode::Char('$') | KeyCode::End => Some(Motion::LineEnd),
+            KeyCode::Char('G') => Some(Motion::LastLine),
+            _ => None,
+        }
+    }
+
+    /// Starts an operator from its Normal-mode key.
+    pub(super) fn start_operator(&mut self, c: char) {
+        if let Some(op) = Operator::from_key(c) {
+            self.begin_operator(op);
+        }
+    }
+
+    /// Enters Operator-pending mode, keeping the count typed so far to
+    /// multiply into the motion's.
+    fn begin_operator(&mut self, op: Operator) {
+        self.operator_count = self.count.take();
+        self.mode = Mode::OperatorPending(op);
+    }
+
+    /// Moves the cursor by `motion`, or applies the pending operator over it,
+    /// using up the pending count.
+    pub(super) fn run_motion(&mut self, motion: Motion, text_area_height: u16) {
+        let count = self.take_count();
+        let motion = match (motion, count) {
+            (Motion::FirstLine | Motion::LastLine, Some(n)) => Motion::GotoLine(n),
+            // `{count}%` goes that far through the file.
+            (Motion::MatchPair, Some(n)) => Motion::GotoLine((n * self.buffer.len_lines()).div_ceil(100)),
+            _ => motion,
+        };
+        let count = count.unwrap_or(1);
+        match self.mode {
+            Mode::OperatorPending(op) => {
+                self.mode = Mode::Normal;
+                match self.operator_range(op, motion, count) {
+                    Some(range) => self.apply_operator(op, range),
+                    None => self.fail(),
+                }
+                self.selected_register = None;
+            }
+            _ => self.move_cursor(motion, count),
+        }
+        self.adjust_scroll(text_area_height);
+    }
+
+    fn move_cursor(&mut self, motion: Motion, count: usize) {
+        let from = self.cursor_pos();
+        match motion {
+            // Plain h/l/j/k keep wrapping across lines and remembering the column.
+            Motion::Left => (0..count).for_each(|_| self.move_cursor_left()),
+            Motion::Right => (0..count).for_each(|_| self.move_cursor_right()),
+            Motion::Up => (0..count).for_each(|_| self.move_cursor_up()),
+            Motion::Down => (0..count).for_each(|_| self.move_cursor_down()),
+            _ => {
+                match motion::target(&self.buffer, &self.iskeyword, motion, from, count) {
+                    Some(target) => {
+                        if motion.is_jump() && target != from {
+                            self.push_jump(from);
+                        }
+                        self.cursor_y = target.line;
+                        self.cursor_x = target.col;
+                        self.adjust_cursor_to_line_end();
+                    }
+                    None => self.fail(),
+                }
+            }
+        }
+        if matches!(motion, Motion::Left | Motion::Right | Motion::Up | Motion::Down) && self.cursor_pos() == from {
+            self.fail();
+        }
+    }
+
+    pub(super) fn handle_operator_pending_key(&mut self, op: Operator, event: KeyEvent, text_area_height: u16) {
+        self.message.clear();
+        if let Some(pending) = self.pending_key.take() {
+            self.handle_pending_key(pending, event, text_area_height);
+            return;
+        }
+        if self.push_count_key(&event) {
+            return;
+        }
+        if let Some(motion) = Self::motion_for_key(&event) {
+            self.run_motion(motion, text_area_height);
+            return;
+        }
+        match event.code {
+            KeyCode::Char(c) if c == op.line_key() => self.apply_to_lines(op, text_area_height),
+            KeyCode::Char('g') => self.pending_key = Some(PendingKey::G),
+            KeyCode::Char(c @ ('f' | 't' | 'F' | 'T')) => self.pending_key = Some(PendingKey::find(c)),
+            KeyCode::Char(c @ (';' | ',')) => self.repeat_find(c == ',', text_area_height),
+            KeyCode::Char(c @ ('\'' | '`')) => self.pending_key = Some(PendingKey::GotoMark { linewise: c == '\'' }),
+            KeyCode::Char(c @ ('i' | 'a')) => self.pending_key = Some(PendingKey::TextObject { inner: c == 'i' }),
+            _ => self.cancel_pending(),
+        }
+    }
+
+    /// Finishes a two-key command started in Normal or Operator-pending mode.
+    pub(super) fn handle_pending_key(&mut self, pending: PendingKey, event: KeyEvent, text_area_height: u16) {
+        let KeyCode::Char(c) = event.code else {
+            self.cancel_pending();
+            return;
+        };
+        match pending {
+            PendingKey::G => match (c, Operator::from_g_key(c), self.mode) {
+                ('g', _, _) => self.run_motion(Motion::FirstLine, text_area_height),
+                ('e', _, _) => self.run_motion(Motion::WordEndBackward { big: false }, text_area_height),
+                ('E', _, _) => self.run_motion(Motion::WordEndBackward { big: true }, text_area_height),
+                ('v', _, Mode::Normal | Mode::Visual(_)) => self.reselect_visual(),
+                ('i', _, Mode::Normal) => self.insert_at_last(),
+                (';' | ',', _, Mode::Normal) => {
+                    let count = self.take_count().unwrap_or(1);
+                    self.step_changes(count, c == ',', text_area_height);
+                }
+                (_, Some(g_op), Mode::OperatorPending(op)) if g_op == op => self.apply_to_lines(op, text_area_height),
+                (_, Some(g_op), Mode::Normal) => self.begin_operator(g_op),
+                (_, Some(g_op), Mode::Visual(_)) => self.apply_visual_operator(g_op),
+                _ => self.cancel_pending(),
+            },
+            PendingKey::Find { till, backward } => {
+                let search = CharSearch { ch: c, till, backward };
+                self.last_find = Some(search);
+                self.run_motion(Motion::FindChar(search), text_area_height);
+            }
+            PendingKey::Register if Registers::is_valid(c) => self.selected_register = Some(c),
+            PendingKey::Register | PendingKey::InsertRegister | PendingKey::Window => self.cancel_pending(),
+            PendingKey::TextObject { inner } => self.select_text_object(c, inner, text_area_height),
+            PendingKey::Record => self.start_recording(c),
+            PendingKey::SetMark => self.set_mark(c),
+            PendingKey::GotoMark { linewise } => self.goto_mark(c, linewise, text_area_height),
+            PendingKey::Macro => {
+                let count = self.take_count().unwrap_or(1);
+                self.play_macro(c, count);
+            }
+        }
+    }
+
+    /// `;` and `,`: the last `f`, `t`, `F` or `T` again, the other way for `,`.
+    pub(super) fn repeat_find(&mut self, reverse: bool, text_area_height: u16) {
+        let Some(search) = self.last_find else {
+            self.cancel_pending();
+            self.fail();
+            return;
+        };
+        let search = CharSearch { backward: search.backward != reverse, ..search };
+        self.run_motion(Motion::RepeatFind(search), text_area_height);
+    }
+
+    /// Columns of the cursor's line to highlight while `f` and friends wait
+    /// for their char, when `highlight_find_targets` is on.
+    pub(super) fn find_target_cols(&self) -> Vec<usize> {
+        let Some(PendingKey::Find { backward, .. }) = self.pending_key else {
+            return Vec::new();
+        };
+        if !self.config.editor.highlight_find_targets.unwrap_or(false) {
+            return Vec::new();
+        }
+        motion::find_targets(&self.buffer.line(self.cursor_y), self.cursor_x, backward)
+    }
+
+    /// Applies the pending operator to the text object on `key`, or in
+    /// Visual mode selects it.
+    fn select_text_object(&mut self, key: char, inner: bool, text_area_height: u16) {
+        let count = self.take_count().unwrap_or(1);
+        let context = textobject::Context {
+            buffer: &self.buffer,
+            iskeyword: &self.iskeyword,
+            cursor: self.cursor_char(),
+            count,
+            inner,
+        };
+        let Some(object) = self.text_objects.get(key).and_then(|object| object(&context)) else {
+            self.cancel_pending();
+            self.fail();
+            return;
+        };
+        let pos = |idx: usize| {
+            let (line, col) = self.buffer.char_to_pos(idx);
+            Pos::new(line, col)
+        };
+        let range = match object {
+            ObjectRange::Chars(chars) => OperatorRange { start: pos(chars.start), end: pos(chars.end), linewise: false },
+            ObjectRange::Lines(lines) => OperatorRange {
+                start: Pos::new(*lines.start(), 0),
+                end: Pos::new(*lines.end(), 0),
+                linewise: true,
+            },
+        };
+        match self.mode {
+            Mode::OperatorPending(op) => {
+                self.mode = Mode::Normal;
+                self.apply_operator(op, range);
+                self.selected_register = None;
+            }
+            Mode::Visual(kind) => self.select_range(kind, range),
+            _ => {}
+        }
+        self.adjust_scroll(text_area_height);
+    }
+
+    /// Drops the count after a key that doesn't finish a command, along with
+    /// any operator waiting for a motion.
+    pub(super) fn cancel_pending(&mut self) {
+        self.take_count();
+        self.selected_register = None;
+        if let Mode::OperatorPending(_) = self.mode {
+            self.mode = Mode::Normal;
+        }
+    }
+
+    /// `dd`, `yy`, `>>` and friends: the operator over the cursor's line, or
+    /// over `count` lines from it.
+    pub(super) fn apply_to_lines(&mut self, op: Operator, text_area_height: u16) {
+        self.mode = Mode::Normal;
+        let count = self.take_count().unwrap_or(1);
+        let start = Pos::new(self.cursor_y, 0);
+        let end = Pos::new((self.cursor_y + count - 1).min(self.buffer.len_lines() - 1), 0);
+        self.apply_operator(op, OperatorRange { start, end, linewise: true });
+        self.adjust_scroll(text_area_height);
+    }
+
+    pub(super) fn cursor_pos(&self) -> Pos {
+        Pos::new(self.cursor_y, self.cursor_x)
+    }
+
+    /// Works out what `op` applies to when combined with `motion`.
+    fn operator_range(&self, op: Operator, motion: Motion, count: usize) -> Option<OperatorRange> {
+        let cursor = self.cursor_pos();
+        let on_blank = self.buffer.line(cursor.line).chars().nth(cursor.col).is_none_or(char::is_whitespace);
+        let (target, mut kind) = match motion {
+            Motion::WordForward { big } if op == Operator::Change && !on_blank => {
+                // `cw` changes to the end of the word, leaving the blanks after it.
+                let words = motion::Words { iskeyword: &self.iskeyword, big };
+                let first = motion::current_word_end(&self.buffer, words, self.cursor_char());
+                let end = (1..count).fold(first, |idx, _| motion::word_end(&self.buffer, words, idx));
+                let (line, col) = self.buffer.char_to_pos(end);
+                (Pos::new(line, col), MotionKind::Inclusive)
+            }
+            _ => (motion::target(&self.buffer, &self.iskeyword, motion, cursor, count)?, motion.kind()),
+        };
+        let (start, mut end) = if target < cursor { (target, cursor) } else { (cursor, target) };
+
+        if kind == MotionKind::Exclusive && end.line > start.line && end.col <= motion::first_non_blank(&self.buffer, end.line) {
+            // An operator never takes `w` onto the next line, and an exclusive
+            // motion ending at the start of a line stops at the end of the one
+            // before it instead. If it also began at the start of its line, the
+            // whole lines go.
+            let at_line_start = start.col <= motion::first_non_blank(&self.buffer, start.line);
+            let word = matches!(motion, Motion::WordForward { .. });
+            if word || end.col == 0 {
+                end = Pos::new(end.line - 1, self.buffer.line_len(end.line - 1));
+                if !word && at_line_start {
+                    kind = MotionKind::Linewise;
+                }
+            }
+        }
+
+        match kind {
+            MotionKind::Linewise => Some(OperatorRange { start, end, linewise: true }),
+            MotionKind::Inclusive => {
+                let line = self.buffer.line(end.line);
+                end.col = unicode::next_grapheme_boundary(&line, end.col);
+                Some(OperatorRange { start, end, linewise: false })
+            }
+            MotionKind::Exclusive => Some(OperatorRange { start, end, linewise: false }),
+        }
+    }
+
+    /// Char range of the lines `first..=last`, without the final line break.
+    pub(super) fn line_span(&self, first: usize, last: usize) -> Range<usize> {
+        self.buffer.line_to_char(first)..self.buffer.pos_to_char(last, self.buffer.line_len(last))
+    }
+
+    fn range_chars(&self, range: &OperatorRange) -> Range<usize> {
+        if range.linewise {
+            self.line_span(range.start.line, range.end.line)
+        } else {
+            self.buffer.pos_to_char(range.start.line, range.start.col)..self.buffer.pos_to_char(range.end.line, range.end.col)
+        }
+    }
+
+    fn register_for(&self, range: &OperatorRange) -> Register {
+        let chars = self.range_chars(range);
+        let mut text = self.buffer.slice(chars);
+        if range.linewise {
+            text.push('\n');
+        }
+        Register {
+            text,
+            kind: if range.linewise { RegisterKind::Linewise } else { RegisterKind::Charwise },
+        }
+    }
+
+    pub(super) fn apply_operator(&mut self, op: Operator, range: OperatorRange) {
+        match op {
+            Operator::Yank => {
+                self.store_register(self.register_for(&range), Store::Yank);
+                let end = if range.linewise {
+                    Pos::new(range.end.line, self.buffer.line_len(range.end.line).saturating_sub(1))
+                } else {
+                    Pos::new(range.end.line, range.end.col.saturating_sub(1))
+                };
+                let start = if range.linewise { Pos::new(range.start.line, 0) } else { range.start };
+                self.marks.set('[', start);
+                self.marks.set(']', end);
+                if range.linewise {
+                    self.cursor_y = range.start.line;
+                } else {
+                    self.cursor_y = range.start.line;
+                    self.cursor_x = range.start.col;
+                }
+                self.adjust_cursor_to_line_end();
+            }
+            Operator::Delete => {
+                self.store_register(self.register_for(&range), range.store());
+                if range.linewise {
+                    // Take a line break with the lines: the one after them, or
+                    // the one before if they run to the end of the buffer.
+                    let mut chars = self.range_chars(&range);
+                    if chars.end < self.buffer.len_chars() {
+                        chars.end += 1;
+                    } else if chars.start > 0 {
+                        chars.start -= 1;
+                    }
+                    self.delete_text(chars);
+                    self.cursor_y = range.start.line.min(self.buffer.len_lines() - 1);
+                    self.cursor_x = motion::first_non_blank(&self.buffer, self.cursor_y);
+                } else {
+                    let chars = self.range_chars(&range);
+                    self.delete_text(chars);
+                    self.cursor_y = range.start.line;
+                    self.cursor_x = range.start.col;
+                }
+                self.adjust_cursor_to_line_end();
+            }
+            Operator::Change => {
+                self.store_register(self.register_for(&range), range.store());
+                // Linewise changes keep one, now empty, line to type into.
+                let chars = self.range_chars(&range);
+                self.delete_text(chars);
+                self.cursor_y = range.start.line;
+                self.cursor_x = if range.linewise { 0 } else { range.start.col };
+                self.start_insert();
+            }
+            Operator::Indent | Operator::Outdent => {
+                for line in range.start.line..=range.end.line {
+                    self.shift_line(line, op == Operator::Indent);
+                }
+                self.cursor_y = range.start.line;
+                self.cursor_x = motion::first_non_blank(&self.buffer, self.cursor_y);
+            }
+            Operator::Lowercase | Operator::Uppercase | Operator::ToggleCase => {
+                let chars = self.range_chars(&range);
+                let text = self.buffer.slice(chars.clone());
+                let changed: String = match op {
+                    Operator::Lowercase => text.to_lowercase(),
+                    Operator::Uppercase => text.to_uppercase(),
+                    _ => text.chars().map(toggle_case).collect(),
+                };
+                if changed != text {
+                    self.delete_text(chars.clone());
+                    self.insert_text(chars.start, &changed);
+                }
+                self.cursor_y = range.start.line;
+                if !range.linewise {
+                    self.cursor_x = range.start.col;
+                }
+                self.adjust_cursor_to_line_end();
+            }
+        }
+    }
+
+    pub(super) fn shiftwidth(&self) -> usize {
+        match self.config.editor.shiftwidth {
+            Some(width) if width > 0 => width,
+            _ => self.tabstop(),
+        }
+    }
+
+    /// Adds or removes one `shiftwidth` of indent on `line`. Empty lines are
+    /// left alone when indenting.
+    fn shift_line(&mut self, line: usize, indent: bool) {
+        let text = self.buffer.line(line);
+        if indent && text.is_empty() {
+            return;
+        }
+        let width = self.indent_width(line);
+        let sw = self.shiftwidth();
+        let new_width = if indent { width + sw } else { width.saturating_sub(sw) };
+        self.set_indent(line, new_width);
+    }
+
+    /// Display width of `line`'s indent.
+    pub(super) fn indent_width(&self, line: usize) -> usize {
+        let text = self.buffer.line(line);
+        let indent_len = text.chars().take_while(|&c| c == ' ' || c == '\t').count();
+        unicode::display_col(&text, indent_len, self.tabstop())
+    }
+
+    /// Replaces `line`'s indent with one `width` columns wide: tabs and
+    /// spaces, or only spaces with `expandtab`.
+    pub(super) fn set_indent(&mut self, line: usize, width: usize) {
+        let text = self.buffer.line(line);
+        let indent_len = text.chars().take_while(|&c| c == ' ' || c == '\t').count();
+        let new_indent = if self.config.editor.expandtab.unwrap_or(false) {
+            " ".repeat(width)
+        } else {
+            let ts = self.tabstop();
+            format!("{}{}", "\t".repeat(width / ts), " ".repeat(width % ts))
+        };
+        let start = self.buffer.line_to_char(line);
+        self.delete_text(start..start + indent_len);
+        self.insert_text(start, &new_indent);
+    }
+
+    /// `~`: switches the case of the char under the cursor and moves right.
+    pub(super) fn toggle_case_under_cursor(&mut self) {
+        let line = self.buffer.line(self.cursor_y);
+        if self.cursor_x >= self.buffer.line_len(self.cursor_y) {
+            return;
+        }
+        let next = unicode::next_grapheme_boundary(&line, self.cursor_x);
+        let start = self.cursor_pos();
+        let end = Pos::new(self.cursor_y, next);
+        self.apply_operator(Operator::ToggleCase, OperatorRange { start, end, linewise: false });
+        self.cursor_x = next.min(self.buffer.line_len(self.cursor_y));
+        self.adjust_cursor_to_line_end();
+    }
+
+    /// `p`/`P`: puts the selected register after or before the cursor,
+    /// `count` times over.
+    pub(super) fn put(&mut self, after: bool, count: usize) {
+        let name = self.selected_register.take();
+        let Some(mut register) = self.read_register(name) else {
+            self.message = format!("Nothing in register {}", name.unwrap_or('"'));
+            return;
+        };
+        if register.kind == RegisterKind::Blockwise {
+            register.text = register.text.split('\n').map(|piece| piece.repeat(count)).collect::<Vec<_>>().join("\n");
+        } else {
+            register.text = register.text.repeat(count);
+        }
+        match register.kind {
+            RegisterKind::Linewise => {
+                let line = if after { self.cursor_y + 1 } else { self.cursor_y };
+                if line < self.buffer.len_lines() {
+                    let at = self.buffer.line_to_char(line);
+                    self.insert_text(at, &register.text);
+                } else {
+                    let text = register.text.strip_suffix('\n').unwrap_or(&register.text);
+                    let at = self.buffer.len_chars();
+                    self.insert_text(at, &format!("\n{}", text));
+                }
+                self.cursor_y = line;
+                self.cursor_x = motion::first_non_blank(&self.buffer, line);
+            }
+            RegisterKind::Blockwise => self.put_block(&register.text, after),
+            RegisterKind::Charwise => {
+                let mut col = self.cursor_x;
+                if after && col < self.buffer.line_len(self.cursor_y) {
+                    col = unicode::next_grapheme_boundary(&self.buffer.line(self.cursor_y), col);
+                }
+                let at = self.buffer.pos_to_char(self.cursor_y, col);
+                self.insert_text(at, &register.text);
+                // The cursor ends up on the last char put.
+                let end = at + register.text.chars().count();
+                self.set_cursor_char(end.saturating_sub(1).max(at));
+            }
+        }
+        self.adjust_cursor_to_line_end();
+    }
+
+    /// Puts a block's pieces at the cursor's display column (or just after
+    /// it) on the cursor's line and the ones below, adding lines and padding
+    /// short ones as needed.
+    fn put_block(&mut self, text: &str, after: bool) {
+        let line_text = self.buffer.line(self.cursor_y);
+        let tabstop = self.tabstop();
+        let mut col = self.cursor_x;
+        if after && col < self.buffer.line_len(self.cursor_y) {
+            col = unicode::next_grapheme_boundary(&line_text, col);
+        }
+        let display_col = unicode::display_col(&line_text, col, tabstop);
+        for (i, piece) in text.split('\n').enumerate() {
+            let line = self.cursor_y + i;
+            if line >= self.buffer.len_lines() {
+                let at = self.buffer.len_chars();
+                self.insert_text(at, "\n");
+            }
+            let current = self.buffer.line(line);
+            let width = unicode::display_col(&current, current.chars().count(), tabstop);
+            let at = if width < display_col {
+                let end = self.buffer.pos_to_char(line, self.buffer.line_len(line));
+                self.insert_text(end, &" ".repeat(display_col - width));
+                end + display_col - width
+            } else {
+                self.buffer.pos_to_char(line, unicode::col_at_display(&current, display_col, tabstop))
+            };
+            self.insert_text(at, piece);
+        }
+        self.cursor_x = col;
+    }
+}
+
+fn toggle_case(c: char) -> char {
+    if c.is_lowercase() {
+        c.to_uppercase().next().unwrap_or(c)
+    } else if c.is_uppercase() {
+        c.to_lowercase().next().unwrap_or(c)
+    } else {
+        c
+    }
+}
@@ -0,0 +1,112 @@
+use super::{Editor, Mode};
+use crate::keys;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+/// A change `.` can make again: the keys that made it, without the count,
+/// which is kept apart so that a count given to `.` can replace it.
+pub struct Change {
+    keys: Vec<KeyEvent>,
+    count: Option<usize>,
+}
+
+/// The keys of the command being typed, kept in case it turns out to change
+/// the buffer.
+#[derive(Default)]
+pub struct ChangeRecorder {
+    keys: Vec<KeyEvent>,
+    count: Option<usize>,
+    /// `changedtick` when the command was started.
+    tick: usize,
+    /// Set by commands `.` doesn't repeat, like undo and command lines.
+    ignore: bool,
+}
+
+impl Editor {
+    /// Whether the editor is waiting for a new command in Normal mode, so
+    /// that the next key starts one.
+    pub(super) fn is_idle(&self) -> bool {
+        self.mode == Mode::Normal
+            && self.pending_key.is_none()
+            && self.count.is_none()
+            && self.selected_register.is_none()
+    }
+
+    /// Adds a key to the command being recorded, starting a new one if the
+    /// editor was idle.
+    pub(super) fn record_change_key(&mut self, event: KeyEvent) {
+        if self.is_idle() {
+            self.change = ChangeRecorder { tick: self.changedtick, ..ChangeRecorder::default() };
+        }
+        self.change.keys.push(event);
+    }
+
+    /// Takes the key just recorded back out when it was a digit of a count,
+    /// which is kept as a number instead.
+    pub(super) fn record_count_key(&mut self) {
+        if !matches!(self.mode, Mode::Visual(_)) {
+            self.change.keys.pop();
+        }
+    }
+
+    /// Notes the count the command being recorded was given.
+    pub(super) fn record_change_count(&mut self, count: Option<usize>) {
+        if count.is_some() && !matches!(self.mode, Mode::Visual(_)) {
+            self.change.count = count;
+        }
+    }
+
+    /// Ctrl-O in Insert mode: to `.`, the text typed so far is a change of
+    /// its own, as if Esc had ended it.
+    pub(super) fn record_insert_end(&mut self) {
+        if let Some(key) = self.change.keys.last_mut() {
+            *key = KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE);
+        }
+    }
+
+    /// Whether the command being run has changed the buffer yet.
+    pub(super) fn changed_in_command(&self) -> bool {
+        self.change.tick != self.changedtick
+    }
+
+    /// Keeps the command being run from becoming the one `.` repeats.
+    pub(super) fn forget_change(&mut self) {
+        self.change.ignore = true;
+    }
+
+    /// Keeps undoing and redoing from counting as changes, for `.` or the
+    /// change list, though they do change the buffer.
+    pub(super) fn forget_undo(&mut self) {
+        self.forget_change();
+        self.change.tick = self.changedtick;
+    }
+
+    /// Once the editor is idle again, keeps the command just finished for
+    /// `.` if it changed the buffer.
+    pub(super) fn finish_change(&mut self) {
+        if !self.is_idle() {
+            return;
+        }
+        if self.changed_in_command() {
+            self.push_change();
+        }
+        let change = std::mem::take(&mut self.change);
+        if !change.ignore && change.tick != self.changedtick && !change.keys.is_empty() {
+            self.last_change = Some(Change { keys: change.keys, count: change.count });
+        }
+        self.change.tick = self.changedtick;
+    }
+
+    /// `.`: makes the last change again, `count` times if given in place of
+    /// the count it had.
+    pub(super) fn repeat_change(&mut self, count: Option<usize>) {
+        let Some(change) = &self.last_change else {
+            self.fail();
+            return;
+        };
+        let count = count.or(change.count).map(|count| count.to_string()).unwrap_or_default();
+        let replay: Vec<_> = count.chars().map(keys::from_char).chain(change.keys.iter().copied()).collect();
+        for key in replay.into_iter().rev() {
+            self.typeahead.push_front(key);
+        }
+    }
+}
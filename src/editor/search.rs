@@ -0,0 +1,198 @@
+use super::{Editor, Mode};
+use crate::search::{self, CaseOptions};
+use crossterm::event::{KeyCode, KeyEvent};
+use regex::Regex;
+
+/// Where the cursor was when `/` or `?` was typed, so it can go back there
+/// if the search is cancelled.
+pub struct SearchOrigin {
+    cursor_y: usize,
+    cursor_x: usize,
+    scroll_offset_y: usize,
+    scroll_offset_x: usize,
+    /// What was highlighted before the incremental search took over.
+    highlight: Option<Regex>,
+    count: usize,
+}
+
+impl Editor {
+    /// `/` and `?`: opens the search prompt.
+    pub(super) fn start_search(&mut self, backward: bool, count: usize) {
+        self.search_origin = Some(SearchOrigin {
+            cursor_y: self.cursor_y,
+            cursor_x: self.cursor_x,
+            scroll_offset_y: self.scroll_offset_y,
+            scroll_offset_x: self.scroll_offset_x,
+            highlight: self.search_regex.clone(),
+            count,
+        });
+        self.command_input.clear();
+        self.mode = Mode::Search { backward };
+    }
+
+    /// Keys typed at the search prompt. Editing works as on the command line;
+    /// the cursor jumps to the first match as the pattern is typed.
+    pub(super) fn handle_search_key(&mut self, backward: bool, event: KeyEvent, text_area_height: u16) {
+        if self.pending_key.is_none() {
+            match event.code {
+                KeyCode::Enter => {
+                    let count = self.restore_search_origin(false);
+                    self.mode = Mode::Normal;
+                    self.search(backward, count, text_area_height);
+                    return;
+                }
+                KeyCode::Esc => {
+                    self.restore_search_origin(true);
+                    self.command_input.clear();
+                    self.mode = Mode::Normal;
+                    return;
+                }
+                _ => {}
+            }
+        }
+        // Only Enter can leave the command line.
+        let _ = self.handle_command_mode_key(event, text_area_height);
+        self.incremental_search(backward, text_area_height);
+    }
+
+    /// Moves the cursor to the first match of what's been typed so far and
+    /// highlights the rest.
+    fn incremental_search(&mut self, backward: bool, text_area_height: u16) {
+        let Some(origin) = &self.search_origin else {
+            return;
+        };
+        let (cursor_y, cursor_x) = (origin.cursor_y, origin.cursor_x);
+        let (scroll_offset_y, scroll_offset_x) = (origin.scroll_offset_y, origin.scroll_offset_x);
+        self.cursor_y = cursor_y;
+        self.cursor_x = cursor_x;
+        self.scroll_offset_y = scroll_offset_y;
+        self.scroll_offset_x = scroll_offset_x;
+        if self.command_input.is_empty() {
+            self.search_regex = origin.highlight.clone();
+            return;
+        }
+        let Ok(regex) = search::compile(&self.command_input, self.case_options()) else {
+            return;
+        };
+        if let Some(found) = search::find(&self.buffer, &regex, self.cursor_char(), backward) {
+            self.set_cursor_char(found.range.start);
+            self.adjust_scroll(text_area_height);
+        }
+        self.search_regex = Some(regex);
+    }
+
+    /// Puts the cursor back where the search started, along with the old
+    /// highlight if `cancel`. Returns the count typed before `/`.
+    fn restore_search_origin(&mut self, cancel: bool) -> usize {
+        let Some(origin) = self.search_origin.take() else {
+            return 1;
+        };
+        self.cursor_y = origin.cursor_y;
+        self.cursor_x = origin.cursor_x;
+        self.scroll_offset_y = origin.scroll_offset_y;
+        self.scroll_offset_x = origin.scroll_offset_x;
+        if cancel {
+            self.search_regex = origin.highlight;
+        }
+        origin.count
+    }
+
+    /// Runs the pattern on the search prompt, or the last one if it's empty.
+    fn search(&mut self, backward: bool, count: usize, text_area_height: u16) {
+        let pattern = std::mem::take(&mut self.command_input);
+        if !pattern.is_empty() {
+            self.registers.last_search = pattern;
+        }
+        self.search_backward = backward;
+        self.search_next(false, count, text_area_height);
+    }
+
+    /// `n` and `N`: repeats the last search, in the other direction if
+    /// `reverse`.
+    pub(super) fn search_next(&mut self, reverse: bool, count: usize, text_area_height: u16) {
+        let pattern = self.registers.last_search.clone();
+        if pattern.is_empty() {
+            self.message = "No previous regular expression".to_string();
+            self.fail();
+            return;
+        }
+        let regex = match search::compile(&pattern, self.case_options()) {
+            Ok(regex) => regex,
+            Err(_) => {
+                self.message = format!("Invalid pattern: {}", pattern);
+                self.fail();
+                return;
+            }
+        };
+        let backward = self.search_backward != reverse;
+        let mut at = self.cursor_char();
+        let mut wrapped = false;
+        for _ in 0..count {
+            match search::find(&self.buffer, &regex, at, backward) {
+                Some(found) => {
+                    at = found.range.start;
+                    wrapped |= found.wrapped;
+                }
+                None => {
+                    self.message = format!("Pattern not found: {}", pattern);
+                    self.search_regex = Some(regex);
+                    self.fail();
+                    return;
+                }
+            }
+        }
+        self.push_jump(self.cursor_pos());
+        self.set_cursor_char(at);
+        self.adjust_scroll(text_area_height);
+        self.message = match (wrapped, backward) {
+            (true, false) => "search hit BOTTOM, continuing at TOP".to_string(),
+            (true, true) => "search hit TOP, continuing at BOTTOM".to_string(),
+            (false, _) => format!("{}{}", if backward { '?' } else { '/' }, pattern),
+        };
+        self.search_regex = Some(regex);
+    }
+
+    /// `*` and `#`: searches for the whole word under or after the cursor.
+    pub(super) fn search_word(&mut self, backward: bool, count: usize, text_area_height: u16) {
+        let line: Vec<char> = self.buffer.line(self.cursor_y).chars().collect();
+        let Some(start) = (self.cursor_x..line.len()).find(|&i| self.iskeyword.contains(line[i])) else {
+            self.message = "No string under cursor".to_string();
+            self.fail();
+            return;
+        };
+        let start = (0..start).rev().take_while(|&i| self.iskeyword.contains(line[i])).last().unwrap_or(start);
+        let end = (start..line.len()).find(|&i| !self.iskeyword.contains(line[i])).unwrap_or(line.len());
+        let word: String = line[start..end].iter().collect();
+
+        // `\<` only matches where the regex engine sees a word start, which
+        // a keyword char like `-` from 'iskeyword' isn't.
+        let is_word = |c: char| c.is_alphanumeric() || c == '_';
+        let mut pattern = regex::escape(&word);
+        if line[start..end].first().copied().is_some_and(is_word) {
+            pattern.insert_str(0, r"\<");
+        }
+        if line[start..end].last().copied().is_some_and(is_word) {
+            pattern.push_str(r"\>");
+        }
+        self.registers.last_search = pattern;
+        self.search_backward = backward;
+        // Start from the word itself so `#` doesn't land on it.
+        self.cursor_x = start;
+        self.search_next(false, count, text_area_height);
+    }
+
+    pub(super) fn case_options(&self) -> CaseOptions {
+        CaseOptions {
+            ignorecase: self.config.editor.ignorecase.unwrap_or(false),
+            smartcase: self.config.editor.smartcase.unwrap_or(false),
+        }
+    }
+
+    /// Char columns of the highlighted matches on `line`.
+    pub(super) fn search_match_cols(&self, line: &str) -> Vec<std::ops::Range<usize>> {
+        match &self.search_regex {
+            Some(regex) => search::line_matches(line, regex),
+            None => Vec::new(),
+        }
+    }
+}
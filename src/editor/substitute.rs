@@ -0,0 +1,276 @@
+use super::{Editor, Mode};
+use crate::ex::LineRange;
+use crate::motion;
+use crate::search::{self, CaseOptions};
+use crate::substitute::{self, Flags, Substitute};
+use crate::text_buffer::TextBuffer;
+use crossterm::event::{KeyCode, KeyEvent};
+use regex::Regex;
+use std::ops::Range;
+
+/// A `:s` part way through its lines. It's kept between keys while `c` asks
+/// about each match.
+pub struct Substitution {
+    pattern: String,
+    regex: Regex,
+    replacement: String,
+    flags: Flags,
+    /// The line being searched and the byte offset in it to search from.
+    line: usize,
+    from: usize,
+    last_line: usize,
+    /// Where the last match handled ended, so an empty match can't land
+    /// right after it.
+    last_end: Option<(usize, usize)>,
+    /// The match waiting for an answer in confirm mode, as bytes of `line`.
+    pending: Option<Range<usize>>,
+    found: bool,
+    substitutions: usize,
+    lines: usize,
+    last_changed_line: Option<usize>,
+}
+
+impl Substitution {
+    /// Finds the next match from `line`/`from` on, moving there.
+    fn next_match(&mut self, buffer: &TextBuffer) -> Option<Range<usize>> {
+        while self.line <= self.last_line {
+            let text = buffer.line(self.line);
+            while self.from <= text.len() {
+                let Some(m) = self.regex.find_at(&text, self.from) else {
+                    break;
+                };
+                if m.is_empty() && self.last_end == Some((self.line, m.start())) {
+                    self.from = next_char_boundary(&text, m.start());
+                    continue;
+                }
+                return Some(m.range());
+            }
+            self.line += 1;
+            self.from = 0;
+        }
+        None
+    }
+
+    /// Moves on from a match ending at byte `end` of `line`, to the next line
+    /// unless `g` was given.
+    fn advance(&mut self, line: usize, end: usize, text: &str, empty: bool) {
+        self.last_end = Some((line, end));
+        self.line = line;
+        self.from = if empty { next_char_boundary(text, end) } else { end };
+        if !self.flags.global {
+            self.line += 1;
+            self.from = 0;
+        }
+    }
+}
+
+fn next_char_boundary(text: &str, byte: usize) -> usize {
+    byte + text[byte..].chars().next().map_or(1, char::len_utf8)
+}
+
+impl Editor {
+    /// `:s/pattern/replacement/flags` over `range`, or the cursor's line.
+    pub(super) fn substitute(&mut self, range: Option<LineRange>, args: &str, text_area_height: u16) {
+        let range = range.unwrap_or(LineRange::line(self.cursor_y));
+        let command = match Substitute::parse(args) {
+            Ok(Some(command)) => command,
+            Ok(None) => match (&self.last_substitute, Flags::parse(args.trim())) {
+                (Some(last), Ok(flags)) => Substitute { flags, ..last.clone() },
+                (None, _) => {
+                    self.message = "No previous substitute regular expression".to_string();
+                    return;
+                }
+                (_, Err(e)) => {
+                    self.message = e;
+                    return;
+                }
+            },
+            Err(e) => {
+                self.message = e;
+                return;
+            }
+        };
+        if command.flags.confirm && self.global.is_some() {
+            self.message = "Cannot confirm substitutions under :global".to_string();
+            return;
+        }
+        // An empty pattern means the last one searched for.
+        let pattern = match command.pattern.as_str() {
+            "" => self.registers.last_search.clone(),
+            pattern => pattern.to_string(),
+        };
+        if pattern.is_empty() {
+            self.message = "No previous regular expression".to_string();
+            return;
+        }
+        let case = match command.flags.ignore_case {
+            Some(ignorecase) => CaseOptions { ignorecase, smartcase: false },
+            None => self.case_options(),
+        };
+        let regex = match search::compile(&pattern, case) {
+            Ok(regex) => regex,
+            Err(_) => {
+                self.message = format!("Invalid pattern: {}", pattern);
+                return;
+            }
+        };
+        self.registers.last_search = pattern.clone();
+        self.search_regex = Some(regex.clone());
+        self.last_substitute = Some(Substitute { pattern: pattern.clone(), ..command.clone() });
+
+        self.substitution = Some(Substitution {
+            pattern,
+            regex,
+            replacement: command.replacement,
+            flags: command.flags,
+            line: range.start,
+            from: 0,
+            last_line: range.end,
+            last_end: None,
+            pending: None,
+            found: false,
+            substitutions: 0,
+            lines: 0,
+            last_changed_line: None,
+        });
+        self.continue_substitution(text_area_height);
+    }
+
+    /// Handles matches until one needs confirming or there are none left.
+    fn continue_substitution(&mut self, text_area_height: u16) {
+        let Some(mut sub) = self.substitution.take() else {
+            return;
+        };
+        while let Some(m) = sub.next_match(&self.buffer) {
+            sub.found = true;
+            if sub.flags.confirm && !sub.flags.count_only {
+                let line = self.buffer.line(sub.line);
+                self.cursor_y = sub.line;
+                self.cursor_x = line[..m.start].chars().count();
+                self.adjust_cursor_to_line_end();
+                self.adjust_scroll(text_area_height);
+                sub.pending = Some(m);
+                self.substitution = Some(sub);
+                self.mode = Mode::Confirm;
+                return;
+            }
+            self.replace_match(&mut sub, m);
+        }
+        self.finish_substitution(sub, text_area_height);
+    }
+
+    /// Replaces (or with `n`, just counts) match `m` on the current line.
+    fn replace_match(&mut self, sub: &mut Substitution, m: Range<usize>) {
+        let line = sub.line;
+        let text = self.buffer.line(line);
+        sub.substitutions += 1;
+        if sub.last_changed_line != Some(line) {
+            sub.lines += 1;
+            sub.last_changed_line = Some(line);
+        }
+        if sub.flags.count_only {
+            sub.advance(line, m.end, &text, m.is_empty());
+            return;
+        }
+
+        let Some(captures) = sub.regex.captures_at(&text, m.start) else {
+            sub.advance(line, m.end, &text, m.is_empty());
+            return;
+        };
+        let replacement = substitute::expand(&sub.replacement, &captures);
+        let line_start = self.buffer.line_to_char(line);
+        let start = line_start + text[..m.start].chars().count();
+        let end = line_start + text[..m.end].chars().count();
+        self.delete_text(start..end);
+        self.insert_text(start, &replacement);
+
+        let line_breaks = replacement.matches('\n').count();
+        sub.last_line += line_breaks;
+        let (after_line, after_col) = self.buffer.char_to_pos(start + replacement.chars().count());
+        let after_text = self.buffer.line(after_line);
+        let after = after_text.char_indices().nth(after_col).map_or(after_text.len(), |(i, _)| i);
+        sub.last_changed_line = Some(after_line);
+        sub.advance(after_line, after, &after_text, m.is_empty());
+    }
+
+    /// Answers to "replace with ...?" in confirm mode.
+    pub(super) fn handle_confirm_key(&mut self, event: KeyEvent, text_area_height: u16) {
+        let Some(mut sub) = self.substitution.take() else {
+            self.mode = Mode::Normal;
+            return;
+        };
+        let Some(m) = sub.pending.take() else {
+            self.finish_substitution(sub, text_area_height);
+            return;
+        };
+        match event.code {
+            KeyCode::Char('y') => self.replace_match(&mut sub, m),
+            KeyCode::Char('l') => {
+                self.replace_match(&mut sub, m);
+                self.finish_substitution(sub, text_area_height);
+                return;
+            }
+            KeyCode::Char('n') => {
+                let text = self.buffer.line(sub.line);
+                sub.advance(sub.line, m.end, &text, m.is_empty());
+            }
+            KeyCode::Char('a') => {
+                self.replace_match(&mut sub, m);
+                sub.flags.confirm = false;
+            }
+            KeyCode::Char('q') | KeyCode::Esc => {
+                self.finish_substitution(sub, text_area_height);
+                return;
+            }
+            _ => {
+                sub.pending = Some(m);
+                self.substitution = Some(sub);
+                return;
+            }
+        }
+        self.substitution = Some(sub);
+        self.continue_substitution(text_area_height);
+    }
+
+    /// The prompt shown in the command box while confirming.
+    pub(super) fn confirm_prompt(&self) -> String {
+        let replacement = self.substitution.as_ref().map_or("", |sub| sub.replacement.as_str());
+        format!("replace with {} (y/n/a/q/l)?", replacement)
+    }
+
+    fn finish_substitution(&mut self, sub: Substitution, text_area_height: u16) {
+        self.mode = Mode::Normal;
+        // Under `:g` only the totals are reported, once it's done.
+        if let Some(global) = &mut self.global {
+            global.substitutions += sub.substitutions;
+            global.substituted_lines += sub.lines;
+            global.count_only = sub.flags.count_only;
+        } else if !sub.found {
+            self.message = format!("Pattern not found: {}", sub.pattern);
+            return;
+        }
+        if let Some(line) = sub.last_changed_line.filter(|_| !sub.flags.count_only) {
+            self.cursor_y = line;
+            self.cursor_x = motion::first_non_blank(&self.buffer, line);
+            self.adjust_cursor_to_line_end();
+            self.adjust_scroll(text_area_height);
+        }
+        if self.global.is_none() {
+            self.message = substitution_report(sub.flags.count_only, sub.substitutions, sub.lines);
+        }
+    }
+}
+
+/// "3 substitutions on 2 lines", or "3 matches" with the `n` flag.
+pub(super) fn substitution_report(count_only: bool, substitutions: usize, lines: usize) -> String {
+    let what = match (count_only, substitutions) {
+        (true, 1) => "1 match".to_string(),
+        (true, n) => format!("{} matches", n),
+        (false, 1) => "1 substitution".to_string(),
+        (false, n) => format!("{} substitutions", n),
+    };
+    match lines {
+        1 => format!("{} on 1 line", what),
+        n => format!("{} on {} lines", what, n),
+    }
+}
@@ -0,0 +1,195 @@
+use super::Editor;
+use crate::config::Config;
+use crate::keys;
+use crate::text_buffer::TextBuffer;
+
+/// An editor on an unnamed buffer holding `text`, with no terminal.
+fn open(text: &str) -> Editor {
+    let mut editor = Editor::new(Vec::new(), false, Config::default()).unwrap();
+    editor.buffer = TextBuffer::from(text);
+    editor.resize(80, 24);
+    editor
+}
+
+/// Types `keys`, written as for `:normal` with `<Esc>`-style names.
+fn type_keys(editor: &mut Editor, keys: &str) {
+    for key in keys::parse(keys) {
+        assert!(editor.type_key(key).unwrap(), "editor quit");
+    }
+}
+
+fn text(editor: &Editor) -> String {
+    editor.buffer.to_string()
+}
+
+fn cursor(editor: &Editor) -> (usize, usize) {
+    (editor.cursor_y, editor.cursor_x)
+}
+
+#[test]
+fn hex_mode_refuses_text_commands() {
+    let mut editor = open("abc");
+    type_keys(&mut editor, ":hex<CR>");
+    let bytes = editor.hex.as_ref().unwrap().bytes().to_vec();
+    type_keys(&mut editor, ":s/a/x/<CR>");
+    assert_eq!(editor.message, "Not allowed in hex mode: substitute");
+    assert_eq!(editor.hex.as_ref().unwrap().bytes(), bytes);
+    assert_eq!(text(&editor), "abc");
+    assert!(!editor.dirty);
+    type_keys(&mut editor, "u");
+    assert_eq!(editor.message, "Already at oldest change");
+    type_keys(&mut editor, ":hex<CR>");
+    assert_eq!(text(&editor), "abc");
+}
+
+#[test]
+fn hex_edits_from_a_macro_undo_in_one_step() {
+    let mut editor = open("");
+    editor.registers.record('a', ":hex\ria1b2\x1b".to_string());
+    type_keys(&mut editor, "@a");
+    assert_eq!(editor.hex.as_ref().unwrap().bytes(), [0xa1, 0xb2]);
+    type_keys(&mut editor, "i3<Esc>");
+    assert_eq!(editor.hex.as_ref().unwrap().bytes(), [0xa1, 0x32]);
+    type_keys(&mut editor, "u");
+    assert_eq!(editor.hex.as_ref().unwrap().bytes(), [0xa1, 0xb2]);
+    type_keys(&mut editor, "u");
+    assert_eq!(editor.hex.as_ref().unwrap().bytes(), b"\n");
+}
+
+#[test]
+fn long_lines_scroll_sideways_within_the_window() {
+    let mut editor = open(&format!("{}\nshort", "x".repeat(100)));
+    type_keys(&mut editor, ":vsplit<CR>");
+    let rect = editor.current_rect();
+    assert!(rect.width < 80);
+    type_keys(&mut editor, "$");
+    assert_eq!(editor.scroll_offset_x, editor.cursor_x + 1 - rect.width as usize);
+    let (x, _) = editor.cursor_screen_pos(rect);
+    assert_eq!(x, rect.x + rect.width - 1);
+    type_keys(&mut editor, "0");
+    assert_eq!(editor.scroll_offset_x, 0);
+    assert_eq!(editor.cursor_screen_pos(rect).0, rect.x);
+    type_keys(&mut editor, "$j");
+    // Scrolled back no further than needed to show the cursor.
+    assert_eq!(editor.scroll_offset_x, editor.cursor_x);
+    // Each window keeps its own.
+    type_keys(&mut editor, "k$<C-w>w");
+    assert_eq!(editor.scroll_offset_x, 0);
+    type_keys(&mut editor, "<C-w>w");
+    assert!(editor.scroll_offset_x > 0);
+}
+
+#[test]
+fn normal_mode_cursor_stays_on_the_last_char() {
+    for keys in ["$", "9l", "}", "ww", "e$e"] {
+        let mut editor = open("ab cdé");
+        type_keys(&mut editor, keys);
+        assert_eq!(cursor(&editor), (0, 5), "after {}", keys);
+    }
+    let mut editor = open("ab cd\nx");
+    type_keys(&mut editor, "$ay<Esc>");
+    assert_eq!((text(&editor).as_str(), cursor(&editor)), ("ab cdy\nx", (0, 5)));
+    // Visual mode can take in the line break.
+    type_keys(&mut editor, "v$d");
+    assert_eq!(text(&editor), "ab cdx");
+}
+
+#[test]
+fn percent_jumps_to_the_matching_bracket_or_through_the_file() {
+    let mut editor = open("f(a, b) x\n2\n3\n4");
+    type_keys(&mut editor, "%");
+    assert_eq!(cursor(&editor), (0, 6));
+    type_keys(&mut editor, "0d%");
+    assert_eq!(text(&editor), " x\n2\n3\n4");
+    type_keys(&mut editor, "50%");
+    assert_eq!(cursor(&editor), (1, 0));
+}
+
+#[test]
+fn global_marks_every_line_before_running() {
+    let mut editor = open("a\n\n\nb\n\nc");
+    type_keys(&mut editor, ":g/^$/d<CR>");
+    assert_eq!(text(&editor), "a\nb\nc");
+    type_keys(&mut editor, "u");
+    assert_eq!(text(&editor), "a\n\n\nb\n\nc");
+    type_keys(&mut editor, "<C-r>");
+    assert_eq!(text(&editor), "a\nb\nc");
+
+    let mut editor = open("x1\ny\nx2");
+    type_keys(&mut editor, ":v/x/normal Az<CR>");
+    assert_eq!(text(&editor), "x1\nyz\nx2");
+    type_keys(&mut editor, ":g/x/t$<CR>");
+    assert_eq!(text(&editor), "x1\nyz\nx2\nx1\nx2");
+}
+
+#[test]
+fn macros_repeat_and_stop_on_failure() {
+    let mut editor = open("x");
+    type_keys(&mut editor, "qaA!<Esc>q");
+    assert_eq!(editor.registers.get(Some('a'), None).unwrap().text, "A!<Esc>");
+    type_keys(&mut editor, "10@a");
+    assert_eq!(text(&editor), format!("x{}", "!".repeat(11)));
+    // The ten runs undo together.
+    type_keys(&mut editor, "u");
+    assert_eq!(text(&editor), "x!");
+
+    // A macro that calls itself runs until `j` has nowhere to go.
+    let mut editor = open("a\nb\nc\nd");
+    type_keys(&mut editor, "qaqqaA!<Esc>j@aq@a");
+    assert_eq!(text(&editor), "a!\nb!\nc!\nd!");
+    assert_eq!(cursor(&editor), (3, 1));
+    assert!(editor.typeahead.is_empty());
+    type_keys(&mut editor, "u");
+    assert_eq!(text(&editor), "a!\nb\nc\nd");
+}
+
+#[test]
+fn dot_count_replaces_the_recorded_one() {
+    let mut editor = open("a b c d e f g h i");
+    type_keys(&mut editor, "2dw");
+    assert_eq!(text(&editor), "c d e f g h i");
+    type_keys(&mut editor, "3.");
+    assert_eq!(text(&editor), "f g h i");
+    // The new count sticks for the next `.`.
+    type_keys(&mut editor, ".");
+    assert_eq!(text(&editor), "i");
+    type_keys(&mut editor, "u");
+    assert_eq!(text(&editor), "f g h i");
+
+    let mut editor = open("x");
+    type_keys(&mut editor, "a-<Esc>3.");
+    assert_eq!(text(&editor), "x----");
+    assert_eq!(cursor(&editor), (0, 4));
+}
+
+#[test]
+fn marks_and_place_lists_keep_to_their_lines() {
+    use crate::motion::Pos;
+
+    let mut editor = open("1\n2\n3\n4\n5");
+    type_keys(&mut editor, "4Gma2Gx");
+    type_keys(&mut editor, "ggOnew<Esc>");
+    assert_eq!(editor.mark_pos('a'), Ok(Pos::new(4, 0)));
+    // Back through the jumps to where `gg`, `2G` and `4G` were made from.
+    type_keys(&mut editor, "<C-o>");
+    assert_eq!(cursor(&editor), (2, 0));
+    type_keys(&mut editor, "<C-o>");
+    assert_eq!(cursor(&editor), (4, 0));
+    type_keys(&mut editor, "<C-o>");
+    assert_eq!(cursor(&editor), (1, 0));
+    // And through the changes: the `O`, then the `x` a line further down.
+    type_keys(&mut editor, "g;");
+    assert_eq!(cursor(&editor).0, 0);
+    type_keys(&mut editor, "g;");
+    assert_eq!(cursor(&editor), (2, 0));
+
+    // Undo moves them back too.
+    type_keys(&mut editor, "u");
+    assert_eq!(editor.mark_pos('a'), Ok(Pos::new(3, 0)));
+    type_keys(&mut editor, "<C-r>");
+    assert_eq!(editor.mark_pos('a'), Ok(Pos::new(4, 0)));
+
+    // Deleting its line drops a mark.
+    type_keys(&mut editor, "'add");
+    assert_eq!(editor.mark_pos('a'), Err("Mark not set".to_string()));
+}
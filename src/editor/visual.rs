@@ -0,0 +1,477 @@
+use super::operator::{Operator, OperatorRange};
+use super::{Editor, Mode};
+use crate::motion::{Motion, Pos};
+use crate::register::{Register, RegisterKind, Store};
+use crate::unicode;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::ops::Range;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VisualKind {
+    /// `v`: from one char to another.
+    Char,
+    /// `V`: whole lines.
+    Line,
+    /// Ctrl-V: a rectangle of display columns.
+    Block,
+}
+
+impl VisualKind {
+    fn from_key(event: &KeyEvent) -> Option<Self> {
+        let control = event.modifiers.contains(KeyModifiers::CONTROL);
+        match event.code {
+            KeyCode::Char('v') if control => Some(VisualKind::Block),
+            KeyCode::Char('v') => Some(VisualKind::Char),
+            KeyCode::Char('V') if !control => Some(VisualKind::Line),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            VisualKind::Char => "VISUAL",
+            VisualKind::Line => "V-LINE",
+            VisualKind::Block => "V-BLOCK",
+        }
+    }
+}
+
+/// A selection: where it was started and where the cursor is.
+#[derive(Debug, Clone, Copy)]
+pub struct Selection {
+    kind: VisualKind,
+    anchor: Pos,
+    cursor: Pos,
+    to_end: bool,
+}
+
+/// Insert mode started with `I` or `A` in Visual block mode. The text typed
+/// on the first line of the block is copied to the others when it ends.
+#[derive(Debug, Clone)]
+pub struct BlockInsert {
+    /// The block's lines after the first.
+    lines: Range<usize>,
+    display_col: usize,
+    /// Char index where typing started.
+    start: usize,
+    /// Whether lines too short to reach `display_col` get padded with
+    /// spaces (`A`) or skipped (`I`).
+    pad: bool,
+    /// Whether `A` was given on a block selected with `$`, so that the text
+    /// goes at the end of every line instead.
+    to_end: bool,
+}
+
+/// A block's lines and its display columns, end exclusive. A block selected
+/// with `$` has `usize::MAX` on the right.
+struct Block {
+    lines: Range<usize>,
+    left: usize,
+    right: usize,
+}
+
+impl Editor {
+    /// Starts Visual mode of `kind` from Normal mode.
+    pub(super) fn start_visual(&mut self, event: &KeyEvent) -> bool {
+        let Some(kind) = VisualKind::from_key(event) else {
+            return false;
+        };
+        self.visual_anchor = self.cursor_pos();
+        self.visual_to_end = false;
+        self.mode = Mode::Visual(kind);
+        true
+    }
+
+    pub(super) fn exit_visual(&mut self) {
+        if let Mode::Visual(kind) = self.mode {
+            self.last_visual = Some(self.selection(kind));
+        }
+        self.mode = Mode::Normal;
+    }
+
+    fn selection(&self, kind: VisualKind) -> Selection {
+        Selection { kind, anchor: self.visual_anchor, cursor: self.cursor_pos(), to_end: self.visual_to_end }
+    }
+
+    /// Start or end of the previous Visual area, for `'<` and `'>`.
+    pub(super) fn visual_mark(&self, last: bool) -> Option<Pos> {
+        let selection = self.last_visual.as_ref()?;
+        let (first, end) = if selection.anchor < selection.cursor {
+            (selection.anchor, selection.cursor)
+        } else {
+            (selection.cursor, selection.anchor)
+        };
+        Some(if last { end } else { first })
+    }
+
+    /// `gv`: selects the previous Visual area again. In Visual mode the
+    /// current selection becomes the previous one.
+    pub(super) fn reselect_visual(&mut self) {
+        let current = match self.mode {
+            Mode::Visual(kind) => Some(self.selection(kind)),
+            _ => None,
+        };
+        let Some(selection) = self.last_visual else {
+            return;
+        };
+        let last_line = self.buffer.len_lines() - 1;
+        let clamp = |pos: Pos, buffer: &crate::text_buffer::TextBuffer| {
+            let line = pos.line.min(last_line);
+            Pos::new(line, pos.col.min(buffer.line_len(line)))
+        };
+        self.visual_anchor = clamp(selection.anchor, &self.buffer);
+        let cursor = clamp(selection.cursor, &self.buffer);
+        self.cursor_y = cursor.line;
+        self.cursor_x = cursor.col;
+        self.visual_to_end = selection.to_end;
+        self.mode = Mode::Visual(selection.kind);
+        if current.is_some() {
+            self.last_visual = current;
+        }
+    }
+
+    pub(super) fn handle_visual_key(&mut self, kind: VisualKind, event: KeyEvent, text_area_height: u16) {
+        self.message.clear();
+        if let Some(pending) = self.pending_key.take() {
+            self.handle_pending_key(pending, event, text_area_height);
+            return;
+        }
+        if self.push_count_key(&event) {
+            return;
+        }
+        if let Some(motion) = Self::motion_for_key(&event) {
+            self.run_motion(motion, text_area_height);
+            // After `$`, moving up or down keeps to the ends of the lines.
+            match motion {
+                Motion::LineEnd => self.visual_to_end = true,
+                Motion::Up | Motion::Down if self.visual_to_end => self.cursor_x = self.buffer.line_len(self.cursor_y),
+                _ => self.visual_to_end = false,
+            }
+            return;
+        }
+        if let Some(new_kind) = VisualKind::from_key(&event) {
+            if new_kind == kind {
+                self.exit_visual();
+            } else {
+                self.mode = Mode::Visual(new_kind);
+            }
+            return;
+        }
+
+        let operator = match event.code {
+            KeyCode::Char('d' | 'x') | KeyCode::Delete => Some(Operator::Delete),
+            KeyCode::Char('c' | 's') => Some(Operator::Change),
+            KeyCode::Char('y') => Some(Operator::Yank),
+            KeyCode::Char('>') => Some(Operator::Indent),
+            KeyCode::Char('<') => Some(Operator::Outdent),
+            KeyCode::Char('u') => Some(Operator::Lowercase),
+            KeyCode::Char('U') => Some(Operator::Uppercase),
+            KeyCode::Char('~') => Some(Operator::ToggleCase),
+            _ => None,
+        };
+        if let Some(op) = operator {
+            self.apply_visual_operator(op);
+            self.adjust_scroll(text_area_height);
+            return;
+        }
+
+        match event.code {
+            KeyCode::Char('o') => {
+                let cursor = self.cursor_pos();
+                self.cursor_y = self.visual_anchor.line;
+                self.cursor_x = self.visual_anchor.col;
+                self.visual_anchor = cursor;
+            }
+            KeyCode::Char(':') => {
+                self.exit_visual();
+                self.mode = Mode::Command;
+                self.command_input = "'<,'>".to_string();
+            }
+            KeyCode::Char('g') => self.pending_key = Some(super::PendingKey::G),
+            KeyCode::Char(c @ ('f' | 't' | 'F' | 'T')) => self.pending_key = Some(super::PendingKey::find(c)),
+            KeyCode::Char(c @ (';' | ',')) => self.repeat_find(c == ',', text_area_height),
+            KeyCode::Char('"') => self.pending_key = Some(super::PendingKey::Register),
+            KeyCode::Char(c @ ('\'' | '`')) => self.pending_key = Some(super::PendingKey::GotoMark { linewise: c == '\'' }),
+            KeyCode::Char(c @ ('i' | 'a')) => self.pending_key = Some(super::PendingKey::TextObject { inner: c == 'i' }),
+            KeyCode::Char(c @ ('I' | 'A')) if kind == VisualKind::Block => self.block_insert(c == 'A'),
+            KeyCode::PageUp => self.page_up(text_area_height),
+            KeyCode::PageDown => self.page_down(text_area_height),
+            KeyCode::Esc => {
+                self.cancel_pending();
+                self.exit_visual();
+            }
+            _ => {}
+        }
+        self.adjust_scroll(text_area_height);
+    }
+
+    /// Selects a text object's `range`. Whole lines switch a charwise
+    /// selection to linewise.
+    pub(super) fn select_range(&mut self, kind: VisualKind, range: OperatorRange) {
+        let end = if range.linewise {
+            if kind == VisualKind::Char {
+                self.mode = Mode::Visual(VisualKind::Line);
+            }
+            range.end
+        } else {
+            let end = self.buffer.pos_to_char(range.end.line, range.end.col);
+            if end <= self.buffer.pos_to_char(range.start.line, range.start.col) {
+                self.fail();
+                return;
+            }
+            let (line, col) = self.buffer.char_to_pos(end - 1);
+            Pos::new(line, col)
+        };
+        self.visual_anchor = range.start;
+        self.cursor_y = end.line;
+        self.cursor_x = end.col;
+    }
+
+    fn selection_bounds(&self) -> (Pos, Pos) {
+        let cursor = self.cursor_pos();
+        if self.visual_anchor <= cursor {
+            (self.visual_anchor, cursor)
+        } else {
+            (cursor, self.visual_anchor)
+        }
+    }
+
+    /// End of a charwise selection ending on `pos`: past its grapheme, or
+    /// past the line break if it's at the end of the line.
+    fn char_selection_end(&self, pos: Pos) -> Pos {
+        let len = self.buffer.line_len(pos.line);
+        if pos.col < len {
+            Pos::new(pos.line, unicode::next_grapheme_boundary(&self.buffer.line(pos.line), pos.col))
+        } else if pos.line + 1 < self.buffer.len_lines() {
+            Pos::new(pos.line + 1, 0)
+        } else {
+            Pos::new(pos.line, len)
+        }
+    }
+
+    /// Display columns a block corner covers on its line.
+    fn block_corner(&self, pos: Pos) -> (usize, usize) {
+        let line = self.buffer.line(pos.line);
+        let tabstop = self.tabstop();
+        let start = unicode::display_col(&line, pos.col, tabstop);
+        let end = if pos.col < self.buffer.line_len(pos.line) {
+            unicode::display_col(&line, unicode::next_grapheme_boundary(&line, pos.col), tabstop)
+        } else {
+            start + 1
+        };
+        (start, end)
+    }
+
+    fn block(&self) -> Block {
+        let (start, end) = self.selection_bounds();
+        let (a_start, a_end) = self.block_corner(self.visual_anchor);
+        let (c_start, c_end) = self.block_corner(self.cursor_pos());
+        Block {
+            lines: start.line..end.line + 1,
+            left: a_start.min(c_start),
+            right: if self.visual_to_end { usize::MAX } else { a_end.max(c_end) },
+        }
+    }
+
+    /// Char columns of `line` that fall inside display columns `left..right`.
+    fn display_span(&self, line: usize, left: usize, right: usize) -> Range<usize> {
+        let text = self.buffer.line(line);
+        let tabstop = self.tabstop();
+        unicode::col_at_display(&text, left, tabstop)..unicode::col_at_display(&text, right, tabstop)
+    }
+
+    /// Columns of `line` to highlight for the selection; an end past the
+    /// line's length includes its line break. `None` outside Visual mode.
+    pub(super) fn visual_cols(&self, line: usize) -> Option<Range<usize>> {
+        let Mode::Visual(kind) = self.mode else {
+            return None;
+        };
+        let (start, end) = self.selection_bounds();
+        if line < start.line || line > end.line {
+            return None;
+        }
+        let len = self.buffer.line_len(line);
+        match kind {
+            VisualKind::Line => Some(0..len + 1),
+            VisualKind::Block => {
+                let block = self.block();
+                Some(self.display_span(line, block.left, block.right))
+            }
+            VisualKind::Char => {
+                let from = if line == start.line { start.col } else { 0 };
+                let to = if line < end.line {
+                    len + 1
+                } else if end.col < len {
+                    unicode::next_grapheme_boundary(&self.buffer.line(line), end.col)
+                } else {
+                    len + 1
+                };
+                Some(from..to)
+            }
+        }
+    }
+
+    /// Applies `op` to the selection and leaves Visual mode.
+    pub(super) fn apply_visual_operator(&mut self, op: Operator) {
+        let Mode::Visual(kind) = self.mode else {
+            return;
+        };
+        let count = self.take_count().unwrap_or(1);
+        let (start, end) = self.selection_bounds();
+        let block = self.block();
+        self.exit_visual();
+
+        match kind {
+            VisualKind::Block => self.apply_block_operator(op, block, count),
+            VisualKind::Line | VisualKind::Char => {
+                let range = if kind == VisualKind::Line {
+                    OperatorRange { start, end, linewise: true }
+                } else {
+                    OperatorRange { start, end: self.char_selection_end(end), linewise: false }
+                };
+                // `3>` shifts three times.
+                let times = if matches!(op, Operator::Indent | Operator::Outdent) { count } else { 1 };
+                for _ in 0..times {
+                    self.apply_operator(op, range);
+                }
+            }
+        }
+        self.selected_register = None;
+    }
+
+    fn apply_block_operator(&mut self, op: Operator, block: Block, count: usize) {
+        let spans: Vec<(usize, Range<usize>)> =
+            block.lines.clone().map(|line| (line, self.display_span(line, block.left, block.right))).collect();
+        let top = block.lines.start;
+
+        match op {
+            Operator::Indent | Operator::Outdent => {
+                let range = OperatorRange {
+                    start: Pos::new(top, 0),
+                    end: Pos::new(block.lines.end - 1, 0),
+                    linewise: true,
+                };
+                for _ in 0..count {
+                    self.apply_operator(op, range);
+                }
+                return;
+            }
+            Operator::Yank | Operator::Delete | Operator::Change => {
+                let text = spans
+                    .iter()
+                    .map(|(line, cols)| self.buffer.line(*line).chars().skip(cols.start).take(cols.len()).collect::<String>())
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                let store = if op == Operator::Yank { Store::Yank } else { Store::Delete { small: false } };
+                self.store_register(Register { text, kind: RegisterKind::Blockwise }, store);
+                if op != Operator::Yank {
+                    for (line, cols) in spans.iter().rev() {
+                        let start = self.buffer.pos_to_char(*line, cols.start);
+                        self.delete_text(start..start + cols.len());
+                    }
+                }
+            }
+            Operator::Lowercase | Operator::Uppercase | Operator::ToggleCase => {
+                for (line, cols) in spans.iter().rev() {
+                    let range = OperatorRange {
+                        start: Pos::new(*line, cols.start),
+                        end: Pos::new(*line, cols.end),
+                        linewise: false,
+                    };
+                    self.apply_operator(op, range);
+                }
+            }
+        }
+
+        self.cursor_y = top;
+        self.cursor_x = self.display_span(top, block.left, block.right).start;
+        if op == Operator::Change {
+            self.begin_block_insert(block.lines, block.left, false, false);
+        } else {
+            self.adjust_cursor_to_line_end();
+        }
+    }
+
+    /// `I`/`A` in Visual block mode: inserts before or after the block on
+    /// every one of its lines.
+    fn block_insert(&mut self, append: bool) {
+        let block = self.block();
+        self.take_count();
+        self.exit_visual();
+        let to_end = append && block.right == usize::MAX;
+        let display_col = if append { block.right } else { block.left };
+        let top = block.lines.start;
+        self.cursor_y = top;
+        if to_end {
+            self.cursor_x = self.buffer.line_len(top);
+        } else {
+            if append {
+                self.pad_line_to(top, display_col);
+            }
+            self.cursor_x = self.display_span(top, display_col, display_col).start;
+        }
+        self.begin_block_insert(block.lines, display_col, append, to_end);
+    }
+
+    fn begin_block_insert(&mut self, lines: Range<usize>, display_col: usize, pad: bool, to_end: bool) {
+        self.block_insert = Some(BlockInsert {
+            lines: lines.start + 1..lines.end,
+            display_col,
+            start: self.cursor_char(),
+            pad,
+            to_end,
+        });
+        self.start_insert();
+    }
+
+    /// Appends spaces to `line` until it reaches display column `display_col`.
+    fn pad_line_to(&mut self, line: usize, display_col: usize) {
+        let text = self.buffer.line(line);
+        let width = unicode::display_col(&text, text.chars().count(), self.tabstop());
+        if width < display_col {
+            let at = self.buffer.pos_to_char(line, self.buffer.line_len(line));
+            self.insert_text(at, &" ".repeat(display_col - width));
+        }
+    }
+
+    /// Copies the text typed after `I`/`A` in Visual block mode to the rest
+    /// of the block's lines, as long as it stayed on one line.
+    pub(super) fn finish_block_insert(&mut self) {
+        let Some(insert) = self.block_insert.take() else {
+            return;
+        };
+        let end = self.cursor_char();
+        if end <= insert.start {
+            return;
+        }
+        let text = self.buffer.slice(insert.start..end);
+        if text.contains('\n') {
+            return;
+        }
+        for line in insert.lines.clone().rev() {
+            if line >= self.buffer.len_lines() {
+                continue;
+            }
+            if insert.to_end {
+                let at = self.buffer.pos_to_char(line, self.buffer.line_len(line));
+                self.insert_text(at, &text);
+                continue;
+            }
+            if insert.pad {
+                self.pad_line_to(line, insert.display_col);
+            }
+            let span = self.display_span(line, insert.display_col, insert.display_col);
+            let text_width = {
+                let line_text = self.buffer.line(line);
+                unicode::display_col(&line_text, line_text.chars().count(), self.tabstop())
+            };
+            // `I` leaves alone lines that don't reach into the block.
+            if !insert.pad && text_width <= insert.display_col {
+                continue;
+            }
+            let at = self.buffer.pos_to_char(line, span.start);
+            self.insert_text(at, &text);
+        }
+        self.set_cursor_char(insert.start);
+    }
+}
@@ -0,0 +1,399 @@
+use super::operator::PendingKey;
+use super::Editor;
+use crate::ex::CommandLine;
+use crate::hex::HexBuffer;
+use crate::layout::{Layout, Rect};
+use crate::text_buffer::{LineShift, TextBuffer};
+use crate::unicode;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::ops::Range;
+
+/// A window other than the current one. The current window's cursor and
+/// scroll position are the editor's own, like the current buffer's text.
+pub struct Window {
+    pub(super) id: usize,
+    /// The number of the buffer it shows.
+    pub(super) buffer: usize,
+    pub(super) cursor_x: usize,
+    pub(super) cursor_y: usize,
+    pub(super) scroll_offset_y: usize,
+    pub(super) scroll_offset_x: usize,
+    pub(super) alternate_buffer: Option<usize>,
+}
+
+/// What a window shows, for drawing it.
+pub(super) struct View<'a> {
+    pub(super) buffer: &'a TextBuffer,
+    pub(super) hex: Option<&'a HexBuffer>,
+    pub(super) filename: Option<&'a str>,
+    pub(super) readonly: bool,
+    pub(super) dirty: bool,
+    pub(super) cursor_x: usize,
+    pub(super) cursor_y: usize,
+    pub(super) scroll_offset_y: usize,
+    pub(super) scroll_offset_x: usize,
+}
+
+/// The top line to show in a window `height` rows tall so that `cursor_y`
+/// is in view, moving no further from `scroll_offset_y` than it has to.
+fn scroll_to(cursor_y: usize, scroll_offset_y: usize, height: u16) -> usize {
+    scroll_offset_y.clamp((cursor_y + 1).saturating_sub(height.max(1) as usize), cursor_y)
+}
+
+/// The first display column to show in a window `width` columns wide so
+/// that the cursor's columns, `cursor`, are in view, moving no further from
+/// `scroll_offset_x` than it has to.
+pub(super) fn scroll_to_col(cursor: Range<usize>, scroll_offset_x: usize, width: u16) -> usize {
+    scroll_offset_x.clamp(cursor.end.saturating_sub(width.max(1) as usize).min(cursor.start), cursor.start)
+}
+
+impl Editor {
+    /// Where each window goes in `area`, in order from the top left.
+    pub(super) fn window_rects(&self, area: Rect) -> Vec<(usize, Rect)> {
+        self.layout.arrange(area)
+    }
+
+    /// Rows of text a window `rect` shows, leaving out its status line.
+    /// Only split windows have one.
+    pub(super) fn text_rows(&self, rect: Rect) -> u16 {
+        let status_line = !self.windows.is_empty() as u16;
+        rect.height.saturating_sub(status_line).max(1)
+    }
+
+    pub(super) fn current_rect(&self) -> Rect {
+        let rects = self.window_rects(self.text_area);
+        rects.iter().find(|(id, _)| *id == self.window_id).map_or(self.text_area, |&(_, rect)| rect)
+    }
+
+    /// Rows of text the current window shows.
+    pub(super) fn window_height(&self) -> u16 {
+        self.text_rows(self.current_rect())
+    }
+
+    /// What window `id` shows.
+    pub(super) fn view(&self, id: usize) -> View<'_> {
+        let current = View {
+            buffer: &self.buffer,
+            hex: self.hex.as_ref(),
+            filename: self.filename.as_deref(),
+            readonly: self.readonly,
+            dirty: self.dirty,
+            cursor_x: self.cursor_x,
+            cursor_y: self.cursor_y,
+            scroll_offset_y: self.scroll_offset_y,
+            scroll_offset_x: self.scroll_offset_x,
+        };
+        let Some(window) = self.windows.iter().find(|window| window.id == id) else {
+            return current;
+        };
+        let view = match self.buffers.iter().find(|state| state.number == window.buffer) {
+            Some(state) => View {
+                buffer: &state.buffer,
+                hex: state.hex.as_ref(),
+                filename: state.filename.as_deref(),
+                readonly: state.readonly,
+                dirty: state.dirty,
+                ..current
+            },
+            None => current,
+        };
+        // Edits made from another window can leave this one's cursor past
+        // the end.
+        let cursor_y = window.cursor_y.min(view.buffer.len_lines() - 1);
+        View {
+            cursor_x: window.cursor_x.min(view.buffer.line_len(cursor_y)),
+            cursor_y,
+            scroll_offset_y: window.scroll_offset_y.min(cursor_y),
+            scroll_offset_x: window.scroll_offset_x,
+            ..view
+        }
+    }
+
+    /// Fits the windows to a terminal of `width` by `height`.
+    pub fn resize(&mut self, width: u16, height: u16) {
+        let mode_bar_height = self.config.mode_bar.height.unwrap_or(2);
+        self.text_area = Rect { x: 0, y: 0, width, height: height.saturating_sub(mode_bar_height) };
+        self.relayout();
+    }
+
+    /// Fits the layout to the text area after it or the windows in it have
+    /// changed, and scrolls each window to keep its cursor in view.
+    fn relayout(&mut self) {
+        self.layout.fit(self.text_area.width, self.text_area.height);
+        for (id, rect) in self.window_rects(self.text_area) {
+            let rows = self.text_rows(rect);
+            if id == self.window_id {
+                self.adjust_scroll(rows);
+                continue;
+            }
+            let view = self.view(id);
+            let cursor = unicode::display_span(&view.buffer.line(view.cursor_y), view.cursor_x, self.tabstop());
+            if let Some(window) = self.windows.iter_mut().find(|window| window.id == id) {
+                window.scroll_offset_y = scroll_to(window.cursor_y, window.scroll_offset_y, rows);
+                window.scroll_offset_x = scroll_to_col(cursor, window.scroll_offset_x, rect.width);
+            }
+        }
+    }
+
+    /// Keeps the cursors of other windows onto the current buffer on their
+    /// text, like marks.
+    pub(super) fn shift_windows(&mut self, shift: LineShift) {
+        for window in self.windows.iter_mut().filter(|window| window.buffer == self.buffer_number) {
+            window.cursor_y = shift.apply(window.cursor_y).unwrap_or(shift.at);
+            window.scroll_offset_y = shift.apply(window.scroll_offset_y).unwrap_or(shift.at);
+        }
+    }
+
+    /// Whether buffer `number` is in a window other than the current one.
+    pub(super) fn in_other_window(&self, number: usize) -> bool {
+        self.windows.iter().any(|window| window.buffer == number)
+    }
+
+    /// Puts the current window's cursor and scroll position away, for
+    /// going to another.
+    fn stash_window(&mut self) {
+        self.windows.push(Window {
+            id: self.window_id,
+            buffer: self.buffer_number,
+            cursor_x: self.cursor_x,
+            cursor_y: self.cursor_y,
+            scroll_offset_y: self.scroll_offset_y,
+            scroll_offset_x: self.scroll_offset_x,
+            alternate_buffer: self.alternate_buffer,
+        });
+    }
+
+    /// Makes window `id` the current one, in place of one already put away
+    /// or closed.
+    fn load_window(&mut self, id: usize) {
+        let Some(at) = self.windows.iter().position(|window| window.id == id) else {
+            return;
+        };
+        let window = self.windows.remove(at);
+        self.window_id = id;
+        if window.buffer != self.buffer_number {
+            self.switch_to_buffer(window.buffer, self.window_height());
+        }
+        self.alternate_buffer = window.alternate_buffer;
+        self.cursor_y = window.cursor_y.min(self.buffer.len_lines() - 1);
+        self.cursor_x = window.cursor_x;
+        self.scroll_offset_y = window.scroll_offset_y;
+        self.scroll_offset_x = window.scroll_offset_x;
+        self.adjust_cursor_to_line_end();
+        self.adjust_scroll(self.window_height());
+    }
+
+    fn enter_window(&mut self, id: usize) {
+        if id != self.window_id {
+            self.stash_window();
+            self.load_window(id);
+        }
+    }
+
+    /// Splits the current window in two, the new one above it or to its left
+    /// if `vertical`, and goes to the new one. Both show the current buffer
+    /// until the new one is given another.
+    fn split_window(&mut self, vertical: bool) -> bool {
+        let id = self.next_window_id;
+        if !self.layout.split(self.window_id, id, vertical, self.text_area) {
+            self.message = "Not enough room".to_string();
+            self.fail();
+            return false;
+        }
+        self.next_window_id += 1;
+        self.stash_window();
+        self.window_id = id;
+        self.relayout();
+        true
+    }
+
+    /// Closes window `id`. Closing the current one goes to the window that
+    /// gets its space.
+    fn close_window(&mut self, id: usize) {
+        let Some(next) = self.layout.close(id) else {
+            self.message = "Cannot close last window".to_string();
+            self.fail();
+            return;
+        };
+        if id == self.window_id {
+            self.load_window(next);
+        } else {
+            self.windows.retain(|window| window.id != id);
+        }
+        self.relayout();
+    }
+
+    /// Closes the other windows showing buffer `number`, for when it's
+    /// deleted.
+    pub(super) fn close_windows_showing(&mut self, number: usize) {
+        let ids: Vec<usize> = self.windows.iter().filter(|window| window.buffer == number).map(|window| window.id).collect();
+        for id in ids {
+            self.close_window(id);
+        }
+    }
+
+    /// `:q` and Ctrl-W q: closes the current window, or quits if it's the
+    /// last one and no changes would be lost. Returns false to quit.
+    pub(super) fn quit_window(&mut self, force: bool) -> bool {
+        if self.windows.is_empty() {
+            return !self.can_quit(force);
+        }
+        self.close_window(self.window_id);
+        true
+    }
+
+    fn only_window(&mut self) {
+        self.layout = Layout::Window(self.window_id);
+        self.windows.clear();
+        self.relayout();
+    }
+
+    /// Ctrl-W h, j, k and l: goes to the window next to the current one on
+    /// the left, below, above or on the right, level with the cursor.
+    fn goto_window_towards(&mut self, direction: char) -> bool {
+        let rects = self.window_rects(self.text_area);
+        let rect = self.current_rect();
+        let (cursor_x, cursor_y) = self.cursor_screen_pos(rect);
+        let x = cursor_x.min(rect.x + rect.width.saturating_sub(1));
+        let target = match direction {
+            // Past the separator between them.
+            'h' => rect.x.checked_sub(2).map(|left| (left, cursor_y)),
+            'l' => Some((rect.x + rect.width + 1, cursor_y)),
+            'k' => rect.y.checked_sub(1).map(|above| (x, above)),
+            _ => Some((x, rect.y + rect.height)),
+        };
+        let found = target.and_then(|(x, y)| rects.iter().find(|(_, rect)| rect.contains(x, y)));
+        match found {
+            Some(&(id, _)) => {
+                self.enter_window(id);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The key after Ctrl-W, which does something with the windows. A count
+    /// can be typed before Ctrl-W or after it. Returns false to quit.
+    pub(super) fn handle_window_key(&mut self, event: KeyEvent) -> bool {
+        if self.push_count_key(&event) {
+            self.pending_key = Some(PendingKey::Window);
+            return true;
+        }
+        let count = self.take_count();
+        let key = match event.code {
+            // Ctrl-W Ctrl-C cancels, as Ctrl-W Esc does.
+            KeyCode::Char('c') if event.modifiers.contains(KeyModifiers::CONTROL) => return true,
+            // The letters work with Ctrl held down too.
+            KeyCode::Char(c) => c,
+            KeyCode::Left => 'h',
+            KeyCode::Down => 'j',
+            KeyCode::Up => 'k',
+            KeyCode::Right => 'l',
+            _ => return true,
+        };
+        let ok = match key {
+            'h' | 'j' | 'k' | 'l' => (0..count.unwrap_or(1)).all(|_| self.goto_window_towards(key)),
+            'w' | 'W' => {
+                let ids = self.layout.windows();
+                let at = ids.iter().position(|&id| id == self.window_id).unwrap_or(0);
+                let to = match count {
+                    Some(count) => (count - 1).min(ids.len() - 1),
+                    None if key == 'w' => (at + 1) % ids.len(),
+                    None => (at + ids.len() - 1) % ids.len(),
+                };
+                self.enter_window(ids[to]);
+                true
+            }
+            's' | 'S' => self.split_window(false),
+            'v' => self.split_window(true),
+            'n' => {
+                let split = self.split_window(false);
+                if split {
+                    self.new_buffer(self.window_height());
+                }
+                split
+            }
+            'c' => {
+                self.close_window(self.window_id);
+                true
+            }
+            'q' => return self.quit_window(false),
+            'o' => {
+                self.only_window();
+                true
+            }
+            '=' => {
+                self.layout.equalize();
+                self.relayout();
+                true
+            }
+            '+' | '-' | '<' | '>' => {
+                let delta = count.unwrap_or(1) as i32;
+                let delta = if matches!(key, '-' | '<') { -delta } else { delta };
+                self.layout.resize(self.window_id, matches!(key, '<' | '>'), delta);
+                self.relayout();
+                true
+            }
+            _ => false,
+        };
+        if !ok {
+            self.fail();
+        }
+        true
+    }
+
+    /// `:sp[lit] [file]` and `:vs[plit] [file]`: splits the window, and
+    /// edits `file` in the new one if one is given.
+    pub(super) fn ex_split(&mut self, command: &CommandLine, _text_area_height: u16) -> bool {
+        let path = command.args.trim();
+        if self.split_window(command.name.starts_with('v')) && !path.is_empty() {
+            self.edit_file(path, self.window_height());
+        }
+        true
+    }
+
+    /// `:new` and `:vne[w]`: splits the window and starts a new, empty
+    /// buffer in the new one.
+    pub(super) fn ex_new(&mut self, command: &CommandLine, _text_area_height: u16) -> bool {
+        if self.split_window(command.name.starts_with('v')) {
+            self.new_buffer(self.window_height());
+        }
+        true
+    }
+
+    /// `:clo[se]`: closes the current window, unless it's the last one.
+    pub(super) fn ex_close(&mut self, _command: &CommandLine, _text_area_height: u16) -> bool {
+        self.close_window(self.window_id);
+        true
+    }
+
+    /// `:on[ly]`: closes every window but the current one. The buffers they
+    /// showed stay in the list.
+    pub(super) fn ex_only(&mut self, _command: &CommandLine, _text_area_height: u16) -> bool {
+        self.only_window();
+        true
+    }
+
+    /// `:res[ize] [+-]N`: makes the current window `N` rows tall, or that
+    /// many taller or shorter.
+    pub(super) fn ex_resize(&mut self, command: &CommandLine, _text_area_height: u16) -> bool {
+        let arg = command.args.trim();
+        let height = self.window_height() as i32;
+        let delta = match (arg.strip_prefix('+'), arg.strip_prefix('-'), arg.parse::<i32>()) {
+            (Some(more), _, _) => more.parse::<i32>().ok(),
+            (_, Some(less), _) => less.parse::<i32>().ok().map(|less| -less),
+            (_, _, Ok(rows)) => Some(rows - height),
+            // With no argument it's as tall as it can be.
+            _ if arg.is_empty() => Some(i32::from(u16::MAX)),
+            _ => None,
+        };
+        match delta {
+            Some(delta) => {
+                self.layout.resize(self.window_id, false, delta);
+                self.relayout();
+            }
+            None => self.message = format!("Invalid argument: {}", arg),
+        }
+        true
+    }
+}
@@ -0,0 +1,102 @@
+use chardetng::EncodingDetector;
+use encoding_rs::{Encoding, SHIFT_JIS, UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1252};
+
+/// Character encoding a buffer is read from and written back in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileEncoding(&'static Encoding);
+
+impl Default for FileEncoding {
+    fn default() -> Self {
+        FileEncoding(UTF_8)
+    }
+}
+
+impl FileEncoding {
+    /// Guesses the encoding of `bytes`: BOMs first, then UTF-16 without a
+    /// BOM, then UTF-8, and finally a statistical guess for legacy encodings.
+    pub fn detect(bytes: &[u8]) -> Self {
+        if let Some((encoding, _)) = Encoding::for_bom(bytes) {
+            return FileEncoding(encoding);
+        }
+        if let Some(encoding) = Self::detect_utf16(bytes) {
+            return FileEncoding(encoding);
+        }
+        if std::str::from_utf8(bytes).is_ok() {
+            return FileEncoding(UTF_8);
+        }
+        let mut detector = EncodingDetector::new();
+        detector.feed(bytes, true);
+        FileEncoding(detector.guess(None, false))
+    }
+
+    /// BOM-less UTF-16 shows up as a NUL in every other byte for mostly
+    /// ASCII text; that's the only case worth guessing at.
+    fn detect_utf16(bytes: &[u8]) -> Option<&'static Encoding> {
+        if bytes.len() < 2 || !bytes.len().is_multiple_of(2) {
+            return None;
+        }
+        let pairs = bytes.len() / 2;
+        let even_nuls = bytes.iter().step_by(2).filter(|&&b| b == 0).count();
+        let odd_nuls = bytes.iter().skip(1).step_by(2).filter(|&&b| b == 0).count();
+        if odd_nuls * 2 > pairs && even_nuls == 0 {
+            Some(UTF_16LE)
+        } else if even_nuls * 2 > pairs && odd_nuls == 0 {
+            Some(UTF_16BE)
+        } else {
+            None
+        }
+    }
+
+    /// Looks up an encoding by the name used with `:set fileencoding=`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        let encoding = match name.to_ascii_lowercase().as_str() {
+            "utf8" => UTF_8,
+            "utf-16" | "utf16" | "ucs-2le" => UTF_16LE,
+            "ucs-2" => UTF_16BE,
+            "cp932" => SHIFT_JIS,
+            label => Encoding::for_label(label.as_bytes())?,
+        };
+        (encoding != encoding_rs::REPLACEMENT).then_some(FileEncoding(encoding))
+    }
+
+    pub fn name(&self) -> String {
+        match self.0 {
+            enc if enc == WINDOWS_1252 => "latin1".to_string(),
+            enc if enc == SHIFT_JIS => "sjis".to_string(),
+            enc => enc.name().to_ascii_lowercase(),
+        }
+    }
+
+    /// Whether the encoding can hold any Unicode text (and so a BOM).
+    pub fn is_unicode(&self) -> bool {
+        self.0 == UTF_8 || self.0 == UTF_16LE || self.0 == UTF_16BE
+    }
+
+    /// Decodes `bytes`, keeping any BOM as a leading U+FEFF. Returns `None`
+    /// if the bytes aren't valid in this encoding.
+    pub fn decode(&self, bytes: &[u8]) -> Option<String> {
+        self.0
+            .decode_without_bom_handling_and_without_replacement(bytes)
+            .map(|text| text.into_owned())
+    }
+
+    /// Like `decode`, but replaces invalid sequences with U+FFFD.
+    pub fn decode_lossy(&self, bytes: &[u8]) -> String {
+        self.0.decode_without_bom_handling(bytes).0.into_owned()
+    }
+
+    /// Encodes `text`, or returns `None` if it has characters this encoding
+    /// can't represent.
+    pub fn encode(&self, text: &str) -> Option<Vec<u8>> {
+        // encoding_rs only encodes to ASCII-compatible encodings, so UTF-16 is
+        // done by hand.
+        if self.0 == UTF_16LE {
+            return Some(text.encode_utf16().flat_map(u16::to_le_bytes).collect());
+        }
+        if self.0 == UTF_16BE {
+            return Some(text.encode_utf16().flat_map(u16::to_be_bytes).collect());
+        }
+        let (bytes, _, had_errors) = self.0.encode(text);
+        (!had_errors).then(|| bytes.into_owned())
+    }
+}
@@ -0,0 +1,231 @@
+use crate::search;
+
+/// Lines a command-line command works on, both ends included. Lines are
+/// counted from 0 here and from 1 on the command line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineRange {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl LineRange {
+    pub fn line(line: usize) -> Self {
+        LineRange { start: line, end: line }
+    }
+}
+
+/// What addresses on the command line are worked out against.
+pub struct Addresses<'a> {
+    /// The cursor's line.
+    pub current: usize,
+    /// The buffer's last line.
+    pub last: usize,
+    /// Line of mark `name`, if it's set.
+    pub mark: &'a dyn Fn(char) -> Option<usize>,
+    /// Line of the first match of a pattern after the given line, or before
+    /// it when searching backward.
+    pub search: &'a dyn Fn(&str, usize, bool) -> Result<usize, String>,
+}
+
+/// A command line split into its parts, like `:3,5d a 2` or `:w! foo`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct CommandLine<'a> {
+    pub range: Option<LineRange>,
+    /// The command as typed, possibly abbreviated. Empty for `:5` and the like.
+    pub name: &'a str,
+    pub bang: bool,
+    pub args: &'a str,
+}
+
+/// Splits `input` into range, command name, `!` and arguments.
+pub fn parse<'a>(input: &'a str, addresses: &Addresses) -> Result<CommandLine<'a>, String> {
+    let (range, rest) = parse_range(input.trim_start_matches([' ', ':']), addresses)?;
+    let rest = rest.trim_start();
+    // `:>>>` shifts three times; other commands are a run of letters.
+    let name_len = match rest.chars().next() {
+        Some(c @ ('>' | '<')) => rest.find(|next| next != c).unwrap_or(rest.len()),
+        Some('&') => 1,
+        _ => rest.find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(rest.len()),
+    };
+    let (name, rest) = rest.split_at(name_len);
+    let (bang, rest) = match rest.strip_prefix('!') {
+        Some(rest) => (true, rest),
+        None => (false, rest),
+    };
+    Ok(CommandLine { range, name, bang, args: rest.trim_start() })
+}
+
+/// Parses the line range in front of a command, like the `%` in `:%s` or the
+/// `.,$` in `:.,$d`. Returns the range, if one was given, and the rest of the
+/// command.
+pub fn parse_range<'a>(input: &'a str, addresses: &Addresses) -> Result<(Option<LineRange>, &'a str), String> {
+    if let Some(rest) = input.strip_prefix('%') {
+        return Ok((Some(LineRange { start: 0, end: addresses.last }), rest));
+    }
+    let (start, rest) = parse_address(input, addresses)?;
+    let Some(separator) = rest.chars().next().filter(|c| matches!(c, ',' | ';')) else {
+        return Ok((start.map(|line| to_range(line, line)), rest));
+    };
+    let start = start.unwrap_or(addresses.current + 1);
+    // After `;` the second address counts from the first rather than the cursor.
+    let second = Addresses {
+        current: if separator == ';' { start.max(1) - 1 } else { addresses.current },
+        ..*addresses
+    };
+    let (end, rest) = parse_address(&rest[1..], &second)?;
+    let end = end.unwrap_or(addresses.current + 1);
+    Ok((Some(to_range(start, end)), rest))
+}
+
+/// Turns two line numbers as typed into a range. Vim asks before swapping a
+/// backwards range; there's nothing to lose by just doing it.
+fn to_range(start: usize, end: usize) -> LineRange {
+    let (start, end) = (start.min(end), start.max(end));
+    LineRange { start: start.max(1) - 1, end: end.max(1) - 1 }
+}
+
+/// Parses one address: a line number, `.` for the cursor's line, `$` for the
+/// last one, `'x` for a mark or `/pattern/` and `?pattern?` for the next
+/// and previous matching lines, followed by any number of `+n` and `-n`.
+/// Returns the line number counting from 1, where 0 means above the first
+/// line, and the rest of `input`.
+pub fn parse_address<'a>(input: &'a str, addresses: &Addresses) -> Result<(Option<usize>, &'a str), String> {
+    let current = addresses.current as i64 + 1;
+    let mut chars = input.chars();
+    let (base, mut rest) = match chars.next() {
+        Some('.') => (current, chars.as_str()),
+        Some('$') => (addresses.last as i64 + 1, chars.as_str()),
+        Some(c) if c.is_ascii_digit() => split_number(input),
+        Some('\'') => {
+            let name = chars.next().ok_or("Missing mark name")?;
+            let line = (addresses.mark)(name).ok_or("Mark not set")?;
+            (line as i64 + 1, chars.as_str())
+        }
+        Some(delimiter @ ('/' | '?')) => {
+            let (pattern, rest) = search::split_pattern(chars.as_str(), delimiter);
+            let line = (addresses.search)(&pattern, addresses.current, delimiter == '?')?;
+            let rest = rest.unwrap_or("");
+            (line as i64 + 1, rest)
+        }
+        Some('+' | '-') => (current, input),
+        _ => return Ok((None, input)),
+    };
+    let mut line = base;
+    while let Some(sign) = rest.chars().next().filter(|c| matches!(c, '+' | '-')) {
+        let (offset, after) = split_number(&rest[1..]);
+        let offset = if after.len() == rest.len() - 1 { 1 } else { offset };
+        line += if sign == '+' { offset } else { -offset };
+        rest = after;
+    }
+    if line < 0 || line > addresses.last as i64 + 1 {
+        return Err("Invalid range".to_string());
+    }
+    Ok((Some(line as usize), rest))
+}
+
+fn split_number(input: &str) -> (i64, &str) {
+    let end = input.find(|c: char| !c.is_ascii_digit()).unwrap_or(input.len());
+    (input[..end].parse().unwrap_or(0), &input[end..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Addresses for a ten-line buffer with the cursor on line 3 (4 on the
+    /// command line), mark `a` on line 6 and lines 2 and 8 matching "x".
+    fn with_addresses<R>(f: impl FnOnce(&Addresses) -> R) -> R {
+        let mark = |name| (name == 'a').then_some(6);
+        let search = |pattern: &str, from: usize, backward: bool| {
+            let matches = [2, 8];
+            let found = if pattern != "x" {
+                None
+            } else if backward {
+                matches.iter().rev().find(|&&line| line < from).or(matches.last())
+            } else {
+                matches.iter().find(|&&line| line > from).or(matches.first())
+            };
+            found.copied().ok_or_else(|| format!("Pattern not found: {}", pattern))
+        };
+        f(&Addresses { current: 3, last: 9, mark: &mark, search: &search })
+    }
+
+    fn range(input: &str) -> Result<(Option<LineRange>, &str), String> {
+        with_addresses(|addresses| parse_range(input, addresses))
+    }
+
+    fn lines(start: usize, end: usize) -> Option<LineRange> {
+        Some(LineRange { start, end })
+    }
+
+    #[test]
+    fn parses_command_parts() {
+        let command = with_addresses(|addresses| parse(":3,5d a 2", addresses)).unwrap();
+        assert_eq!(command, CommandLine { range: lines(2, 4), name: "d", bang: false, args: "a 2" });
+        let command = with_addresses(|addresses| parse("w! foo", addresses)).unwrap();
+        assert_eq!(command, CommandLine { range: None, name: "w", bang: true, args: "foo" });
+        let command = with_addresses(|addresses| parse("%s/a/b/g", addresses)).unwrap();
+        assert_eq!((command.range, command.name, command.args), (lines(0, 9), "s", "/a/b/g"));
+        let command = with_addresses(|addresses| parse(">>>", addresses)).unwrap();
+        assert_eq!((command.name, command.args), (">>>", ""));
+        let command = with_addresses(|addresses| parse("&&", addresses)).unwrap();
+        assert_eq!((command.name, command.args), ("&", "&"));
+        let command = with_addresses(|addresses| parse(":5", addresses)).unwrap();
+        assert_eq!((command.range, command.name), (lines(4, 4), ""));
+    }
+
+    #[test]
+    fn parses_single_addresses() {
+        assert_eq!(range("."), Ok((lines(3, 3), "")));
+        assert_eq!(range("$"), Ok((lines(9, 9), "")));
+        assert_eq!(range("7d"), Ok((lines(6, 6), "d")));
+        assert_eq!(range("'a"), Ok((lines(6, 6), "")));
+        assert_eq!(range("/x/d"), Ok((lines(8, 8), "d")));
+        assert_eq!(range("?x?"), Ok((lines(2, 2), "")));
+        assert_eq!(range("/x"), Ok((lines(8, 8), "")));
+        assert_eq!(range("d"), Ok((None, "d")));
+    }
+
+    #[test]
+    fn applies_offsets() {
+        assert_eq!(range("+2"), Ok((lines(5, 5), "")));
+        assert_eq!(range("-"), Ok((lines(2, 2), "")));
+        assert_eq!(range("+++"), Ok((lines(6, 6), "")));
+        assert_eq!(range(".+1-3"), Ok((lines(1, 1), "")));
+        assert_eq!(range("$-1"), Ok((lines(8, 8), "")));
+        assert_eq!(range("'a+1"), Ok((lines(7, 7), "")));
+        assert_eq!(range("/x/-1"), Ok((lines(7, 7), "")));
+    }
+
+    #[test]
+    fn parses_ranges() {
+        assert_eq!(range("%d"), Ok((lines(0, 9), "d")));
+        assert_eq!(range("1,$"), Ok((lines(0, 9), "")));
+        assert_eq!(range(".,+2"), Ok((lines(3, 5), "")));
+        assert_eq!(range(",5"), Ok((lines(3, 4), "")));
+        assert_eq!(range("2,"), Ok((lines(1, 3), "")));
+        // Backwards ranges are turned round.
+        assert_eq!(range("5,2"), Ok((lines(1, 4), "")));
+        // After `;` the second address counts from the first.
+        assert_eq!(range("7;+1"), Ok((lines(6, 7), "")));
+        assert_eq!(range("7,+1"), Ok((lines(4, 6), "")));
+        assert_eq!(range("/x/;/x/"), Ok((lines(2, 8), "")));
+        assert_eq!(range("1;/x/"), Ok((lines(0, 2), "")));
+    }
+
+    #[test]
+    fn line_zero_is_the_first_line() {
+        assert_eq!(range("0"), Ok((lines(0, 0), "")));
+        assert_eq!(with_addresses(|addresses| parse_address("0", addresses)), Ok((Some(0), "")));
+        assert_eq!(with_addresses(|addresses| parse_address("$+1", addresses)), Err("Invalid range".to_string()));
+    }
+
+    #[test]
+    fn rejects_bad_addresses() {
+        assert_eq!(range("11"), Err("Invalid range".to_string()));
+        assert_eq!(range("1-2"), Err("Invalid range".to_string()));
+        assert_eq!(range("'b"), Err("Mark not set".to_string()));
+        assert_eq!(range("'"), Err("Missing mark name".to_string()));
+        assert_eq!(range("/y/"), Err("Pattern not found: y".to_string()));
+    }
+}
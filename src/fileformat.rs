@@ -0,0 +1,110 @@
+/// Line terminator a file was written with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineEnding {
+    Lf,
+    Crlf,
+    Cr,
+}
+
+impl LineEnding {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::Crlf => "\r\n",
+            LineEnding::Cr => "\r",
+        }
+    }
+
+    /// Vim's `fileformat` name for this line ending.
+    pub fn name(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "unix",
+            LineEnding::Crlf => "dos",
+            LineEnding::Cr => "mac",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "unix" => Some(LineEnding::Lf),
+            "dos" => Some(LineEnding::Crlf),
+            "mac" => Some(LineEnding::Cr),
+            _ => None,
+        }
+    }
+}
+
+/// On-disk layout of a buffer's text that the editor keeps out of the
+/// buffer itself, so a file can be written back exactly as it was read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileFormat {
+    pub line_ending: LineEnding,
+    pub final_newline: bool,
+    pub bom: bool,
+}
+
+impl Default for FileFormat {
+    fn default() -> Self {
+        FileFormat {
+            line_ending: LineEnding::Lf,
+            final_newline: true,
+            bom: false,
+        }
+    }
+}
+
+impl FileFormat {
+    /// Works out the format of `content` and returns it together with the
+    /// text normalized to the buffer's form: `\n` separators, no BOM and no
+    /// newline after the last line.
+    pub fn detect(content: &str) -> (FileFormat, String) {
+        let (bom, content) = match content.strip_prefix('\u{feff}') {
+            Some(rest) => (true, rest),
+            None => (false, content),
+        };
+
+        let line_ending = if content.contains('\n') {
+            // With mixed endings the one most lines use wins, as with Vim's
+            // 'fileformats'; the lines that end otherwise get it when written.
+            let lf_count = content.matches('\n').count();
+            let crlf_count = content.matches("\r\n").count();
+            if crlf_count * 2 > lf_count {
+                LineEnding::Crlf
+            } else {
+                LineEnding::Lf
+            }
+        } else if content.contains('\r') {
+            LineEnding::Cr
+        } else {
+            LineEnding::Lf
+        };
+
+        let mut text = match line_ending {
+            LineEnding::Lf => content.to_string(),
+            LineEnding::Crlf => content.replace("\r\n", "\n"),
+            LineEnding::Cr => content.replace('\r', "\n"),
+        };
+        let final_newline = text.ends_with('\n');
+        if final_newline {
+            text.pop();
+        }
+
+        (FileFormat { line_ending, final_newline, bom }, text)
+    }
+
+    /// Turns buffer text back into file contents in this format.
+    pub fn apply(&self, text: &str) -> String {
+        let mut content = String::with_capacity(text.len() + 4);
+        if self.bom {
+            content.push('\u{feff}');
+        }
+        match self.line_ending {
+            LineEnding::Lf => content.push_str(text),
+            ending => content.push_str(&text.replace('\n', ending.as_str())),
+        }
+        if self.final_newline {
+            content.push_str(self.line_ending.as_str());
+        }
+        content
+    }
+}
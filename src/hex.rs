@@ -0,0 +1,224 @@
+/// Number of bytes shown on each row of the hex view.
+pub const BYTES_PER_ROW: usize = 16;
+
+use crate::undo::{Edit, UndoTree};
+
+/// Width of the `00000000: ` offset column.
+const OFFSET_WIDTH: usize = 10;
+
+/// A byte-oriented view of a file for editing binary data. Rows show the
+/// offset, the bytes in hex and the bytes as ASCII.
+pub struct HexBuffer {
+    bytes: Vec<u8>,
+    /// Byte under the cursor. In Insert mode it can be one past the last
+    /// byte, where typing adds a new one.
+    cursor: usize,
+    low_nibble: bool,
+    scroll_row: usize,
+    /// Undo history of the byte edits, kept apart from the text's so the two
+    /// kinds of edit never mix.
+    undo: UndoTree,
+}
+
+impl HexBuffer {
+    pub fn new(bytes: Vec<u8>) -> Self {
+        HexBuffer {
+            bytes,
+            cursor: 0,
+            low_nibble: false,
+            scroll_row: 0,
+            undo: UndoTree::new(true),
+        }
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn row_count(&self) -> usize {
+        self.bytes.len().max(self.cursor + 1).div_ceil(BYTES_PER_ROW)
+    }
+
+    pub fn undo(&mut self) -> &mut UndoTree {
+        &mut self.undo
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    pub fn cursor_row(&self) -> usize {
+        self.cursor / BYTES_PER_ROW
+    }
+
+    pub fn scroll_row(&self) -> usize {
+        self.scroll_row
+    }
+
+    fn last_index(&self) -> usize {
+        self.bytes.len().saturating_sub(1)
+    }
+
+    pub fn set_cursor(&mut self, cursor: usize) {
+        self.cursor = cursor.min(self.last_index());
+        self.low_nibble = false;
+    }
+
+    pub fn move_left(&mut self) {
+        self.set_cursor(self.cursor.saturating_sub(1));
+    }
+
+    pub fn move_right(&mut self) {
+        self.set_cursor(self.cursor + 1);
+    }
+
+    pub fn move_up(&mut self, rows: usize) {
+        let rows = rows.min(self.cursor_row());
+        self.set_cursor(self.cursor - rows * BYTES_PER_ROW);
+    }
+
+    pub fn move_down(&mut self, rows: usize) {
+        let rows = rows.min(self.row_count() - 1 - self.cursor_row());
+        self.set_cursor(self.cursor + rows * BYTES_PER_ROW);
+    }
+
+    pub fn move_row_start(&mut self) {
+        self.set_cursor(self.cursor_row() * BYTES_PER_ROW);
+    }
+
+    pub fn move_row_end(&mut self) {
+        self.set_cursor(self.cursor_row() * BYTES_PER_ROW + BYTES_PER_ROW - 1);
+    }
+
+    pub fn move_to_start(&mut self) {
+        self.set_cursor(0);
+    }
+
+    pub fn move_to_end(&mut self) {
+        self.set_cursor(self.last_index());
+    }
+
+    /// The edit that writes the hex digit `c` into the nibble under the
+    /// cursor, adding a byte if the cursor is past the end. `None` if `c`
+    /// isn't a hex digit.
+    pub fn nibble_edit(&self, c: char) -> Option<Edit> {
+        let digit = c.to_digit(16)? as u8;
+        let old = self.bytes.get(self.cursor).copied();
+        let byte = old.unwrap_or(0);
+        let new = if self.low_nibble { (byte & 0xf0) | digit } else { (byte & 0x0f) | digit << 4 };
+        Some(Edit::Byte { at: self.cursor, old, new: Some(new) })
+    }
+
+    /// Moves on to the next nibble after one is written, going past the
+    /// last byte so typing can carry on adding bytes.
+    pub fn advance_nibble(&mut self) {
+        if self.low_nibble {
+            self.cursor = (self.cursor + 1).min(self.bytes.len());
+            self.low_nibble = false;
+        } else {
+            self.low_nibble = true;
+        }
+    }
+
+    /// Puts `byte` at `at`, which may be the end to add a byte, or takes the
+    /// byte there away if `byte` is `None`.
+    pub fn set_byte(&mut self, at: usize, byte: Option<u8>) {
+        match byte {
+            Some(byte) if at < self.bytes.len() => self.bytes[at] = byte,
+            Some(byte) => self.bytes.push(byte),
+            None if at < self.bytes.len() => {
+                self.bytes.remove(at);
+            }
+            None => {}
+        }
+    }
+
+    /// Brings the cursor back onto a byte when leaving Insert mode.
+    pub fn clamp_cursor(&mut self) {
+        self.set_cursor(self.cursor);
+    }
+
+    pub fn adjust_scroll(&mut self, text_area_height: u16) {
+        let height = (text_area_height as usize).max(1);
+        let row = self.cursor_row();
+        if row < self.scroll_row {
+            self.scroll_row = row;
+        }
+        if row >= self.scroll_row + height {
+            self.scroll_row = row + 1 - height;
+        }
+    }
+
+    /// Renders row `row` as `offset: hex bytes  |ascii|`.
+    pub fn render_row(&self, row: usize) -> String {
+        let start = row * BYTES_PER_ROW;
+        let chunk = &self.bytes[start.min(self.bytes.len())..(start + BYTES_PER_ROW).min(self.bytes.len())];
+
+        let mut line = format!("{:08x}: ", start);
+        for i in 0..BYTES_PER_ROW {
+            if i == BYTES_PER_ROW / 2 {
+                line.push(' ');
+            }
+            match chunk.get(i) {
+                Some(byte) => line.push_str(&format!("{:02x} ", byte)),
+                None => line.push_str("   "),
+            }
+        }
+        line.push(' ');
+        line.push('|');
+        line.extend(chunk.iter().map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' }));
+        line.push('|');
+        line
+    }
+
+    /// Screen column of the nibble under the cursor within its row.
+    pub fn cursor_col(&self) -> usize {
+        let i = self.cursor % BYTES_PER_ROW;
+        let gap = if i >= BYTES_PER_ROW / 2 { 1 } else { 0 };
+        OFFSET_WIDTH + i * 3 + gap + self.low_nibble as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn type_nibbles(hex: &mut HexBuffer, digits: &str) {
+        for c in digits.chars() {
+            let edit = hex.nibble_edit(c).unwrap();
+            let Edit::Byte { at, new, .. } = edit else { unreachable!() };
+            hex.set_byte(at, new);
+            hex.advance_nibble();
+        }
+    }
+
+    #[test]
+    fn typing_past_the_end_adds_bytes() {
+        let mut hex = HexBuffer::new(Vec::new());
+        type_nibbles(&mut hex, "0aF");
+        assert_eq!(hex.bytes(), &[0x0a, 0xf0]);
+        assert_eq!(hex.cursor(), 1);
+        hex.clamp_cursor();
+        assert_eq!(hex.cursor(), 1);
+        type_nibbles(&mut hex, "12");
+        assert_eq!(hex.cursor(), 2);
+        assert_eq!(hex.row_count(), 1);
+        hex.clamp_cursor();
+        assert_eq!(hex.cursor(), 1);
+        assert!(hex.nibble_edit('g').is_none());
+    }
+
+    #[test]
+    fn set_byte_overwrites_adds_and_removes() {
+        let mut hex = HexBuffer::new(vec![0x11]);
+        // Outside Insert mode the cursor stays on a byte.
+        hex.set_cursor(1);
+        let edit = hex.nibble_edit('a').unwrap();
+        assert!(matches!(edit, Edit::Byte { at: 0, old: Some(0x11), new: Some(0xa1) }));
+        hex.set_byte(0, Some(0xa1));
+        hex.set_byte(1, Some(0x22));
+        assert_eq!(hex.bytes(), &[0xa1, 0x22]);
+        hex.set_byte(1, None);
+        assert_eq!(hex.bytes(), &[0xa1]);
+    }
+}
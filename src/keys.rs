@@ -0,0 +1,215 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+/// The key a char stands for when keys are given as plain text, as after
+/// `:normal`. Control chars, such as ones pasted in with Ctrl-R, are the
+/// control keys they come from.
+pub fn from_char(c: char) -> KeyEvent {
+    match c {
+        '\x1b' => KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE),
+        '\r' | '\n' => KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE),
+        '\t' => KeyEvent::new(KeyCode::Tab, KeyModifiers::NONE),
+        '\x08' | '\x7f' => KeyEvent::new(KeyCode::Backspace, KeyModifiers::NONE),
+        '\x01'..='\x1a' => {
+            let letter = (b'a' + c as u8 - 1) as char;
+            KeyEvent::new(KeyCode::Char(letter), KeyModifiers::CONTROL)
+        }
+        _ => KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE),
+    }
+}
+
+/// Writes `keys` the way Vim does: printable chars as themselves and the
+/// rest like `<Esc>`, `<CR>` or `<C-r>`, with `<lt>` for `<`. Keys with no
+/// name are left out.
+pub fn to_notation(keys: &[KeyEvent]) -> String {
+    keys.iter().map(key_notation).collect()
+}
+
+fn key_notation(key: &KeyEvent) -> String {
+    let name = match key.code {
+        KeyCode::Char('<') => "lt".to_string(),
+        KeyCode::Char(c) => c.to_string(),
+        KeyCode::Esc => "Esc".to_string(),
+        KeyCode::Enter => "CR".to_string(),
+        KeyCode::Backspace => "BS".to_string(),
+        KeyCode::Tab => "Tab".to_string(),
+        KeyCode::BackTab => "S-Tab".to_string(),
+        KeyCode::Delete => "Del".to_string(),
+        KeyCode::Insert => "Insert".to_string(),
+        KeyCode::Up => "Up".to_string(),
+        KeyCode::Down => "Down".to_string(),
+        KeyCode::Left => "Left".to_string(),
+        KeyCode::Right => "Right".to_string(),
+        KeyCode::Home => "Home".to_string(),
+        KeyCode::End => "End".to_string(),
+        KeyCode::PageUp => "PageUp".to_string(),
+        KeyCode::PageDown => "PageDown".to_string(),
+        KeyCode::F(n) => format!("F{}", n),
+        _ => return String::new(),
+    };
+    let mut modifiers = String::new();
+    if key.modifiers.contains(KeyModifiers::CONTROL) {
+        modifiers.push_str("C-");
+    }
+    if key.modifiers.contains(KeyModifiers::ALT) {
+        modifiers.push_str("M-");
+    }
+    // Shifted chars are just the uppercase ones.
+    if key.modifiers.contains(KeyModifiers::SHIFT) && !matches!(key.code, KeyCode::Char(_) | KeyCode::BackTab) {
+        modifiers.push_str("S-");
+    }
+    match key.code {
+        KeyCode::Char(c) if c != '<' && modifiers.is_empty() => name,
+        _ => format!("<{}{}>", modifiers, name),
+    }
+}
+
+/// Reads keys written by [`to_notation`]. Names in `<>` are matched ignoring
+/// case, as in Vim; a `<` that doesn't start one is just a `<`.
+pub fn parse(text: &str) -> Vec<KeyEvent> {
+    let mut keys = Vec::new();
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        let named = rest
+            .strip_prefix('<')
+            .and_then(|after| after.find('>').map(|end| (&after[..end], &after[end + 1..])))
+            .and_then(|(name, after)| parse_name(name).map(|key| (key, after)));
+        match named {
+            Some((key, after)) => {
+                keys.push(key);
+                rest = after;
+            }
+            None => {
+                keys.push(from_char(c));
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+    keys
+}
+
+/// The key for a name like `Esc`, `C-r` or `S-Tab`.
+fn parse_name(name: &str) -> Option<KeyEvent> {
+    let mut modifiers = KeyModifiers::NONE;
+    let mut name = name;
+    while name.len() > 2 && name.as_bytes()[1] == b'-' {
+        modifiers |= match name.as_bytes()[0].to_ascii_uppercase() {
+            b'C' => KeyModifiers::CONTROL,
+            b'S' => KeyModifiers::SHIFT,
+            b'M' | b'A' => KeyModifiers::ALT,
+            _ => return None,
+        };
+        name = &name[2..];
+    }
+    let mut chars = name.chars();
+    if modifiers.is_empty() && chars.clone().count() == 1 {
+        // `<x>` is not a key name.
+        return None;
+    }
+    let code = match (chars.next(), chars.next()) {
+        (Some(c), None) => KeyCode::Char(c),
+        _ => match name.to_ascii_lowercase().as_str() {
+            "lt" => KeyCode::Char('<'),
+            "space" => KeyCode::Char(' '),
+            "bar" => KeyCode::Char('|'),
+            "bslash" => KeyCode::Char('\\'),
+            "esc" => KeyCode::Esc,
+            "cr" | "enter" | "return" | "nl" => KeyCode::Enter,
+            "bs" | "backspace" => KeyCode::Backspace,
+            "tab" => KeyCode::Tab,
+            "del" | "delete" => KeyCode::Delete,
+            "insert" => KeyCode::Insert,
+            "up" => KeyCode::Up,
+            "down" => KeyCode::Down,
+            "left" => KeyCode::Left,
+            "right" => KeyCode::Right,
+            "home" => KeyCode::Home,
+            "end" => KeyCode::End,
+            "pageup" => KeyCode::PageUp,
+            "pagedown" => KeyCode::PageDown,
+            f => KeyCode::F(f.strip_prefix('f')?.parse().ok()?),
+        },
+    };
+    let code = match code {
+        // The terminal reports Ctrl-R as `r` with Ctrl held, and Shift
+        // with a char as the uppercase char.
+        KeyCode::Char(c) if modifiers.contains(KeyModifiers::CONTROL) => KeyCode::Char(c.to_ascii_lowercase()),
+        KeyCode::Char(c) if modifiers.contains(KeyModifiers::SHIFT) => {
+            modifiers.remove(KeyModifiers::SHIFT);
+            KeyCode::Char(c.to_ascii_uppercase())
+        }
+        KeyCode::Tab if modifiers.contains(KeyModifiers::SHIFT) => {
+            modifiers.remove(KeyModifiers::SHIFT);
+            KeyCode::BackTab
+        }
+        code => code,
+    };
+    Some(KeyEvent::new(code, modifiers))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::NONE)
+    }
+
+    fn ctrl(c: char) -> KeyEvent {
+        KeyEvent::new(KeyCode::Char(c), KeyModifiers::CONTROL)
+    }
+
+    #[test]
+    fn writes_keys_in_vim_notation() {
+        let keys = [key(KeyCode::Char('i')), key(KeyCode::Char('<')), key(KeyCode::Esc), ctrl('r'), key(KeyCode::Enter)];
+        assert_eq!(to_notation(&keys), "i<lt><Esc><C-r><CR>");
+        assert_eq!(to_notation(&[key(KeyCode::F(5)), key(KeyCode::BackTab)]), "<F5><S-Tab>");
+        assert_eq!(to_notation(&[KeyEvent::new(KeyCode::Up, KeyModifiers::SHIFT | KeyModifiers::ALT)]), "<M-S-Up>");
+        // Shift with a char is already in the char.
+        assert_eq!(to_notation(&[KeyEvent::new(KeyCode::Char('A'), KeyModifiers::SHIFT)]), "A");
+        assert_eq!(to_notation(&[key(KeyCode::Null)]), "");
+    }
+
+    #[test]
+    fn reads_key_names() {
+        assert_eq!(parse("a<Esc>"), vec![key(KeyCode::Char('a')), key(KeyCode::Esc)]);
+        assert_eq!(parse("<esc><cR><lt>"), vec![key(KeyCode::Esc), key(KeyCode::Enter), key(KeyCode::Char('<'))]);
+        assert_eq!(parse("<C-R><c-w>"), vec![ctrl('r'), ctrl('w')]);
+        assert_eq!(parse("<S-a><S-Tab>"), vec![key(KeyCode::Char('A')), key(KeyCode::BackTab)]);
+        assert_eq!(parse("<A-x>"), vec![KeyEvent::new(KeyCode::Char('x'), KeyModifiers::ALT)]);
+        assert_eq!(parse("<F12><Space>"), vec![key(KeyCode::F(12)), key(KeyCode::Char(' '))]);
+    }
+
+    #[test]
+    fn leaves_other_angle_brackets_alone() {
+        let chars = |text: &str| text.chars().map(from_char).collect::<Vec<_>>();
+        assert_eq!(parse("<x>"), chars("<x>"));
+        assert_eq!(parse("a<b"), chars("a<b"));
+        assert_eq!(parse("<nope>"), chars("<nope>"));
+        assert_eq!(parse("<X-a>"), chars("<X-a>"));
+        assert_eq!(parse("<"), chars("<"));
+    }
+
+    #[test]
+    fn reads_back_what_it_writes() {
+        let keys = vec![
+            key(KeyCode::Char('d')),
+            key(KeyCode::Char('<')),
+            ctrl('v'),
+            key(KeyCode::Backspace),
+            key(KeyCode::Tab),
+            key(KeyCode::BackTab),
+            key(KeyCode::Delete),
+            key(KeyCode::PageDown),
+            KeyEvent::new(KeyCode::Left, KeyModifiers::CONTROL),
+        ];
+        assert_eq!(parse(&to_notation(&keys)), keys);
+    }
+
+    #[test]
+    fn control_chars_are_control_keys() {
+        assert_eq!(from_char('\x12'), ctrl('r'));
+        assert_eq!(from_char('\x1b'), key(KeyCode::Esc));
+        assert_eq!(from_char('\n'), key(KeyCode::Enter));
+        assert_eq!(from_char('\x7f'), key(KeyCode::Backspace));
+    }
+}
@@ -0,0 +1,407 @@
+/// The fewest rows or columns a window can be squeezed into: one for its
+/// text and one for its status line, or for the separator to its right.
+const MIN_SIZE: u16 = 2;
+
+/// The parts of a split, each with the rows or columns it takes.
+type Parts = Vec<(Layout, u16)>;
+
+/// A part of the screen, in terminal cells.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: u16,
+    pub y: u16,
+    pub width: u16,
+    pub height: u16,
+}
+
+impl Rect {
+    pub fn contains(&self, x: u16, y: u16) -> bool {
+        x >= self.x && x - self.x < self.width && y >= self.y && y - self.y < self.height
+    }
+}
+
+/// How the text area is shared out between windows, which are known by the
+/// numbers the editor gives them. Splits are kept flat: a split never holds
+/// another split the same way round.
+#[derive(Debug)]
+pub enum Layout {
+    Window(usize),
+    /// Parts one above another, or side by side if `vertical`. A
+    /// side-by-side part's columns take in the separator on its right, if
+    /// there's a part after it.
+    Split { vertical: bool, parts: Parts },
+}
+
+impl Layout {
+    /// Where each window goes when the layout fills `area`, in order from
+    /// the top left. The rectangles take in the windows' status lines but
+    /// not the separators between them.
+    pub fn arrange(&self, area: Rect) -> Vec<(usize, Rect)> {
+        let mut windows = Vec::new();
+        self.arrange_into(area, &mut windows);
+        windows
+    }
+
+    fn arrange_into(&self, area: Rect, windows: &mut Vec<(usize, Rect)>) {
+        let Layout::Split { vertical, parts } = self else {
+            if let Layout::Window(id) = self {
+                windows.push((*id, area));
+            }
+            return;
+        };
+        let total = if *vertical { area.width } else { area.height };
+        let last = parts.len() - 1;
+        let mut offset = 0;
+        for (i, (part, size)) in parts.iter().enumerate() {
+            // Sizes that don't add up, like just after the terminal shrank,
+            // are cut down to what's left, and the last part gets the rest.
+            let left = total.saturating_sub(offset);
+            let size = if i == last { left } else { (*size).min(left) };
+            let rect = if *vertical {
+                Rect { x: area.x + offset, width: size.saturating_sub((i < last) as u16), ..area }
+            } else {
+                Rect { y: area.y + offset, height: size, ..area }
+            };
+            part.arrange_into(rect, windows);
+            offset += size;
+        }
+    }
+
+    /// The windows, in order from the top left.
+    pub fn windows(&self) -> Vec<usize> {
+        match self {
+            Layout::Window(id) => vec![*id],
+            Layout::Split { parts, .. } => parts.iter().flat_map(|(part, _)| part.windows()).collect(),
+        }
+    }
+
+    fn contains(&self, id: usize) -> bool {
+        match self {
+            Layout::Window(window) => *window == id,
+            Layout::Split { parts, .. } => parts.iter().any(|(part, _)| part.contains(id)),
+        }
+    }
+
+    /// Splits window `id` in two, one above the other or side by side if
+    /// `vertical`, putting window `new` in the top or left half. Returns
+    /// false if there isn't room.
+    pub fn split(&mut self, id: usize, new: usize, vertical: bool, area: Rect) -> bool {
+        let Some(&(_, rect)) = self.arrange(area).iter().find(|(window, _)| *window == id) else {
+            return false;
+        };
+        match self.parts_holding(id) {
+            Some((parts_vertical, parts, i)) if parts_vertical == vertical => {
+                let size = parts[i].1;
+                if size < 2 * MIN_SIZE {
+                    return false;
+                }
+                parts[i].1 = size - size / 2;
+                parts.insert(i, (Layout::Window(new), size / 2));
+            }
+            _ => {
+                let size = if vertical { rect.width } else { rect.height };
+                if size < 2 * MIN_SIZE {
+                    return false;
+                }
+                let Some(window) = self.window_mut(id) else {
+                    return false;
+                };
+                *window = Layout::Split {
+                    vertical,
+                    parts: vec![(Layout::Window(new), size / 2), (Layout::Window(id), size - size / 2)],
+                };
+            }
+        }
+        true
+    }
+
+    /// Takes window `id` out, giving its space to the part after it, or
+    /// before it if it was the last. Returns a window in the part that got
+    /// the space, or `None` if `id` is the only window.
+    pub fn close(&mut self, id: usize) -> Option<usize> {
+        let (_, parts, i) = self.parts_holding(id)?;
+        let (_, size) = parts.remove(i);
+        let to = i.min(parts.len() - 1);
+        parts[to].1 += size;
+        let next = parts[to].0.windows()[0];
+        self.tidy();
+        Some(next)
+    }
+
+    /// Makes window `id` `delta` rows taller (or shorter), or columns wider
+    /// if `vertical`. Growing takes space from the parts after it and then
+    /// the ones before, as far as they can give it; shrinking gives it to
+    /// the next part.
+    pub fn resize(&mut self, id: usize, vertical: bool, delta: i32) {
+        let Some((parts, i)) = self.parts_along(id, vertical) else {
+            return;
+        };
+        let mins: Vec<u16> = parts.iter().map(|(part, _)| part.min_size(vertical)).collect();
+        let mut wanted = delta.unsigned_abs().min(u16::MAX as u32) as u16;
+        if delta > 0 {
+            for j in (i + 1..parts.len()).chain((0..i).rev()) {
+                let spare = parts[j].1.saturating_sub(mins[j]).min(wanted);
+                parts[j].1 -= spare;
+                parts[i].1 += spare;
+                wanted -= spare;
+            }
+        } else {
+            let Some(j) = (i + 1 < parts.len()).then_some(i + 1).or(i.checked_sub(1)) else {
+                return;
+            };
+            let spare = parts[i].1.saturating_sub(mins[i]).min(wanted);
+            parts[i].1 -= spare;
+            parts[j].1 += spare;
+        }
+    }
+
+    /// Shares each split's space out so that the windows in it are all as
+    /// near the same size as they can be.
+    pub fn equalize(&mut self) {
+        let Layout::Split { vertical, parts } = self else {
+            return;
+        };
+        let total = parts.iter().map(|(_, size)| *size).sum();
+        let weights: Vec<u16> = parts.iter().map(|(part, _)| part.count_along(*vertical)).collect();
+        for ((part, size), share) in parts.iter_mut().zip(share(total, &weights)) {
+            *size = share;
+            part.equalize();
+        }
+    }
+
+    /// Fits the layout to a text area of `width` by `height`, keeping the
+    /// parts of each split in proportion.
+    pub fn fit(&mut self, width: u16, height: u16) {
+        let Layout::Split { vertical, parts } = self else {
+            return;
+        };
+        let total = if *vertical { width } else { height };
+        let sizes: Vec<u16> = parts.iter().map(|(_, size)| *size).collect();
+        if sizes.iter().sum::<u16>() != total {
+            let weights = if sizes.contains(&0) { vec![1; sizes.len()] } else { sizes };
+            for ((_, size), share) in parts.iter_mut().zip(share(total, &weights)) {
+                *size = share;
+            }
+        }
+        let last = parts.len() - 1;
+        for (i, (part, size)) in parts.iter_mut().enumerate() {
+            if *vertical {
+                part.fit(size.saturating_sub((i < last) as u16), height);
+            } else {
+                part.fit(width, *size);
+            }
+        }
+    }
+
+    /// The split `id` is directly in, which way round it is and where `id`
+    /// is in it.
+    fn parts_holding(&mut self, id: usize) -> Option<(bool, &mut Parts, usize)> {
+        let Layout::Split { vertical, parts } = self else {
+            return None;
+        };
+        if let Some(i) = parts.iter().position(|(part, _)| matches!(part, Layout::Window(window) if *window == id)) {
+            return Some((*vertical, parts, i));
+        }
+        parts.iter_mut().find_map(|(part, _)| part.parts_holding(id))
+    }
+
+    /// The innermost split the `vertical` way round with `id` in it, and
+    /// which of its parts `id` is in.
+    fn parts_along(&mut self, id: usize, vertical: bool) -> Option<(&mut Parts, usize)> {
+        let Layout::Split { vertical: this_way, parts } = self else {
+            return None;
+        };
+        let i = parts.iter().position(|(part, _)| part.contains(id))?;
+        if parts[i].0.splits_along(id, vertical) {
+            return parts[i].0.parts_along(id, vertical);
+        }
+        (*this_way == vertical).then_some((parts, i))
+    }
+
+    fn splits_along(&self, id: usize, vertical: bool) -> bool {
+        match self {
+            Layout::Window(_) => false,
+            Layout::Split { vertical: this_way, parts } => parts
+                .iter()
+                .any(|(part, _)| part.contains(id) && (*this_way == vertical || part.splits_along(id, vertical))),
+        }
+    }
+
+    fn window_mut(&mut self, id: usize) -> Option<&mut Layout> {
+        if matches!(self, Layout::Window(window) if *window == id) {
+            return Some(self);
+        }
+        match self {
+            Layout::Window(_) => None,
+            Layout::Split { parts, .. } => parts.iter_mut().find_map(|(part, _)| part.window_mut(id)),
+        }
+    }
+
+    /// The rows, or columns if `vertical`, the part can't be made smaller
+    /// than.
+    fn min_size(&self, vertical: bool) -> u16 {
+        match self {
+            Layout::Window(_) => MIN_SIZE,
+            Layout::Split { vertical: this_way, parts } => {
+                let mins = parts.iter().map(|(part, _)| part.min_size(vertical));
+                if *this_way == vertical { mins.sum() } else { mins.max().unwrap_or(MIN_SIZE) }
+            }
+        }
+    }
+
+    /// How many windows the part has one after another the `vertical` way.
+    fn count_along(&self, vertical: bool) -> u16 {
+        match self {
+            Layout::Window(_) => 1,
+            Layout::Split { vertical: this_way, parts } => {
+                let counts = parts.iter().map(|(part, _)| part.count_along(vertical));
+                if *this_way == vertical { counts.sum() } else { counts.max().unwrap_or(1) }
+            }
+        }
+    }
+
+    /// Puts the only part of a split in its place, and merges splits into
+    /// ones the same way round that they're in.
+    fn tidy(&mut self) {
+        let Layout::Split { vertical, parts } = self else {
+            return;
+        };
+        let mut merged = Vec::new();
+        for (mut part, size) in parts.drain(..) {
+            part.tidy();
+            match part {
+                Layout::Split { vertical: this_way, parts: inner } if this_way == *vertical => merged.extend(inner),
+                part => merged.push((part, size)),
+            }
+        }
+        if merged.len() == 1 {
+            *self = merged.pop().expect("one part").0;
+        } else {
+            *parts = merged;
+        }
+    }
+}
+
+/// Splits `total` between parts in proportion to `weights`, handing out
+/// what rounding leaves over one at a time from the first.
+fn share(total: u16, weights: &[u16]) -> Vec<u16> {
+    let sum = weights.iter().map(|&weight| weight as u32).sum::<u32>().max(1);
+    let mut sizes: Vec<u16> = weights.iter().map(|&weight| (total as u32 * weight as u32 / sum) as u16).collect();
+    let mut left = total - sizes.iter().sum::<u16>();
+    for size in sizes.iter_mut() {
+        if left == 0 {
+            break;
+        }
+        *size += 1;
+        left -= 1;
+    }
+    sizes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AREA: Rect = Rect { x: 0, y: 0, width: 80, height: 20 };
+
+    fn rects(layout: &Layout) -> Vec<(usize, u16, u16, u16, u16)> {
+        layout.arrange(AREA).into_iter().map(|(id, r)| (id, r.x, r.y, r.width, r.height)).collect()
+    }
+
+    #[test]
+    fn splits_windows() {
+        let mut layout = Layout::Window(1);
+        assert_eq!(rects(&layout), vec![(1, 0, 0, 80, 20)]);
+        assert!(layout.split(1, 2, false, AREA));
+        assert_eq!(rects(&layout), vec![(2, 0, 0, 80, 10), (1, 0, 10, 80, 10)]);
+        // Side by side, the left window gives up a column for the separator.
+        assert!(layout.split(1, 3, true, AREA));
+        assert_eq!(rects(&layout), vec![(2, 0, 0, 80, 10), (3, 0, 10, 39, 10), (1, 40, 10, 40, 10)]);
+        // Splitting the same way as the split it's in adds to that split.
+        assert!(layout.split(2, 4, false, AREA));
+        assert_eq!(layout.windows(), vec![4, 2, 3, 1]);
+        assert_eq!(rects(&layout)[..2], [(4, 0, 0, 80, 5), (2, 0, 5, 80, 5)]);
+    }
+
+    #[test]
+    fn refuses_to_split_without_room() {
+        let mut layout = Layout::Window(1);
+        let small = Rect { height: 3, ..AREA };
+        assert!(!layout.split(1, 2, false, small));
+        assert!(layout.split(1, 2, true, small));
+        assert!(!layout.split(9, 3, true, small));
+    }
+
+    #[test]
+    fn closing_gives_space_to_a_neighbour() {
+        let mut layout = Layout::Window(1);
+        layout.split(1, 2, false, AREA);
+        layout.split(1, 3, true, AREA);
+        assert_eq!(layout.close(3), Some(1));
+        // The split with one window left in it goes.
+        assert!(matches!(&layout, Layout::Split { vertical: false, parts } if parts.len() == 2));
+        assert_eq!(rects(&layout), vec![(2, 0, 0, 80, 10), (1, 0, 10, 80, 10)]);
+        assert_eq!(layout.close(1), Some(2));
+        assert_eq!(rects(&layout), vec![(2, 0, 0, 80, 20)]);
+        assert_eq!(layout.close(2), None);
+    }
+
+    #[test]
+    fn closing_merges_splits_the_same_way_round() {
+        let mut layout = Layout::Window(1);
+        layout.split(1, 2, false, AREA);
+        layout.split(1, 3, true, AREA);
+        layout.split(3, 4, false, AREA);
+        layout.close(1);
+        assert_eq!(layout.windows(), vec![2, 4, 3]);
+        assert!(matches!(&layout, Layout::Split { vertical: false, parts } if parts.len() == 3));
+    }
+
+    #[test]
+    fn resizes_within_limits() {
+        let mut layout = Layout::Window(1);
+        layout.split(1, 2, false, AREA);
+        layout.resize(2, false, 3);
+        assert_eq!(rects(&layout), vec![(2, 0, 0, 80, 13), (1, 0, 13, 80, 7)]);
+        layout.resize(2, false, 100);
+        assert_eq!(rects(&layout), vec![(2, 0, 0, 80, 18), (1, 0, 18, 80, 2)]);
+        layout.resize(1, false, -100);
+        assert_eq!(rects(&layout), vec![(2, 0, 0, 80, 18), (1, 0, 18, 80, 2)]);
+        layout.resize(2, false, -100);
+        assert_eq!(rects(&layout), vec![(2, 0, 0, 80, 2), (1, 0, 2, 80, 18)]);
+        // There's no side-by-side split to make wider.
+        layout.resize(2, true, 5);
+        assert_eq!(rects(&layout), vec![(2, 0, 0, 80, 2), (1, 0, 2, 80, 18)]);
+    }
+
+    #[test]
+    fn equalizes_by_window_count() {
+        let mut layout = Layout::Window(1);
+        layout.split(1, 2, true, AREA);
+        layout.split(1, 3, true, AREA);
+        layout.split(1, 4, true, AREA);
+        layout.equalize();
+        assert_eq!(rects(&layout), vec![(2, 0, 0, 19, 20), (3, 20, 0, 19, 20), (4, 40, 0, 19, 20), (1, 60, 0, 20, 20)]);
+        layout.split(2, 5, false, AREA);
+        layout.equalize();
+        assert_eq!(rects(&layout)[..2], [(5, 0, 0, 19, 10), (2, 0, 10, 19, 10)]);
+    }
+
+    #[test]
+    fn fits_a_new_size_in_proportion() {
+        let mut layout = Layout::Window(1);
+        layout.split(1, 2, false, AREA);
+        layout.resize(2, false, 5);
+        layout.fit(80, 40);
+        let area = Rect { height: 40, ..AREA };
+        let heights: Vec<u16> = layout.arrange(area).iter().map(|(_, r)| r.height).collect();
+        assert_eq!(heights, vec![30, 10]);
+    }
+
+    #[test]
+    fn rect_contains() {
+        let rect = Rect { x: 2, y: 3, width: 4, height: 5 };
+        assert!(rect.contains(2, 3) && rect.contains(5, 7));
+        assert!(!rect.contains(6, 3) && !rect.contains(2, 8) && !rect.contains(1, 3));
+    }
+}
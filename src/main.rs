@@ -1,3 +1,4 @@
+
 use crossterm::event::{Event, KeyEventKind};
 use std::env;
 use std::io;
@@ -5,12 +6,35 @@ use crossterm::style::ResetColor;
 use crossterm::ExecutableCommand;
 use std::path::PathBuf;
 
+mod clipboard;
 mod editor;
+mod hex;
+mod motion;
+mod register;
+mod search;
+mod substitute;
+mod encoding;
+mod ex;
+mod keys;
+mod layout;
+mod fileformat;
+mod text_buffer;
+mod textobject;
+mod undo;
+mod unicode;
 mod terminal;
 mod config;
 
+#[allow(clippy::collapsible_if, clippy::collapsible_match)]
 fn main() -> io::Result<()> {
-    let filename = env::args().nth(1);
+    let mut filenames = Vec::new();
+    let mut binary = false;
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "-b" => binary = true,
+            _ => filenames.push(arg),
+        }
+    }
 
     let config_path = if let Some(config_dir) = dirs::config_dir() {
         let mut path = config_dir;
@@ -30,12 +54,11 @@ fn main() -> io::Result<()> {
     };
 
     let mut terminal = terminal::Terminal::new()?;
-    let mut editor = editor::Editor::new(filename, config)?;
+    let mut editor = editor::Editor::new(filenames, binary, config)?;
+    let (width, height) = terminal.size()?;
+    editor.resize(width, height);
 
     loop {
-        let (_, height) = terminal.size()?;
-        let text_area_height = height - 2;
-
         editor.draw(&mut terminal)?;
 
         match terminal.read_event()? {
@@ -46,9 +69,7 @@ fn main() -> io::Result<()> {
                     }
                 }
             }
-            Event::Resize(_, _) => {
-                editor.adjust_scroll(text_area_height);
-            }
+            Event::Resize(width, height) => editor.resize(width, height),
             _ => {}
         }
     }
@@ -0,0 +1,609 @@
+use crate::text_buffer::TextBuffer;
+use crate::unicode;
+
+/// A (line, column) position, with the column in chars.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Pos {
+    pub line: usize,
+    pub col: usize,
+}
+
+impl Pos {
+    pub fn new(line: usize, col: usize) -> Self {
+        Pos { line, col }
+    }
+}
+
+/// How an operator treats the text between the cursor and a motion's target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MotionKind {
+    /// Up to but not including the target.
+    Exclusive,
+    /// Up to and including the target.
+    Inclusive,
+    /// Whole lines, from the cursor's line to the target's.
+    Linewise,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Motion {
+    Left,
+    Right,
+    Up,
+    Down,
+    /// `w`, or `W` with `big`, which only splits words at blanks.
+    WordForward { big: bool },
+    /// `b`/`B`.
+    WordBackward { big: bool },
+    /// `e`/`E`.
+    WordEnd { big: bool },
+    /// `ge`/`gE`.
+    WordEndBackward { big: bool },
+    /// `}`.
+    ParagraphForward,
+    /// `{`.
+    ParagraphBackward,
+    /// `)`.
+    SentenceForward,
+    /// `(`.
+    SentenceBackward,
+    /// `%`: the bracket paired with the next one on the line.
+    MatchPair,
+    /// `0`.
+    LineStart,
+    FirstNonBlank,
+    /// `_`: the first non-blank of the line `count - 1` lines down.
+    FirstNonBlankDown,
+    LineEnd,
+    FirstLine,
+    LastLine,
+    /// `gg`/`G` with a count: the first non-blank of that line (1-based).
+    GotoLine(usize),
+    /// `f`, `t`, `F` or `T`.
+    FindChar(CharSearch),
+    /// `;` and `,`: the last `FindChar` again, or the other way for `,`.
+    RepeatFind(CharSearch),
+    /// `` `x `` goes to mark `x`, `'x` (`linewise`) to the first non-blank
+    /// of its line.
+    Mark { pos: Pos, linewise: bool },
+}
+
+/// What `f`/`t` look for: a char later in the line, or earlier with
+/// `backward` (`F`/`T`). With `till` they stop just before (or after) it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CharSearch {
+    pub ch: char,
+    pub till: bool,
+    pub backward: bool,
+}
+
+impl Motion {
+    pub fn kind(self) -> MotionKind {
+        match self {
+            Motion::Up
+            | Motion::Down
+            | Motion::FirstNonBlankDown
+            | Motion::FirstLine
+            | Motion::LastLine
+            | Motion::GotoLine(_)
+            | Motion::Mark { linewise: true, .. } => MotionKind::Linewise,
+            Motion::WordEnd { .. } | Motion::WordEndBackward { .. } | Motion::MatchPair => MotionKind::Inclusive,
+            Motion::FindChar(search) | Motion::RepeatFind(search) if !search.backward => MotionKind::Inclusive,
+            _ => MotionKind::Exclusive,
+        }
+    }
+
+    /// Whether the motion is a jump, which the jump list remembers where it
+    /// was made from.
+    pub fn is_jump(self) -> bool {
+        matches!(
+            self,
+            Motion::FirstLine
+                | Motion::LastLine
+                | Motion::GotoLine(_)
+                | Motion::ParagraphForward
+                | Motion::ParagraphBackward
+                | Motion::SentenceForward
+                | Motion::SentenceBackward
+                | Motion::MatchPair
+                | Motion::Mark { .. }
+        )
+    }
+}
+
+/// Which chars make up keywords (`iskeyword`), in Vim's notation: a comma
+/// separated list of chars, `N-M` ranges of char codes and `@` for letters,
+/// any of them negated by a leading `^`. Only codes up to 255 are listed;
+/// everything above is always a keyword char.
+#[derive(Debug, Clone)]
+pub struct IsKeyword {
+    table: [bool; 256],
+}
+
+impl Default for IsKeyword {
+    fn default() -> Self {
+        IsKeyword::parse(IsKeyword::DEFAULT).expect("default iskeyword is valid")
+    }
+}
+
+impl IsKeyword {
+    pub const DEFAULT: &'static str = "@,48-57,_,192-255";
+
+    /// Parses an `iskeyword` value, or returns `None` if it's malformed.
+    pub fn parse(spec: &str) -> Option<Self> {
+        let mut table = [false; 256];
+        for part in spec.split(',').filter(|p| !p.is_empty()) {
+            let (include, part) = match part.strip_prefix('^') {
+                Some(rest) if !rest.is_empty() => (false, rest),
+                _ => (true, part),
+            };
+            if part == "@" {
+                for c in 0..=255u8 {
+                    if c.is_ascii_alphabetic() || c >= 0xc0 {
+                        table[c as usize] = include;
+                    }
+                }
+                continue;
+            }
+            let (from, to) = match part.split_once('-') {
+                Some((from, to)) if !from.is_empty() && !to.is_empty() => (Self::code(from)?, Self::code(to)?),
+                _ => {
+                    let code = Self::code(part)?;
+                    (code, code)
+                }
+            };
+            for entry in table.iter_mut().take(to as usize + 1).skip(from as usize) {
+                *entry = include;
+            }
+        }
+        Some(IsKeyword { table })
+    }
+
+    /// A char code or a single char, as used in `iskeyword` ranges.
+    fn code(s: &str) -> Option<u8> {
+        if let Ok(n) = s.parse::<u8>() {
+            return Some(n);
+        }
+        let mut chars = s.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => u8::try_from(c as u32).ok(),
+            _ => None,
+        }
+    }
+
+    pub fn contains(&self, c: char) -> bool {
+        match u8::try_from(c as u32) {
+            Ok(b) => self.table[b as usize],
+            Err(_) => true,
+        }
+    }
+}
+
+/// Word-character classes used by the word motions: blanks, punctuation
+/// and keyword characters. A run of one class is a word.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CharClass {
+    Blank,
+    Punctuation,
+    Keyword,
+}
+
+/// How the word motions split text into words: by `iskeyword`, or for
+/// WORDs (`big`) only at blanks.
+#[derive(Debug, Clone, Copy)]
+pub struct Words<'a> {
+    pub iskeyword: &'a IsKeyword,
+    pub big: bool,
+}
+
+impl Words<'_> {
+    pub fn class(&self, c: char) -> CharClass {
+        if c.is_whitespace() {
+            CharClass::Blank
+        } else if self.big || self.iskeyword.contains(c) {
+            CharClass::Keyword
+        } else {
+            CharClass::Punctuation
+        }
+    }
+
+    /// Class of the char at `idx`; past the end of the buffer counts as blank.
+    fn class_at(&self, buffer: &TextBuffer, idx: usize) -> CharClass {
+        if idx < buffer.len_chars() {
+            self.class(buffer.char(idx))
+        } else {
+            CharClass::Blank
+        }
+    }
+}
+
+/// Whether char index `idx` starts an empty line. Empty lines count as words.
+fn is_empty_line(buffer: &TextBuffer, idx: usize) -> bool {
+    let at_line_start = idx == 0 || buffer.char(idx - 1) == '\n';
+    at_line_start && (idx == buffer.len_chars() || buffer.char(idx) == '\n')
+}
+
+/// `w`: start of the next word. Returns the end of the buffer if there is none.
+pub fn word_forward(buffer: &TextBuffer, words: Words, idx: usize) -> usize {
+    let len = buffer.len_chars();
+    let mut i = idx;
+    if i >= len {
+        return len;
+    }
+    let class = words.class_at(buffer, i);
+    if class != CharClass::Blank {
+        while i < len && words.class_at(buffer, i) == class {
+            i += 1;
+        }
+    }
+    while i < len && words.class_at(buffer, i) == CharClass::Blank {
+        i += 1;
+        if buffer.char(i - 1) == '\n' && is_empty_line(buffer, i) {
+            break;
+        }
+    }
+    i
+}
+
+/// `b`: start of the current or previous word.
+pub fn word_backward(buffer: &TextBuffer, words: Words, idx: usize) -> usize {
+    if idx == 0 {
+        return 0;
+    }
+    let mut i = idx - 1;
+    while i > 0 && words.class_at(buffer, i) == CharClass::Blank {
+        if is_empty_line(buffer, i) {
+            return i;
+        }
+        i -= 1;
+    }
+    let class = words.class_at(buffer, i);
+    if class == CharClass::Blank {
+        return i;
+    }
+    while i > 0 && words.class_at(buffer, i - 1) == class {
+        i -= 1;
+    }
+    i
+}
+
+/// `e`: end of the current or next word.
+pub fn word_end(buffer: &TextBuffer, words: Words, idx: usize) -> usize {
+    let len = buffer.len_chars();
+    let mut i = idx + 1;
+    while i < len && words.class_at(buffer, i) == CharClass::Blank {
+        i += 1;
+    }
+    if i >= len {
+        return len.saturating_sub(1);
+    }
+    let class = words.class_at(buffer, i);
+    while i + 1 < len && words.class_at(buffer, i + 1) == class {
+        i += 1;
+    }
+    i
+}
+
+/// `ge`: end of the previous word.
+pub fn word_end_backward(buffer: &TextBuffer, words: Words, idx: usize) -> usize {
+    let mut i = idx;
+    let class = words.class_at(buffer, i);
+    if class != CharClass::Blank {
+        while i > 0 && words.class_at(buffer, i - 1) == class {
+            i -= 1;
+        }
+    }
+    while i > 0 {
+        i -= 1;
+        if words.class_at(buffer, i) != CharClass::Blank || is_empty_line(buffer, i) {
+            return i;
+        }
+    }
+    0
+}
+
+/// End of the word under `idx`, which may be `idx` itself.
+pub fn current_word_end(buffer: &TextBuffer, words: Words, idx: usize) -> usize {
+    let class = words.class_at(buffer, idx);
+    let mut i = idx;
+    while i + 1 < buffer.len_chars() && words.class_at(buffer, i + 1) == class {
+        i += 1;
+    }
+    i
+}
+
+/// `}`: the next empty line after the current paragraph, or the end of the
+/// last line.
+pub fn paragraph_forward(buffer: &TextBuffer, line: usize) -> Pos {
+    let last = buffer.len_lines() - 1;
+    let mut l = line;
+    while l < last && buffer.line_len(l) == 0 {
+        l += 1;
+    }
+    while l < last && buffer.line_len(l) != 0 {
+        l += 1;
+    }
+    if buffer.line_len(l) == 0 {
+        Pos::new(l, 0)
+    } else {
+        Pos::new(last, buffer.line_len(last))
+    }
+}
+
+/// `%`: finds the first of `()[]{}` at or after `pos` on its line and
+/// returns where its partner is, skipping pairs nested between them.
+pub fn match_pair(buffer: &TextBuffer, pos: Pos) -> Option<Pos> {
+    const PAIRS: [(char, char); 3] = [('(', ')'), ('[', ']'), ('{', '}')];
+    let line_end = buffer.line_to_char(pos.line) + buffer.line_len(pos.line);
+    let (at, c, (open, close)) = (buffer.pos_to_char(pos.line, pos.col)..line_end).find_map(|i| {
+        let c = buffer.char(i);
+        PAIRS.into_iter().find(|&(open, close)| c == open || c == close).map(|pair| (i, c, pair))
+    })?;
+    let forward = c == open;
+    let mut depth = 0;
+    let mut i = at;
+    let found = loop {
+        i = if forward { i + 1 } else { i.checked_sub(1)? };
+        if i >= buffer.len_chars() {
+            return None;
+        }
+        match buffer.char(i) {
+            ch if ch == c => depth += 1,
+            ch if ch == open || ch == close => {
+                if depth == 0 {
+                    break i;
+                }
+                depth -= 1;
+            }
+            _ => {}
+        }
+    };
+    let (line, col) = buffer.char_to_pos(found);
+    Some(Pos::new(line, col))
+}
+
+/// `{`: the empty line before the current paragraph, or the first line.
+pub fn paragraph_backward(buffer: &TextBuffer, line: usize) -> Pos {
+    let mut l = line;
+    while l > 0 && buffer.line_len(l) == 0 {
+        l -= 1;
+    }
+    while l > 0 && buffer.line_len(l) != 0 {
+        l -= 1;
+    }
+    Pos::new(l, 0)
+}
+
+/// Whether a sentence starts at char index `idx`: the first non-blank after
+/// a `.`, `!` or `?` (optionally followed by closing brackets and quotes) and
+/// some blanks, or after an empty line. Empty lines are sentences too.
+fn is_sentence_start(buffer: &TextBuffer, idx: usize) -> bool {
+    if is_empty_line(buffer, idx) {
+        return idx < buffer.len_chars();
+    }
+    if buffer.char(idx).is_whitespace() {
+        return false;
+    }
+    let mut i = idx;
+    while i > 0 && buffer.char(i - 1).is_whitespace() {
+        i -= 1;
+        if is_empty_line(buffer, i) {
+            return true;
+        }
+    }
+    if i == 0 {
+        return true;
+    }
+    if i == idx {
+        return false;
+    }
+    while i > 0 && matches!(buffer.char(i - 1), ')' | ']' | '"' | '\'') {
+        i -= 1;
+    }
+    i > 0 && matches!(buffer.char(i - 1), '.' | '!' | '?')
+}
+
+/// `)`: start of the next sentence, or the end of the buffer.
+pub fn sentence_forward(buffer: &TextBuffer, idx: usize) -> usize {
+    let len = buffer.len_chars();
+    (idx + 1..len).find(|&i| is_sentence_start(buffer, i)).unwrap_or(len)
+}
+
+/// `(`: start of the current or previous sentence.
+pub fn sentence_backward(buffer: &TextBuffer, idx: usize) -> usize {
+    (0..idx).rev().find(|&i| is_sentence_start(buffer, i)).unwrap_or(0)
+}
+
+/// Column of the first non-blank char on `line`, or its end if it's all blank.
+pub fn first_non_blank(buffer: &TextBuffer, line: usize) -> usize {
+    buffer
+        .line(line)
+        .chars()
+        .position(|c| c != ' ' && c != '\t')
+        .unwrap_or_else(|| buffer.line_len(line))
+}
+
+/// `f`/`t`/`F`/`T`: column of the `count`th `search.ch` after (or
+/// before) `col` on `line`, or the one next to it for `till`. `None` if the
+/// line doesn't have that many. When `again`, a `till` search that would
+/// stay where it is, right next to the char, goes on to the next one.
+pub fn find_char(buffer: &TextBuffer, line: usize, col: usize, search: CharSearch, count: usize, again: bool) -> Option<usize> {
+    let text: Vec<char> = buffer.line(line).chars().collect();
+    let skip = if search.till && again { 2 } else { 1 };
+    let is_match = |i: &usize| text[*i] == search.ch;
+    let found = if search.backward {
+        (0..col.saturating_sub(skip - 1)).rev().filter(is_match).nth(count.max(1) - 1)?
+    } else {
+        (col + skip..text.len()).filter(is_match).nth(count.max(1) - 1)?
+    };
+    Some(match (search.till, search.backward) {
+        (false, _) => found,
+        (true, false) => found - 1,
+        (true, true) => found + 1,
+    })
+}
+
+/// Columns of the nearest of each char after `col` on `line`, or before it
+/// when `backward`: where `f` (or `F`) and that char go. Blanks are left out.
+pub fn find_targets(line: &str, col: usize, backward: bool) -> Vec<usize> {
+    let chars: Vec<char> = line.chars().collect();
+    let cols: Vec<usize> = if backward {
+        (0..col.min(chars.len())).rev().collect()
+    } else {
+        (col + 1..chars.len()).collect()
+    };
+    let mut seen = std::collections::HashSet::new();
+    cols.into_iter().filter(|&i| !chars[i].is_whitespace() && seen.insert(chars[i])).collect()
+}
+
+/// Where `motion` repeated `count` times takes the cursor from `pos`, or
+/// `None` if it can't move at all. Left and right stay within the line.
+pub fn target(buffer: &TextBuffer, iskeyword: &IsKeyword, motion: Motion, pos: Pos, count: usize) -> Option<Pos> {
+    let count = count.max(1);
+    let last_line = buffer.len_lines() - 1;
+    let from = buffer.pos_to_char(pos.line, pos.col);
+    let to_pos = |idx: usize| {
+        let (line, col) = buffer.char_to_pos(idx);
+        Pos::new(line, col)
+    };
+    let repeat = |step: &dyn Fn(usize) -> usize| {
+        let to = (0..count).fold(from, |idx, _| step(idx));
+        (to != from).then(|| to_pos(to))
+    };
+    let words = |big| Words { iskeyword, big };
+
+    match motion {
+        Motion::Left => {
+            let line = buffer.line(pos.line);
+            let col = (0..count).fold(pos.col, |col, _| unicode::prev_grapheme_boundary(&line, col));
+            (col != pos.col).then(|| Pos::new(pos.line, col))
+        }
+        Motion::Right => {
+            let line = buffer.line(pos.line);
+            let col = (0..count).fold(pos.col, |col, _| unicode::next_grapheme_boundary(&line, col));
+            (col != pos.col).then(|| Pos::new(pos.line, col))
+        }
+        Motion::Up => (pos.line > 0).then(|| Pos::new(pos.line.saturating_sub(count), pos.col)),
+        Motion::Down => (pos.line < last_line).then(|| Pos::new((pos.line + count).min(last_line), pos.col)),
+        Motion::WordForward { big } => repeat(&|idx| word_forward(buffer, words(big), idx)),
+        Motion::WordBackward { big } => repeat(&|idx| word_backward(buffer, words(big), idx)),
+        Motion::WordEnd { big } => repeat(&|idx| word_end(buffer, words(big), idx)),
+        Motion::WordEndBackward { big } => repeat(&|idx| word_end_backward(buffer, words(big), idx)),
+        Motion::SentenceForward => repeat(&|idx| sentence_forward(buffer, idx)),
+        Motion::SentenceBackward => repeat(&|idx| sentence_backward(buffer, idx)),
+        Motion::ParagraphForward => {
+            let to = (0..count).fold(pos, |p, _| paragraph_forward(buffer, p.line));
+            (to != pos).then_some(to)
+        }
+        Motion::ParagraphBackward => {
+            let to = (0..count).fold(pos, |p, _| paragraph_backward(buffer, p.line));
+            (to != pos).then_some(to)
+        }
+        Motion::MatchPair => match_pair(buffer, pos),
+        Motion::LineStart => Some(Pos::new(pos.line, 0)),
+        Motion::FirstNonBlank => Some(Pos::new(pos.line, first_non_blank(buffer, pos.line))),
+        Motion::FirstNonBlankDown => {
+            let line = (pos.line + count - 1).min(last_line);
+            Some(Pos::new(line, first_non_blank(buffer, line)))
+        }
+        Motion::LineEnd => {
+            let line = (pos.line + count - 1).min(last_line);
+            Some(Pos::new(line, buffer.line_len(line)))
+        }
+        Motion::FirstLine => Some(Pos::new(0, first_non_blank(buffer, 0))),
+        Motion::LastLine => Some(Pos::new(last_line, first_non_blank(buffer, last_line))),
+        Motion::GotoLine(n) => {
+            let line = n.saturating_sub(1).min(last_line);
+            Some(Pos::new(line, first_non_blank(buffer, line)))
+        }
+        Motion::FindChar(search) => find_char(buffer, pos.line, pos.col, search, count, false).map(|col| Pos::new(pos.line, col)),
+        Motion::RepeatFind(search) => find_char(buffer, pos.line, pos.col, search, count, true).map(|col| Pos::new(pos.line, col)),
+        Motion::Mark { pos: mark, linewise } => {
+            let line = mark.line.min(last_line);
+            let col = if linewise { first_non_blank(buffer, line) } else { mark.col.min(buffer.line_len(line)) };
+            Some(Pos::new(line, col))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn go(text: &str, motion: Motion, (line, col): (usize, usize), count: usize) -> Option<(usize, usize)> {
+        let buffer = TextBuffer::from(text);
+        target(&buffer, &IsKeyword::default(), motion, Pos::new(line, col), count).map(|pos| (pos.line, pos.col))
+    }
+
+    const W: Motion = Motion::WordForward { big: false };
+    const B: Motion = Motion::WordBackward { big: false };
+    const E: Motion = Motion::WordEnd { big: false };
+
+    #[test]
+    fn words_across_punctuation_blanks_and_empty_lines() {
+        let text = "foo.bar  baz\n\nqux";
+        assert_eq!(go(text, W, (0, 0), 1), Some((0, 3)));
+        assert_eq!(go(text, W, (0, 3), 1), Some((0, 4)));
+        assert_eq!(go(text, W, (0, 4), 1), Some((0, 9)));
+        // An empty line is a word of its own.
+        assert_eq!(go(text, W, (0, 9), 1), Some((1, 0)));
+        assert_eq!(go(text, W, (1, 0), 1), Some((2, 0)));
+        assert_eq!(go(text, Motion::WordForward { big: true }, (0, 0), 1), Some((0, 9)));
+
+        assert_eq!(go(text, B, (2, 0), 1), Some((1, 0)));
+        assert_eq!(go(text, B, (1, 0), 1), Some((0, 9)));
+        assert_eq!(go(text, B, (0, 9), 2), Some((0, 3)));
+        assert_eq!(go(text, Motion::WordBackward { big: true }, (0, 9), 1), Some((0, 0)));
+        assert_eq!(go(text, B, (0, 0), 1), None);
+
+        assert_eq!(go(text, E, (0, 0), 1), Some((0, 2)));
+        assert_eq!(go(text, E, (0, 2), 1), Some((0, 3)));
+        assert_eq!(go(text, E, (0, 3), 1), Some((0, 6)));
+        // `e` skips empty lines.
+        assert_eq!(go(text, E, (0, 11), 1), Some((2, 2)));
+        assert_eq!(go(text, Motion::WordEnd { big: true }, (0, 0), 1), Some((0, 6)));
+        assert_eq!(go(text, Motion::WordEndBackward { big: false }, (2, 1), 1), Some((1, 0)));
+        assert_eq!(go(text, Motion::WordEndBackward { big: false }, (1, 0), 1), Some((0, 11)));
+    }
+
+    #[test]
+    fn paragraphs_at_the_ends_of_the_buffer() {
+        let text = "a\nb\n\nc\nd";
+        assert_eq!(go(text, Motion::ParagraphForward, (0, 0), 1), Some((2, 0)));
+        // The last paragraph goes to the end of the last line.
+        assert_eq!(go(text, Motion::ParagraphForward, (3, 0), 1), Some((4, 1)));
+        assert_eq!(go(text, Motion::ParagraphForward, (4, 1), 1), None);
+        assert_eq!(go(text, Motion::ParagraphBackward, (4, 0), 1), Some((2, 0)));
+        assert_eq!(go(text, Motion::ParagraphBackward, (1, 0), 1), Some((0, 0)));
+        assert_eq!(go(text, Motion::ParagraphBackward, (0, 0), 1), None);
+    }
+
+    #[test]
+    fn matching_pairs() {
+        let text = "f(a[b]{c})";
+        assert_eq!(go(text, Motion::MatchPair, (0, 0), 1), Some((0, 9)));
+        assert_eq!(go(text, Motion::MatchPair, (0, 3), 1), Some((0, 5)));
+        assert_eq!(go(text, Motion::MatchPair, (0, 9), 1), Some((0, 1)));
+        // From inside, the next bracket on the line is used.
+        assert_eq!(go(text, Motion::MatchPair, (0, 7), 1), Some((0, 6)));
+        assert_eq!(go("if (x) {\n  y\n}", Motion::MatchPair, (0, 6), 1), Some((2, 0)));
+        assert_eq!(go("(a", Motion::MatchPair, (0, 0), 1), None);
+        assert_eq!(go("a) (", Motion::MatchPair, (0, 2), 1), None);
+        assert_eq!(go("abc", Motion::MatchPair, (0, 0), 1), None);
+    }
+
+    #[test]
+    fn counts_past_the_end_stop_at_the_edge() {
+        let text = "one two\nthree";
+        // Past the last char, so an operator takes it in.
+        assert_eq!(go(text, W, (0, 0), 99), Some((1, 5)));
+        assert_eq!(go(text, B, (1, 2), 99), Some((0, 0)));
+        assert_eq!(go(text, E, (0, 0), 99), Some((1, 4)));
+        assert_eq!(go(text, Motion::Down, (0, 0), 99), Some((1, 0)));
+        assert_eq!(go(text, Motion::Up, (1, 0), 99), Some((0, 0)));
+        assert_eq!(go(text, Motion::Right, (0, 5), 99), Some((0, 7)));
+        assert_eq!(go(text, Motion::ParagraphForward, (0, 0), 99), Some((1, 5)));
+        assert_eq!(go(text, Motion::GotoLine(99), (0, 0), 1), Some((1, 0)));
+        assert_eq!(go(text, Motion::LineEnd, (0, 0), 99), Some((1, 5)));
+    }
+}
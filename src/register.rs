@@ -0,0 +1,161 @@
+/// How text in a register goes back into the buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegisterKind {
+    /// Put inside a line, at the cursor.
+    Charwise,
+    /// Whole lines, put above or below the cursor's line. The text always
+    /// ends with a `\n`.
+    Linewise,
+    /// A rectangle from Visual block mode: one piece of text per line,
+    /// separated by `\n`, put at the same column on successive lines.
+    Blockwise,
+}
+
+#[derive(Debug, Clone)]
+pub struct Register {
+    pub text: String,
+    pub kind: RegisterKind,
+}
+
+impl Register {
+    pub fn charwise(text: impl Into<String>) -> Self {
+        Register { text: text.into(), kind: RegisterKind::Charwise }
+    }
+
+    /// Adds `other` to the end, for the uppercase register names. Adding
+    /// lines to a charwise register makes it linewise.
+    fn append(&mut self, other: Register) {
+        if self.kind == RegisterKind::Charwise && other.kind == RegisterKind::Linewise {
+            self.text.push('\n');
+            self.kind = RegisterKind::Linewise;
+        } else if self.kind == RegisterKind::Blockwise || other.kind == RegisterKind::Blockwise {
+            self.text.push('\n');
+        }
+        self.text.push_str(&other.text);
+        if self.kind == RegisterKind::Linewise && !self.text.ends_with('\n') {
+            self.text.push('\n');
+        }
+    }
+}
+
+/// What put text in a register, which decides where else it goes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Store {
+    Yank,
+    /// A delete or change. `small` ones (within a line) go to `"-` instead
+    /// of the numbered registers.
+    Delete { small: bool },
+}
+
+/// All of the registers: `""` (unnamed), `"a`-`"z`, `"0`-`"9`, `"-`, the
+/// black hole `"_`, the read-only `".`, `":` and `"%`, and the last text
+/// sent to the system clipboard through `"+` and `"*`.
+#[derive(Debug, Default)]
+pub struct Registers {
+    unnamed: Option<Register>,
+    named: [Option<Register>; 26],
+    /// `"0` is the last yank, `"1`-`"9` the last nine big deletes.
+    numbered: [Option<Register>; 10],
+    small_delete: Option<Register>,
+    clipboard: Option<Register>,
+    primary: Option<Register>,
+    /// Text typed in the last Insert-mode session, for `".`.
+    pub last_inserted: String,
+    /// The last command-line command run, for `":`.
+    pub last_command: String,
+    /// The last search pattern, for `"/` and `n`.
+    pub last_search: String,
+}
+
+impl Registers {
+    /// Whether `name` can follow `"`.
+    pub fn is_valid(name: char) -> bool {
+        name.is_ascii_alphanumeric() || matches!(name, '"' | '-' | '_' | '.' | ':' | '%' | '/' | '+' | '*')
+    }
+
+    /// Whether text can be put into `name`.
+    pub fn is_writable(name: char) -> bool {
+        Self::is_valid(name) && !matches!(name, '.' | ':' | '%' | '/')
+    }
+
+    /// Puts `register` into register `name` (the unnamed register if
+    /// `None`), along with the others that Vim fills in for `kind`.
+    pub fn store(&mut self, name: Option<char>, register: Register, kind: Store) {
+        match name {
+            Some('_') => return,
+            Some(c) if c.is_ascii_lowercase() => self.named[c as usize - 'a' as usize] = Some(register.clone()),
+            Some(c) if c.is_ascii_uppercase() => {
+                let slot = &mut self.named[c.to_ascii_lowercase() as usize - 'a' as usize];
+                match slot {
+                    Some(existing) => existing.append(register),
+                    None => *slot = Some(register),
+                }
+                self.unnamed = slot.clone();
+                return;
+            }
+            Some(c) if c.is_ascii_digit() => self.numbered[c as usize - '0' as usize] = Some(register.clone()),
+            Some('-') => self.small_delete = Some(register.clone()),
+            Some('+') => self.clipboard = Some(register.clone()),
+            Some('*') => self.primary = Some(register.clone()),
+            _ => match kind {
+                Store::Yank => self.numbered[0] = Some(register.clone()),
+                Store::Delete { small: true } => self.small_delete = Some(register.clone()),
+                Store::Delete { small: false } => {
+                    self.numbered[1..].rotate_right(1);
+                    self.numbered[1] = Some(register.clone());
+                }
+            },
+        }
+        self.unnamed = Some(register);
+    }
+
+    /// Whether `q` can record a macro into `name`.
+    pub fn can_record(name: char) -> bool {
+        name.is_ascii_alphanumeric() || name == '"'
+    }
+
+    /// Puts the keys of a macro recorded with `q` into register `name`, an
+    /// uppercase name adding them to the end. Unlike [`Registers::store`]
+    /// this leaves the other registers alone.
+    pub fn record(&mut self, name: char, keys: String) {
+        let register = Register::charwise(keys);
+        match name {
+            c if c.is_ascii_lowercase() => self.named[c as usize - 'a' as usize] = Some(register),
+            c if c.is_ascii_uppercase() => match &mut self.named[c.to_ascii_lowercase() as usize - 'a' as usize] {
+                Some(existing) => existing.append(register),
+                slot => *slot = Some(register),
+            },
+            c if c.is_ascii_digit() => self.numbered[c as usize - '0' as usize] = Some(register),
+            '"' => self.unnamed = Some(register),
+            _ => {}
+        }
+    }
+
+    /// Contents of register `name` (the unnamed register if `None`).
+    /// `filename` is what `"%` holds.
+    pub fn get(&self, name: Option<char>, filename: Option<&str>) -> Option<Register> {
+        match name.unwrap_or('"') {
+            '"' => self.unnamed.clone(),
+            c if c.is_ascii_alphabetic() => self.named[c.to_ascii_lowercase() as usize - 'a' as usize].clone(),
+            c if c.is_ascii_digit() => self.numbered[c as usize - '0' as usize].clone(),
+            '-' => self.small_delete.clone(),
+            '+' => self.clipboard.clone(),
+            '*' => self.primary.clone(),
+            '.' => (!self.last_inserted.is_empty()).then(|| Register::charwise(self.last_inserted.as_str())),
+            ':' => (!self.last_command.is_empty()).then(|| Register::charwise(self.last_command.as_str())),
+            '/' => (!self.last_search.is_empty()).then(|| Register::charwise(self.last_search.as_str())),
+            '%' => filename.map(Register::charwise),
+            _ => None,
+        }
+    }
+
+    /// Every register with something in it, in the order `:registers`
+    /// lists them.
+    pub fn list(&self, filename: Option<&str>) -> Vec<(char, Register)> {
+        let names = std::iter::once('"')
+            .chain('0'..='9')
+            .chain('a'..='z')
+            .chain(['-', '.', ':', '%', '/', '+', '*']);
+        names.filter_map(|name| self.get(Some(name), filename).map(|r| (name, r))).collect()
+    }
+}
@@ -0,0 +1,197 @@
+use crate::text_buffer::TextBuffer;
+use regex::{Regex, RegexBuilder};
+use std::ops::Range;
+
+/// How letter case is treated when searching.
+#[derive(Debug, Clone, Copy)]
+pub struct CaseOptions {
+    pub ignorecase: bool,
+    /// With `ignorecase`, a pattern with an uppercase letter in it still
+    /// matches case exactly.
+    pub smartcase: bool,
+}
+
+/// Compiles a search pattern. Patterns use Rust's regex syntax, plus Vim's
+/// `\<` and `\>` word boundaries and `\c`/`\C` anywhere in the pattern to
+/// force ignoring or matching case. `^` and `$` match at line breaks.
+pub fn compile(pattern: &str, case: CaseOptions) -> Result<Regex, regex::Error> {
+    let mut translated = String::with_capacity(pattern.len());
+    let mut ignore_case = None;
+    let mut has_upper = false;
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            has_upper |= c.is_uppercase();
+            translated.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('<' | '>') => translated.push_str(r"\b"),
+            Some('c') => ignore_case = Some(true),
+            Some('C') => ignore_case = Some(false),
+            Some(next) => {
+                translated.push('\\');
+                translated.push(next);
+            }
+            None => translated.push('\\'),
+        }
+    }
+    let ignore_case = ignore_case.unwrap_or(case.ignorecase && !(case.smartcase && has_upper));
+    RegexBuilder::new(&translated)
+        .case_insensitive(ignore_case)
+        .multi_line(true)
+        .build()
+}
+
+/// Where a search landed.
+pub struct SearchMatch {
+    /// Chars of the match.
+    pub range: Range<usize>,
+    /// Whether the search went past the end (or start) of the buffer and
+    /// came round from the other side.
+    pub wrapped: bool,
+}
+
+/// Finds the next match of `regex` starting after char index `from`, or
+/// the last one starting before it when `backward`. Wraps around the buffer.
+/// Lines are searched one at a time from `from` outwards, so a match never
+/// spans a line break and a large buffer is never copied whole.
+pub fn find(buffer: &TextBuffer, regex: &Regex, from: usize, backward: bool) -> Option<SearchMatch> {
+    let (from_line, from_col) = buffer.char_to_pos(from.min(buffer.len_chars()));
+    let lines = buffer.len_lines();
+    let found = |line_idx: usize, line: &str, m: regex::Match, wrapped| {
+        let start = buffer.line_to_char(line_idx) + line[..m.start()].chars().count();
+        let end = start + line[m.range()].chars().count();
+        SearchMatch { range: start..end, wrapped }
+    };
+
+    let line = buffer.line(from_line);
+    let from_byte = line.char_indices().nth(from_col).map_or(line.len(), |(i, _)| i);
+    if backward {
+        if let Some(m) = regex.find_iter(&line).take_while(|m| m.start() < from_byte).last() {
+            return Some(found(from_line, &line, m, false));
+        }
+        // The lines above, then round from the bottom back to this one.
+        (1..=lines).find_map(|i| {
+            let line_idx = (from_line + lines - i) % lines;
+            let line = buffer.line(line_idx);
+            let m = regex.find_iter(&line).last()?;
+            Some(found(line_idx, &line, m, i > from_line))
+        })
+    } else {
+        let after = line[from_byte..].chars().next().map(|c| from_byte + c.len_utf8());
+        if let Some(m) = after.and_then(|start| regex.find_at(&line, start)) {
+            return Some(found(from_line, &line, m, false));
+        }
+        // The lines below, then round from the top back to this one.
+        (1..=lines).find_map(|i| {
+            let line_idx = (from_line + i) % lines;
+            let line = buffer.line(line_idx);
+            let m = regex.find(&line)?;
+            Some(found(line_idx, &line, m, from_line + i >= lines))
+        })
+    }
+}
+
+/// Char columns of every match of `regex` on `line`, for highlighting.
+pub fn line_matches(line: &str, regex: &Regex) -> Vec<Range<usize>> {
+    regex
+        .find_iter(line)
+        .filter(|m| !m.is_empty())
+        .map(|m| line[..m.start()].chars().count()..line[..m.end()].chars().count())
+        .collect()
+}
+
+/// Splits `text` at the first unescaped `delimiter`, as in `/pattern/` or
+/// `:s/pattern/replacement/`, dropping the backslash from escaped delimiters
+/// in the part before it.
+pub fn split_pattern(text: &str, delimiter: char) -> (String, Option<&str>) {
+    let mut part = String::new();
+    let mut chars = text.char_indices();
+    while let Some((i, c)) = chars.next() {
+        if c == delimiter {
+            return (part, Some(&text[i + c.len_utf8()..]));
+        }
+        if c == '\\' {
+            match chars.next() {
+                Some((_, next)) if next == delimiter => part.push(next),
+                Some((_, next)) => {
+                    part.push('\\');
+                    part.push(next);
+                }
+                None => part.push('\\'),
+            }
+            continue;
+        }
+        part.push(c);
+    }
+    (part, None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXACT: CaseOptions = CaseOptions { ignorecase: false, smartcase: false };
+
+    #[test]
+    fn splits_at_the_first_unescaped_delimiter() {
+        assert_eq!(split_pattern("foo/bar", '/'), ("foo".to_string(), Some("bar")));
+        assert_eq!(split_pattern("foo", '/'), ("foo".to_string(), None));
+        assert_eq!(split_pattern("/", '/'), (String::new(), Some("")));
+        assert_eq!(split_pattern(r"a\/b/c", '/'), ("a/b".to_string(), Some("c")));
+        assert_eq!(split_pattern(r"a\db#", '#'), (r"a\db".to_string(), Some("")));
+        assert_eq!(split_pattern(r"a\", '/'), (r"a\".to_string(), None));
+        assert_eq!(split_pattern("é?ü", '?'), ("é".to_string(), Some("ü")));
+    }
+
+    #[test]
+    fn translates_vim_pattern_items() {
+        let regex = compile(r"\<is\>", EXACT).unwrap();
+        assert_eq!(line_matches("this is", &regex), vec![5..7]);
+        assert!(compile(r"abc\c", EXACT).unwrap().is_match("ABC"));
+        let smart = CaseOptions { ignorecase: true, smartcase: true };
+        assert!(compile("abc", smart).unwrap().is_match("ABC"));
+        assert!(!compile("Abc", smart).unwrap().is_match("ABC"));
+        assert!(!compile(r"abc\C", smart).unwrap().is_match("ABC"));
+    }
+
+    #[test]
+    fn finds_matches_and_wraps() {
+        let buffer = TextBuffer::from("ab\nab");
+        let regex = compile("b", EXACT).unwrap();
+        let found = find(&buffer, &regex, 1, false).unwrap();
+        assert_eq!((found.range, found.wrapped), (4..5, false));
+        let found = find(&buffer, &regex, 4, false).unwrap();
+        assert_eq!((found.range, found.wrapped), (1..2, true));
+        let found = find(&buffer, &regex, 4, true).unwrap();
+        assert_eq!((found.range, found.wrapped), (1..2, false));
+        let found = find(&buffer, &regex, 0, true).unwrap();
+        assert_eq!((found.range, found.wrapped), (4..5, true));
+        assert!(find(&buffer, &compile("c", EXACT).unwrap(), 0, false).is_none());
+    }
+
+    #[test]
+    fn finds_matches_on_the_cursor_line() {
+        let buffer = TextBuffer::from("aaa é b é\nx");
+        let regex = compile("aa", EXACT).unwrap();
+        // Overlapping the match under the cursor.
+        assert_eq!(find(&buffer, &regex, 0, false).unwrap().range, 1..3);
+        let regex = compile("é", EXACT).unwrap();
+        let found = find(&buffer, &regex, 4, false).unwrap();
+        assert_eq!((found.range, found.wrapped), (8..9, false));
+        let found = find(&buffer, &regex, 8, true).unwrap();
+        assert_eq!((found.range, found.wrapped), (4..5, false));
+        let found = find(&buffer, &regex, 8, false).unwrap();
+        assert_eq!((found.range, found.wrapped), (4..5, true));
+        // The only match, under the cursor, is found by going round.
+        let found = find(&buffer, &compile("x", EXACT).unwrap(), 10, true).unwrap();
+        assert_eq!((found.range, found.wrapped), (10..11, true));
+    }
+
+    #[test]
+    fn line_matches_are_char_columns() {
+        let regex = compile("x", EXACT).unwrap();
+        assert_eq!(line_matches("éxé x", &regex), vec![1..2, 4..5]);
+    }
+}
@@ -0,0 +1,189 @@
+use crate::search;
+use regex::Captures;
+
+/// The parts of `:s/pattern/replacement/flags`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Substitute {
+    pub pattern: String,
+    pub replacement: String,
+    pub flags: Flags,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Flags {
+    /// `g`: every match on a line rather than just the first.
+    pub global: bool,
+    /// `c`: ask before each one.
+    pub confirm: bool,
+    /// `n`: only count the matches.
+    pub count_only: bool,
+    /// `i` or `I`: ignore case or don't, whatever 'ignorecase' says.
+    pub ignore_case: Option<bool>,
+}
+
+impl Flags {
+    pub fn parse(flags: &str) -> Result<Self, String> {
+        let mut parsed = Flags::default();
+        for c in flags.chars() {
+            match c {
+                'g' => parsed.global = !parsed.global,
+                'c' => parsed.confirm = true,
+                'n' => parsed.count_only = true,
+                'i' => parsed.ignore_case = Some(true),
+                'I' => parsed.ignore_case = Some(false),
+                // `&` keeps the flags of the last substitute, which is all the
+                // repeat does anyway.
+                '&' => {}
+                _ => return Err(format!("Trailing characters: {}", flags)),
+            }
+        }
+        Ok(parsed)
+    }
+}
+
+impl Substitute {
+    /// Parses what comes after `:s`. The first char is the delimiter, which
+    /// can be any punctuation; a delimiter can appear in the pattern or
+    /// replacement escaped with a backslash. Returns `None` when `args`
+    /// doesn't start with a delimiter, as in `:s` or `:s g`, which repeat the
+    /// last substitute.
+    pub fn parse(args: &str) -> Result<Option<Self>, String> {
+        let mut chars = args.chars();
+        let delimiter = match chars.next() {
+            None => return Ok(None),
+            Some(c) if c.is_alphanumeric() || c == '&' || c == ' ' => return Ok(None),
+            Some(c) if c == '\\' || c == '"' || c == '|' => {
+                return Err("Regular expressions can't be delimited by letters, \\, \" or |".to_string());
+            }
+            Some(c) => c,
+        };
+        let rest = chars.as_str();
+        let (pattern, rest) = search::split_pattern(rest, delimiter);
+        let (replacement, flags) = match rest {
+            Some(rest) => {
+                let (replacement, flags) = search::split_pattern(rest, delimiter);
+                (replacement, flags.unwrap_or(""))
+            }
+            None => (String::new(), ""),
+        };
+        Ok(Some(Substitute { pattern, replacement, flags: Flags::parse(flags.trim())? }))
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Case {
+    Upper,
+    Lower,
+}
+
+impl Case {
+    fn apply(self, c: char, into: &mut String) {
+        match self {
+            Case::Upper => into.extend(c.to_uppercase()),
+            Case::Lower => into.extend(c.to_lowercase()),
+        }
+    }
+}
+
+/// Builds the text a match is replaced with. In `replacement`, `&` and `\0`
+/// stand for the whole match and `\1` to `\9` for its groups. `\r` breaks
+/// the line, `\n` is a NUL char, as in Vim, and `\t` is a tab. `\u` and
+/// `\l` change the case of the next char, `\U` and `\L` everything up to
+/// `\E` or `\e`.
+pub fn expand(replacement: &str, captures: &Captures) -> String {
+    let mut expanded = String::new();
+    let mut next_case = None;
+    let mut case = None;
+    let mut push = |text: &str, next_case: &mut Option<Case>, case: Option<Case>| {
+        for c in text.chars() {
+            match next_case.take().or(case) {
+                Some(case) => case.apply(c, &mut expanded),
+                None => expanded.push(c),
+            }
+        }
+    };
+    let group = |n: usize| captures.get(n).map_or("", |m| m.as_str());
+
+    let mut chars = replacement.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '&' => push(group(0), &mut next_case, case),
+            '\\' => match chars.next() {
+                Some(digit @ '0'..='9') => push(group(digit as usize - '0' as usize), &mut next_case, case),
+                Some('r') => push("\n", &mut next_case, case),
+                Some('n') => push("\0", &mut next_case, case),
+                Some('t') => push("\t", &mut next_case, case),
+                Some('u') => next_case = Some(Case::Upper),
+                Some('l') => next_case = Some(Case::Lower),
+                Some('U') => case = Some(Case::Upper),
+                Some('L') => case = Some(Case::Lower),
+                Some('E' | 'e') => case = None,
+                Some(other) => push(other.encode_utf8(&mut [0; 4]), &mut next_case, case),
+                None => push("\\", &mut next_case, case),
+            },
+            _ => push(c.encode_utf8(&mut [0; 4]), &mut next_case, case),
+        }
+    }
+    expanded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use regex::Regex;
+
+    fn substitute(pattern: &str, replacement: &str, flags: Flags) -> Result<Option<Substitute>, String> {
+        Ok(Some(Substitute { pattern: pattern.to_string(), replacement: replacement.to_string(), flags }))
+    }
+
+    fn replaced(pattern: &str, text: &str, replacement: &str) -> String {
+        let captures = Regex::new(pattern).unwrap().captures(text).unwrap();
+        expand(replacement, &captures)
+    }
+
+    #[test]
+    fn parses_pattern_replacement_and_flags() {
+        assert_eq!(Substitute::parse("/a/b/g"), substitute("a", "b", Flags { global: true, ..Flags::default() }));
+        assert_eq!(Substitute::parse("#a#b#"), substitute("a", "b", Flags::default()));
+        assert_eq!(Substitute::parse("/a/b"), substitute("a", "b", Flags::default()));
+        assert_eq!(Substitute::parse("/a"), substitute("a", "", Flags::default()));
+        assert_eq!(Substitute::parse(r"/a\/b/c\/d/"), substitute("a/b", "c/d", Flags::default()));
+        let flags = Flags { confirm: true, count_only: true, ignore_case: Some(false), ..Flags::default() };
+        assert_eq!(Substitute::parse("/a/b/cnI"), substitute("a", "b", flags));
+    }
+
+    #[test]
+    fn repeats_without_a_delimiter() {
+        assert_eq!(Substitute::parse(""), Ok(None));
+        assert_eq!(Substitute::parse(" g"), Ok(None));
+        assert_eq!(Substitute::parse("&"), Ok(None));
+        assert_eq!(Substitute::parse("g"), Ok(None));
+    }
+
+    #[test]
+    fn rejects_bad_delimiters_and_flags() {
+        assert!(Substitute::parse(r"\a\b\").is_err());
+        assert!(Substitute::parse("|a|b|").is_err());
+        assert_eq!(Substitute::parse("/a/b/x"), Err("Trailing characters: x".to_string()));
+        // `g` twice cancels out, as in Vim.
+        assert_eq!(Flags::parse("gg"), Ok(Flags::default()));
+    }
+
+    #[test]
+    fn expands_matches_and_groups() {
+        assert_eq!(replaced("b+", "abbc", "[&]"), "[bb]");
+        assert_eq!(replaced("(a)(b)", "ab", r"\2\1\0"), "baab");
+        assert_eq!(replaced("(a)|(b)", "a", r"<\2>"), "<>");
+        assert_eq!(replaced("a", "a", r"\&\\"), r"&\");
+        assert_eq!(replaced("a", "a", r"x\ry\nz\t"), "x\ny\0z\t");
+        assert_eq!(replaced("a", "a", "x\\"), "x\\");
+    }
+
+    #[test]
+    fn changes_case() {
+        assert_eq!(replaced("(\\w+) (\\w+)", "foo bar", r"\u\1 \U\2\E!"), "Foo BAR!");
+        assert_eq!(replaced("\\w+", "FOO", r"\L&"), "foo");
+        assert_eq!(replaced("\\w+", "foo", r"\U\l&"), "fOO");
+        assert_eq!(replaced("\\w+", "straße", r"\U&"), "STRASSE");
+    }
+}
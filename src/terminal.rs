@@ -35,6 +35,14 @@ impl Terminal {
         Ok(())
     }
 
+    /// Writes a raw escape sequence, like the OSC 52 ones that set the
+    /// clipboard.
+    pub fn write_escape(&self, sequence: &str) -> io::Result<()> {
+        let mut out = stdout();
+        out.write_all(sequence.as_bytes())?;
+        out.flush()
+    }
+
     pub fn flush(&self) -> io::Result<()> {
         stdout().flush()?;
         Ok(())
@@ -0,0 +1,154 @@
+use ropey::Rope;
+use std::ops::Range;
+
+/// Document text, stored as a rope so edits stay cheap on large files.
+///
+/// Lines are separated by a single `\n` with no terminator after the last
+/// line, so `len_lines` is always the number of lines the editor shows (at
+/// least 1).
+/// Positions are char indices unless a method says otherwise.
+#[derive(Clone, Default)]
+pub struct TextBuffer {
+    rope: Rope,
+}
+
+impl TextBuffer {
+    pub fn len_chars(&self) -> usize {
+        self.rope.len_chars()
+    }
+
+    pub fn len_lines(&self) -> usize {
+        self.rope.len_lines()
+    }
+
+    /// Returns the text of line `line_idx` without its line break.
+    pub fn line(&self, line_idx: usize) -> String {
+        let mut line = self.rope.line(line_idx).to_string();
+        if line.ends_with('\n') {
+            line.pop();
+        }
+        line
+    }
+
+    /// Length of line `line_idx` in chars, not counting the line break.
+    pub fn line_len(&self, line_idx: usize) -> usize {
+        let line = self.rope.line(line_idx);
+        let len = line.len_chars();
+        if len > 0 && line.char(len - 1) == '\n' {
+            len - 1
+        } else {
+            len
+        }
+    }
+
+    pub fn line_to_char(&self, line_idx: usize) -> usize {
+        self.rope.line_to_char(line_idx)
+    }
+
+    pub fn char_to_line(&self, char_idx: usize) -> usize {
+        self.rope.char_to_line(char_idx)
+    }
+
+    pub fn char_to_byte(&self, char_idx: usize) -> usize {
+        self.rope.char_to_byte(char_idx)
+    }
+
+    pub fn byte_to_char(&self, byte_idx: usize) -> usize {
+        self.rope.byte_to_char(byte_idx)
+    }
+
+    /// Converts a (line, column) pair, with the column in chars, to a char index.
+    pub fn pos_to_char(&self, line_idx: usize, col: usize) -> usize {
+        self.line_to_char(line_idx) + col.min(self.line_len(line_idx))
+    }
+
+    /// Converts a char index to a (line, column) pair.
+    pub fn char_to_pos(&self, char_idx: usize) -> (usize, usize) {
+        let line_idx = self.char_to_line(char_idx);
+        (line_idx, char_idx - self.line_to_char(line_idx))
+    }
+
+    pub fn char(&self, char_idx: usize) -> char {
+        self.rope.char(char_idx)
+    }
+
+    pub fn slice(&self, range: Range<usize>) -> String {
+        self.rope.slice(range).to_string()
+    }
+
+    /// How inserting `text` at `char_idx` would move lines around, if it
+    /// adds any.
+    pub fn insert_shift(&self, char_idx: usize, text: &str) -> Option<LineShift> {
+        let inserted = text.matches('\n').count();
+        if inserted == 0 {
+            return None;
+        }
+        let (line, col) = self.char_to_pos(char_idx);
+        // Whole lines put in above `line` push it down; otherwise it's split
+        // and keeps its number.
+        let at = if col == 0 && text.ends_with('\n') { line } else { line + 1 };
+        Some(LineShift { at, deleted: 0, inserted })
+    }
+
+    /// How removing `range` would move lines around, if it takes any line
+    /// breaks with it.
+    pub fn remove_shift(&self, range: Range<usize>) -> Option<LineShift> {
+        let (start_line, start_col) = self.char_to_pos(range.start);
+        let (end_line, end_col) = self.char_to_pos(range.end);
+        if end_line == start_line {
+            return None;
+        }
+        // Removing from the start of one line to the start of another takes
+        // whole lines; otherwise the lines after the first are joined onto it.
+        let at = if start_col == 0 && end_col == 0 { start_line } else { start_line + 1 };
+        Some(LineShift { at, deleted: end_line - start_line, inserted: 0 })
+    }
+
+    pub fn insert(&mut self, char_idx: usize, text: &str) {
+        self.rope.insert(char_idx, text);
+    }
+
+    pub fn remove(&mut self, range: Range<usize>) {
+        if range.start < range.end {
+            self.rope.remove(range);
+        }
+    }
+}
+
+/// Lines that an edit added or took away, for keeping line numbers pointing
+/// at the same text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineShift {
+    /// The first line added or taken away.
+    pub at: usize,
+    pub deleted: usize,
+    pub inserted: usize,
+}
+
+impl LineShift {
+    /// Where `line` is after the edit, or `None` if it was taken away.
+    pub fn apply(self, line: usize) -> Option<usize> {
+        if line < self.at {
+            Some(line)
+        } else if line < self.at + self.deleted {
+            None
+        } else {
+            Some(line - self.deleted + self.inserted)
+        }
+    }
+}
+
+impl From<&str> for TextBuffer {
+    fn from(text: &str) -> Self {
+        TextBuffer { rope: Rope::from_str(text) }
+    }
+}
+
+impl std::fmt::Display for TextBuffer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for chunk in self.rope.chunks() {
+            f.write_str(chunk)?;
+        }
+        Ok(())
+    }
+}
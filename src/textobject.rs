@@ -0,0 +1,443 @@
+use crate::motion::{self, CharClass, IsKeyword, Words};
+use crate::text_buffer::TextBuffer;
+use regex::Regex;
+use std::collections::HashMap;
+use std::ops::{Range, RangeInclusive};
+use std::sync::OnceLock;
+
+/// How many lines either side of the cursor `it`/`at` look for tags in, so
+/// a large file isn't scanned whole.
+const TAG_SCAN_LINES: usize = 1000;
+
+/// What a text object is looked for around.
+pub struct Context<'a> {
+    pub buffer: &'a TextBuffer,
+    pub iskeyword: &'a IsKeyword,
+    /// Char index of the cursor.
+    pub cursor: usize,
+    pub count: usize,
+    /// Typed with `i` rather than `a`: leaves out the white space, quotes,
+    /// brackets or tags around the object.
+    pub inner: bool,
+}
+
+/// The text a text object covers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ObjectRange {
+    /// Chars, end exclusive.
+    Chars(Range<usize>),
+    /// Whole lines.
+    Lines(RangeInclusive<usize>),
+}
+
+/// Finds an object's text around the cursor, or `None` if there isn't one.
+pub type TextObject = fn(&Context) -> Option<ObjectRange>;
+
+/// The text objects that can follow `i` and `a`, by key. Filetype code can
+/// add its own, such as a function or an argument, with `register`.
+pub struct TextObjects {
+    objects: HashMap<char, TextObject>,
+}
+
+impl Default for TextObjects {
+    fn default() -> Self {
+        let mut objects = TextObjects { objects: HashMap::new() };
+        objects.register('w', |cx| word(cx, false));
+        objects.register('W', |cx| word(cx, true));
+        objects.register('s', sentence);
+        objects.register('p', paragraph);
+        objects.register('"', |cx| quoted(cx, '"'));
+        objects.register('\'', |cx| quoted(cx, '\''));
+        objects.register('`', |cx| quoted(cx, '`'));
+        objects.register('(', |cx| bracketed(cx, '(', ')'));
+        objects.register(')', |cx| bracketed(cx, '(', ')'));
+        objects.register('b', |cx| bracketed(cx, '(', ')'));
+        objects.register('[', |cx| bracketed(cx, '[', ']'));
+        objects.register(']', |cx| bracketed(cx, '[', ']'));
+        objects.register('{', |cx| bracketed(cx, '{', '}'));
+        objects.register('}', |cx| bracketed(cx, '{', '}'));
+        objects.register('B', |cx| bracketed(cx, '{', '}'));
+        objects.register('<', |cx| bracketed(cx, '<', '>'));
+        objects.register('>', |cx| bracketed(cx, '<', '>'));
+        objects.register('t', tag);
+        objects
+    }
+}
+
+impl TextObjects {
+    /// Makes `object` what `i{key}` and `a{key}` select, in place of any
+    /// object already on that key.
+    pub fn register(&mut self, key: char, object: TextObject) {
+        self.objects.insert(key, object);
+    }
+
+    pub fn get(&self, key: char) -> Option<TextObject> {
+        self.objects.get(&key).copied()
+    }
+}
+
+fn is_blank(c: char) -> bool {
+    c == ' ' || c == '\t'
+}
+
+/// `iw`/`aw`, or `iW`/`aW` with `big`. A run of blanks is a word too for
+/// `iw`; `aw` takes the blanks after the word, or before it if there are
+/// none after.
+fn word(cx: &Context, big: bool) -> Option<ObjectRange> {
+    let words = Words { iskeyword: cx.iskeyword, big };
+    let (line, col) = cx.buffer.char_to_pos(cx.cursor);
+    let chars: Vec<char> = cx.buffer.line(line).chars().collect();
+    let len = chars.len();
+    if len == 0 {
+        return None;
+    }
+    let col = col.min(len - 1);
+    let class = |i: usize| words.class(chars[i]);
+    let run_end = |i: usize| (i..len).find(|&j| class(j) != class(i)).unwrap_or(len);
+    let on_blank = class(col) == CharClass::Blank;
+
+    let mut start = (0..col).rev().take_while(|&i| class(i) == class(col)).last().unwrap_or(col);
+    let mut end = run_end(col);
+    if cx.inner {
+        for _ in 1..cx.count {
+            if end == len {
+                return None;
+            }
+            end = run_end(end);
+        }
+    } else {
+        if on_blank {
+            if end == len {
+                return None;
+            }
+            end = run_end(end);
+        }
+        for _ in 1..cx.count {
+            if end < len && class(end) == CharClass::Blank {
+                end = run_end(end);
+            }
+            if end == len {
+                return None;
+            }
+            end = run_end(end);
+        }
+        if !on_blank {
+            if end < len && class(end) == CharClass::Blank {
+                end = run_end(end);
+            } else {
+                // Not the indent, though.
+                let before = (0..start).rev().take_while(|&i| class(i) == CharClass::Blank).last().unwrap_or(start);
+                if before > 0 {
+                    start = before;
+                }
+            }
+        }
+    }
+    let line_start = cx.buffer.line_to_char(line);
+    Some(ObjectRange::Chars(line_start + start..line_start + end))
+}
+
+/// `is`/`as`. `as` takes the blanks after the sentence, or before it if
+/// there are none after.
+fn sentence(cx: &Context) -> Option<ObjectRange> {
+    let buffer = cx.buffer;
+    let len = buffer.len_chars();
+    if len == 0 {
+        return None;
+    }
+    let mut start = motion::sentence_backward(buffer, cx.cursor.min(len - 1) + 1);
+    let mut end = start;
+    for _ in 0..cx.count {
+        if end >= len {
+            return None;
+        }
+        end = motion::sentence_forward(buffer, end);
+    }
+    while end > start && buffer.char(end - 1).is_whitespace() {
+        end -= 1;
+    }
+    if cx.inner {
+        return Some(ObjectRange::Chars(start..end));
+    }
+    let inner_end = end;
+    while end < len && is_blank(buffer.char(end)) {
+        end += 1;
+    }
+    if end == inner_end {
+        while start > 0 && is_blank(buffer.char(start - 1)) {
+            start -= 1;
+        }
+    }
+    Some(ObjectRange::Chars(start..end))
+}
+
+/// `ip`/`ap`: lines up to the next empty line, or a run of empty lines.
+/// `ap` takes the empty lines after the paragraph too, or before it if
+/// it's the last one. Counts take in that many more paragraphs.
+fn paragraph(cx: &Context) -> Option<ObjectRange> {
+    let buffer = cx.buffer;
+    let last = buffer.len_lines() - 1;
+    let (line, _) = buffer.char_to_pos(cx.cursor);
+    let empty = |l: usize| buffer.line_len(l) == 0;
+    let run_end = |l: usize| (l..last).find(|&e| empty(e + 1) != empty(l)).unwrap_or(last);
+
+    let mut start = (0..line).rev().take_while(|&l| empty(l) == empty(line)).last().unwrap_or(line);
+    let wanted = if cx.inner { cx.count } else { cx.count * 2 };
+    let mut end = run_end(line);
+    let mut runs = 1;
+    while runs < wanted && end < last {
+        end = run_end(end + 1);
+        runs += 1;
+    }
+    if runs < wanted {
+        if cx.inner || runs + 1 < wanted || empty(line) {
+            return None;
+        }
+        while start > 0 && empty(start - 1) {
+            start -= 1;
+        }
+    }
+    Some(ObjectRange::Lines(start..=end))
+}
+
+/// `i"`/`a"` and the other quotes, within the cursor's line. Which quotes
+/// pair up is worked out from the start of the line, skipping ones escaped
+/// with a backslash. When the cursor isn't inside a string the next one on
+/// the line is used.
+fn quoted(cx: &Context, quote: char) -> Option<ObjectRange> {
+    let (line, col) = cx.buffer.char_to_pos(cx.cursor);
+    let chars: Vec<char> = cx.buffer.line(line).chars().collect();
+    let mut quotes = Vec::new();
+    let mut escaped = false;
+    for (i, &c) in chars.iter().enumerate() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == quote {
+            quotes.push(i);
+        }
+    }
+    let pairs = || quotes.chunks_exact(2).map(|pair| (pair[0], pair[1]));
+    let (open, close) = pairs()
+        .find(|&(open, close)| open <= col && col <= close)
+        .or_else(|| pairs().find(|&(open, _)| open > col))?;
+
+    let (mut start, mut end) = if cx.inner { (open + 1, close) } else { (open, close + 1) };
+    if !cx.inner {
+        let inner_end = end;
+        while end < chars.len() && is_blank(chars[end]) {
+            end += 1;
+        }
+        if end == inner_end {
+            while start > 0 && is_blank(chars[start - 1]) {
+                start -= 1;
+            }
+        }
+    }
+    let line_start = cx.buffer.line_to_char(line);
+    Some(ObjectRange::Chars(line_start + start..line_start + end))
+}
+
+/// `i(`/`a(` and the other brackets: the `count`th pair around the cursor,
+/// skipping nested pairs. A block whose brackets are on lines of their own
+/// is, inside, the whole lines between them.
+fn bracketed(cx: &Context, open: char, close: char) -> Option<ObjectRange> {
+    let buffer = cx.buffer;
+    let len = buffer.len_chars();
+    if len == 0 {
+        return None;
+    }
+    let cursor = cx.cursor.min(len - 1);
+
+    // A close bracket under the cursor isn't counted, so its own pair is found.
+    let mut depth = 0;
+    let mut remaining = cx.count;
+    let mut start = None;
+    for i in (0..=cursor).rev() {
+        match buffer.char(i) {
+            c if c == close && i != cursor => depth += 1,
+            c if c == open && depth > 0 => depth -= 1,
+            c if c == open => {
+                remaining -= 1;
+                if remaining == 0 {
+                    start = Some(i);
+                    break;
+                }
+            }
+            _ => {}
+        }
+    }
+    let start = start?;
+    let mut depth = 0;
+    let mut end = None;
+    for i in start + 1..len {
+        match buffer.char(i) {
+            c if c == open => depth += 1,
+            c if c == close && depth > 0 => depth -= 1,
+            c if c == close => {
+                end = Some(i);
+                break;
+            }
+            _ => {}
+        }
+    }
+    let end = end?;
+    if !cx.inner {
+        return Some(ObjectRange::Chars(start..end + 1));
+    }
+    let (open_line, _) = buffer.char_to_pos(start);
+    let (close_line, close_col) = buffer.char_to_pos(end);
+    let close_on_own_line = buffer.line(close_line).chars().take(close_col).all(char::is_whitespace);
+    if close_line > open_line + 1 && buffer.char(start + 1) == '\n' && close_on_own_line {
+        return Some(ObjectRange::Lines(open_line + 1..=close_line - 1));
+    }
+    Some(ObjectRange::Chars(start + 1..end))
+}
+
+/// `it`/`at`: the `count`th XML or HTML element around the cursor, inside
+/// or including its tags. Tags left unclosed, like `<br>`, are skipped.
+fn tag(cx: &Context) -> Option<ObjectRange> {
+    static TAGS: OnceLock<Regex> = OnceLock::new();
+    let tags = TAGS.get_or_init(|| Regex::new(r"<(/?)([A-Za-z][\w:.-]*)[^>]*?(/?)>").expect("tag pattern is valid"));
+    let buffer = cx.buffer;
+    let cursor = cx.cursor.min(buffer.len_chars());
+    let line = buffer.char_to_line(cursor);
+    let first = buffer.line_to_char(line.saturating_sub(TAG_SCAN_LINES));
+    let last = (line + TAG_SCAN_LINES).min(buffer.len_lines() - 1);
+    let window = first..buffer.line_to_char(last) + buffer.line_len(last);
+    let text = buffer.slice(window);
+    let base = buffer.char_to_byte(first);
+    let cursor = buffer.char_to_byte(cursor) - base;
+
+    let mut open: Vec<(&str, Range<usize>)> = Vec::new();
+    let mut around = Vec::new();
+    for tag in tags.captures_iter(&text) {
+        let range = tag.get(0).map_or(0..0, |m| m.range());
+        let name = tag.get(2).map_or("", |m| m.as_str());
+        if !tag[1].is_empty() {
+            let Some(at) = open.iter().rposition(|(open_name, _)| open_name.eq_ignore_ascii_case(name)) else {
+                continue;
+            };
+            let opening = open[at].1.clone();
+            open.truncate(at);
+            if opening.start <= cursor && cursor < range.end {
+                around.push((opening, range));
+            }
+        } else if tag[3].is_empty() {
+            open.push((name, range));
+        }
+    }
+    // Innermost first.
+    around.sort_by_key(|(opening, _)| std::cmp::Reverse(opening.start));
+    let (opening, closing) = around.into_iter().nth(cx.count - 1)?;
+    let bytes = if cx.inner { opening.end..closing.start } else { opening.start..closing.end };
+    Some(ObjectRange::Chars(buffer.byte_to_char(base + bytes.start)..buffer.byte_to_char(base + bytes.end)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn select(text: &str, cursor: usize, count: usize, object: &str) -> Option<ObjectRange> {
+        let mut keys = object.chars();
+        let inner = keys.next() == Some('i');
+        let key = keys.next().unwrap();
+        let buffer = TextBuffer::from(text);
+        let iskeyword = IsKeyword::default();
+        let cx = Context { buffer: &buffer, iskeyword: &iskeyword, cursor, count, inner };
+        TextObjects::default().get(key).unwrap()(&cx)
+    }
+
+    fn chars(range: Range<usize>) -> Option<ObjectRange> {
+        Some(ObjectRange::Chars(range))
+    }
+
+    #[test]
+    fn words_and_blanks() {
+        assert_eq!(select("foo   bar", 4, 1, "iw"), chars(3..6));
+        assert_eq!(select("foo   bar", 4, 1, "aw"), chars(3..9));
+        assert_eq!(select("foo bar", 1, 1, "aw"), chars(0..4));
+        // No blanks after the last word, so the ones before it are taken.
+        assert_eq!(select("foo bar", 5, 1, "aw"), chars(3..7));
+        assert_eq!(select("  foo", 3, 1, "aw"), chars(2..5));
+        assert_eq!(select("foo.bar", 4, 1, "iW"), chars(0..7));
+        assert_eq!(select("", 0, 1, "iw"), None);
+    }
+
+    #[test]
+    fn word_counts() {
+        assert_eq!(select("foo bar baz", 0, 3, "iw"), chars(0..7));
+        assert_eq!(select("a b c", 0, 2, "aw"), chars(0..4));
+        assert_eq!(select("a b", 0, 4, "iw"), None);
+        assert_eq!(select("a b", 0, 3, "aw"), None);
+    }
+
+    #[test]
+    fn nested_brackets() {
+        let text = "f(a(b)c)";
+        assert_eq!(select(text, 4, 1, "i("), chars(4..5));
+        assert_eq!(select(text, 3, 1, "i("), chars(4..5));
+        assert_eq!(select(text, 5, 1, "i)"), chars(4..5));
+        assert_eq!(select(text, 1, 1, "ib"), chars(2..7));
+        assert_eq!(select(text, 7, 1, "a("), chars(1..8));
+        assert_eq!(select(text, 4, 2, "i("), chars(2..7));
+        assert_eq!(select(text, 4, 3, "i("), None);
+        assert_eq!(select(text, 0, 1, "i("), None);
+    }
+
+    #[test]
+    fn block_on_lines_of_its_own_is_linewise() {
+        let text = "fn f() {\n    x\n}";
+        assert_eq!(select(text, 13, 1, "i{"), Some(ObjectRange::Lines(1..=1)));
+        assert_eq!(select(text, 13, 1, "a{"), chars(7..16));
+        assert_eq!(select("{ x\n}", 2, 1, "iB"), chars(1..4));
+    }
+
+    #[test]
+    fn quotes() {
+        let text = r#"say "a\"b" ok"#;
+        assert_eq!(select(text, 6, 1, "i\""), chars(5..9));
+        assert_eq!(select(text, 6, 1, "a\""), chars(4..11));
+        // Before the string, the next one on the line is used.
+        assert_eq!(select(r#"x = "ab""#, 0, 1, "i\""), chars(5..7));
+        assert_eq!(select(r#"x = "ab""#, 0, 1, "a\""), chars(3..8));
+        assert_eq!(select(r#""ab" x"#, 5, 1, "i\""), None);
+        assert_eq!(select("'a' 'b'", 4, 1, "i'"), chars(5..6));
+    }
+
+    #[test]
+    fn tags() {
+        let text = "<a href=\"x\"><b>hi</b><br></A>";
+        assert_eq!(select(text, 15, 1, "it"), chars(15..17));
+        assert_eq!(select(text, 15, 1, "at"), chars(12..21));
+        assert_eq!(select(text, 15, 2, "it"), chars(12..25));
+        assert_eq!(select(text, 22, 1, "it"), chars(12..25));
+        assert_eq!(select(text, 15, 3, "it"), None);
+        assert_eq!(select("<br>", 1, 1, "it"), None);
+    }
+
+    #[test]
+    fn paragraphs() {
+        let text = "a\nb\n\nc\n\n\nd\ne";
+        assert_eq!(select(text, 0, 1, "ip"), Some(ObjectRange::Lines(0..=1)));
+        assert_eq!(select(text, 0, 1, "ap"), Some(ObjectRange::Lines(0..=2)));
+        assert_eq!(select(text, 0, 2, "ip"), Some(ObjectRange::Lines(0..=2)));
+        // The last paragraph takes the empty lines before it.
+        assert_eq!(select(text, 10, 1, "ap"), Some(ObjectRange::Lines(4..=7)));
+        assert_eq!(select(text, 10, 2, "ap"), None);
+    }
+
+    #[test]
+    fn register_replaces_an_object() {
+        let mut objects = TextObjects::default();
+        objects.register('w', |cx| Some(ObjectRange::Chars(cx.cursor..cx.cursor + 1)));
+        objects.register('f', |_| None);
+        let buffer = TextBuffer::from("foo bar");
+        let iskeyword = IsKeyword::default();
+        let cx = Context { buffer: &buffer, iskeyword: &iskeyword, cursor: 2, count: 1, inner: true };
+        assert_eq!(objects.get('w').unwrap()(&cx), chars(2..3));
+        assert_eq!(objects.get('f').unwrap()(&cx), None);
+        assert!(objects.get('z').is_none());
+    }
+}
@@ -0,0 +1,466 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A single change to the buffer, with enough text to reverse it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Edit {
+    Insert { at: usize, text: String },
+    Delete { at: usize, text: String },
+    /// A byte in the hex view changing from `old` to `new`, where `None`
+    /// means there is no byte at `at`.
+    Byte { at: usize, old: Option<u8>, new: Option<u8> },
+}
+
+impl Edit {
+    /// The edit that takes this one back.
+    fn inverse(&self) -> Edit {
+        match self {
+            Edit::Insert { at, text } => Edit::Delete { at: *at, text: text.clone() },
+            Edit::Delete { at, text } => Edit::Insert { at: *at, text: text.clone() },
+            Edit::Byte { at, old, new } => Edit::Byte { at: *at, old: *new, new: *old },
+        }
+    }
+
+    fn at(&self) -> usize {
+        match self {
+            Edit::Insert { at, .. } | Edit::Delete { at, .. } | Edit::Byte { at, .. } => *at,
+        }
+    }
+}
+
+/// What `undo`, `redo`, `:earlier` or `:later` asks of the buffer: the edits
+/// to make, in order, and where to put the cursor after them.
+pub struct Travel {
+    pub edits: Vec<Edit>,
+    pub cursor: usize,
+}
+
+/// One undoable step: every edit made by a single command or Insert-mode
+/// session. Node 0 is the root and stands for the buffer as first loaded.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct UndoNode {
+    parent: usize,
+    /// Child that `redo` goes to; the most recently created or undone one.
+    redo_child: Option<usize>,
+    edits: Vec<Edit>,
+    /// Cursor char index before the step was made.
+    cursor: usize,
+    /// Seconds since the Unix epoch when the step was made.
+    time: u64,
+}
+
+/// Undo history kept as a tree, so undoing and then making a new change
+/// doesn't throw the undone changes away. Node indices double as change
+/// numbers, which `:earlier`/`:later` step through in creation order.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UndoTree {
+    nodes: Vec<UndoNode>,
+    current: usize,
+    /// Node whose state matches the file on disk, if any.
+    saved: Option<usize>,
+    #[serde(skip)]
+    pending: Vec<Edit>,
+    #[serde(skip)]
+    pending_cursor: usize,
+}
+
+/// On-disk form of an undo file; `hash` ties it to the text it was saved with.
+#[derive(Serialize, Deserialize)]
+struct UndoFile {
+    version: u32,
+    hash: String,
+    tree: UndoTree,
+}
+
+const UNDO_FILE_VERSION: u32 = 1;
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+/// How far `:earlier`/`:later` should travel.
+pub enum UndoDistance {
+    Steps(usize),
+    Seconds(u64),
+}
+
+impl UndoDistance {
+    /// Parses the argument to `:earlier`/`:later`: a count, or a count with an
+    /// `s`, `m`, `h` or `d` suffix. An empty argument means one step.
+    pub fn parse(arg: &str) -> Option<Self> {
+        let arg = arg.trim();
+        if arg.is_empty() {
+            return Some(UndoDistance::Steps(1));
+        }
+        let (number, unit) = match arg.find(|c: char| !c.is_ascii_digit()) {
+            Some(i) => arg.split_at(i),
+            None => (arg, ""),
+        };
+        let n: u64 = number.parse().ok()?;
+        match unit {
+            "" => Some(UndoDistance::Steps(n as usize)),
+            "s" => Some(UndoDistance::Seconds(n)),
+            "m" => Some(UndoDistance::Seconds(n * 60)),
+            "h" => Some(UndoDistance::Seconds(n * 60 * 60)),
+            "d" => Some(UndoDistance::Seconds(n * 60 * 60 * 24)),
+            _ => None,
+        }
+    }
+}
+
+impl UndoTree {
+    /// Starts a history for a buffer. `saved` says whether the buffer as it
+    /// stands matches a file on disk.
+    pub fn new(saved: bool) -> Self {
+        UndoTree {
+            nodes: vec![UndoNode {
+                parent: 0,
+                redo_child: None,
+                edits: Vec::new(),
+                cursor: 0,
+                time: now(),
+            }],
+            current: 0,
+            saved: saved.then_some(0),
+            pending: Vec::new(),
+            pending_cursor: 0,
+        }
+    }
+
+    /// Adds an edit that has already been applied to the buffer to the step
+    /// being built. `cursor` is where the cursor was before the edit.
+    pub fn record(&mut self, edit: Edit, cursor: usize) {
+        if self.pending.is_empty() {
+            self.pending_cursor = cursor;
+        }
+        self.pending.push(edit);
+    }
+
+    /// Closes the step being built, making it one unit for undo.
+    pub fn commit(&mut self) {
+        if self.pending.is_empty() {
+            return;
+        }
+        let index = self.nodes.len();
+        self.nodes.push(UndoNode {
+            parent: self.current,
+            redo_child: None,
+            edits: std::mem::take(&mut self.pending),
+            cursor: self.pending_cursor,
+            time: now(),
+        });
+        self.nodes[self.current].redo_child = Some(index);
+        self.current = index;
+    }
+
+    /// Change number of the current state; 0 is the original text.
+    pub fn seq(&self) -> usize {
+        self.current
+    }
+
+    pub fn is_saved(&self) -> bool {
+        self.pending.is_empty() && self.saved == Some(self.current)
+    }
+
+    pub fn mark_saved(&mut self) {
+        self.commit();
+        self.saved = Some(self.current);
+    }
+
+    /// Forgets which state is on disk, for changes the tree doesn't track
+    /// (like switching the file format).
+    pub fn forget_saved(&mut self) {
+        self.saved = None;
+    }
+
+    /// Reverts the current step. Returns the edits that do it, or `None` if
+    /// there is nothing to undo.
+    pub fn undo(&mut self) -> Option<Travel> {
+        self.commit();
+        if self.current == 0 {
+            return None;
+        }
+        let node = &self.nodes[self.current];
+        let edits = node.edits.iter().rev().map(Edit::inverse).collect();
+        let cursor = node.cursor;
+        let parent = node.parent;
+        self.nodes[parent].redo_child = Some(self.current);
+        self.current = parent;
+        Some(Travel { edits, cursor })
+    }
+
+    /// Reapplies the most recently undone step below the current state.
+    pub fn redo(&mut self) -> Option<Travel> {
+        self.commit();
+        let child = self.nodes[self.current].redo_child?;
+        let node = &self.nodes[child];
+        let cursor = node.edits.first().map_or(0, Edit::at);
+        let edits = node.edits.clone();
+        self.current = child;
+        Some(Travel { edits, cursor })
+    }
+
+    /// Moves to the state after change `target`, undoing up to the common
+    /// ancestor and redoing down from there.
+    fn goto(&mut self, target: usize) -> Option<Travel> {
+        self.commit();
+        let target = target.min(self.nodes.len() - 1);
+        if target == self.current {
+            return None;
+        }
+
+        let mut down = Vec::new();
+        let mut node = target;
+        while !self.is_ancestor(node, self.current) {
+            down.push(node);
+            node = self.nodes[node].parent;
+        }
+        let mut steps = Vec::new();
+        while self.current != node {
+            steps.extend(self.undo());
+        }
+        for &child in down.iter().rev() {
+            self.nodes[self.current].redo_child = Some(child);
+            steps.extend(self.redo());
+        }
+        let cursor = steps.last()?.cursor;
+        Some(Travel { edits: steps.into_iter().flat_map(|step| step.edits).collect(), cursor })
+    }
+
+    fn is_ancestor(&self, ancestor: usize, mut node: usize) -> bool {
+        loop {
+            if node == ancestor {
+                return true;
+            }
+            if node == 0 {
+                return false;
+            }
+            node = self.nodes[node].parent;
+        }
+    }
+
+    /// `:earlier`: goes back in time, across branches.
+    pub fn earlier(&mut self, distance: UndoDistance) -> Option<Travel> {
+        self.commit();
+        let target = match distance {
+            UndoDistance::Steps(n) => self.current.saturating_sub(n),
+            UndoDistance::Seconds(secs) => {
+                let time = self.nodes[self.current].time.saturating_sub(secs);
+                (0..=self.current).rev().find(|&i| self.nodes[i].time <= time).unwrap_or(0)
+            }
+        };
+        self.goto(target)
+    }
+
+    /// `:later`: goes forward in time, across branches.
+    pub fn later(&mut self, distance: UndoDistance) -> Option<Travel> {
+        self.commit();
+        let last = self.nodes.len() - 1;
+        let target = match distance {
+            UndoDistance::Steps(n) => (self.current + n).min(last),
+            UndoDistance::Seconds(secs) => {
+                let time = self.nodes[self.current].time + secs;
+                (self.current..=last).rev().find(|&i| self.nodes[i].time <= time).unwrap_or(self.current)
+            }
+        };
+        self.goto(target)
+    }
+
+    /// Writes the history to `path`, tagged with a hash of `text` so it is only
+    /// picked up again for the same file contents.
+    pub fn save(&mut self, path: &Path, text: &str) -> io::Result<()> {
+        self.commit();
+        let file = UndoFile {
+            version: UNDO_FILE_VERSION,
+            hash: content_hash(text),
+            tree: UndoTree {
+                nodes: self.nodes.clone(),
+                current: self.current,
+                saved: self.saved,
+                pending: Vec::new(),
+                pending_cursor: 0,
+            },
+        };
+        let content = toml::to_string(&file).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, content)
+    }
+
+    /// Reads a history written by `save`. Returns `None` if there is no undo
+    /// file or it was written for different contents.
+    pub fn load(path: &Path, text: &str) -> Option<Self> {
+        let content = fs::read_to_string(path).ok()?;
+        let file: UndoFile = toml::from_str(&content).ok()?;
+        if file.version != UNDO_FILE_VERSION || file.hash != content_hash(text) {
+            return None;
+        }
+        let tree = file.tree;
+        let valid = tree.current < tree.nodes.len()
+            && tree.nodes.iter().enumerate().all(|(i, n)| n.parent < i.max(1));
+        valid.then_some(tree)
+    }
+}
+
+/// FNV-1a over the text, stable across builds unlike `DefaultHasher`.
+fn content_hash(text: &str) -> String {
+    let hash = text.bytes().fold(0xcbf2_9ce4_8422_2325u64, |hash, b| {
+        (hash ^ b as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    });
+    format!("{:016x}", hash)
+}
+
+/// Undo file for `file` inside `dir`, named after the file's absolute path
+/// with separators turned into `%`, the same way Vim names them.
+pub fn undo_file_path(dir: &Path, file: &str) -> PathBuf {
+    let absolute = fs::canonicalize(file).unwrap_or_else(|_| PathBuf::from(file));
+    let name = absolute.to_string_lossy().replace(std::path::MAIN_SEPARATOR, "%");
+    dir.join(name)
+}
+
+/// Default undo directory: `undo` next to where swap files would go, under
+/// the user's state directory.
+pub fn default_undo_dir() -> PathBuf {
+    dirs::state_dir()
+        .or_else(dirs::data_local_dir)
+        .unwrap_or_else(std::env::temp_dir)
+        .join("fervim")
+        .join("undo")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Makes `edit` to `text`, which is kept to ASCII so chars are bytes.
+    fn apply(text: &mut String, edit: &Edit) {
+        match edit {
+            Edit::Insert { at, text: inserted } => text.insert_str(*at, inserted),
+            Edit::Delete { at, text: deleted } => text.replace_range(*at..*at + deleted.len(), ""),
+            Edit::Byte { .. } => unreachable!("text histories have no byte edits"),
+        }
+    }
+
+    fn travel(text: &mut String, travel: Option<Travel>) -> Option<usize> {
+        let travel = travel?;
+        for edit in &travel.edits {
+            apply(text, edit);
+        }
+        Some(travel.cursor)
+    }
+
+    /// Makes and records one step of edits.
+    fn change(tree: &mut UndoTree, text: &mut String, edits: Vec<Edit>) {
+        for edit in edits {
+            apply(text, &edit);
+            tree.record(edit, 0);
+        }
+        tree.commit();
+    }
+
+    fn insert(at: usize, text: &str) -> Edit {
+        Edit::Insert { at, text: text.to_string() }
+    }
+
+    fn delete(at: usize, text: &str) -> Edit {
+        Edit::Delete { at, text: text.to_string() }
+    }
+
+    #[test]
+    fn undoes_and_redoes_whole_steps() {
+        let mut tree = UndoTree::new(true);
+        let mut text = "abc".to_string();
+        change(&mut tree, &mut text, vec![insert(3, "d"), insert(4, "e")]);
+        change(&mut tree, &mut text, vec![delete(0, "ab")]);
+        assert_eq!(text, "cde");
+
+        assert_eq!(travel(&mut text, tree.undo()), Some(0));
+        assert_eq!(text, "abcde");
+        assert_eq!(travel(&mut text, tree.undo()), Some(0));
+        assert_eq!(text, "abc");
+        assert!(tree.undo().is_none());
+        assert!(tree.is_saved());
+
+        assert_eq!(travel(&mut text, tree.redo()), Some(3));
+        assert_eq!(text, "abcde");
+        assert_eq!(travel(&mut text, tree.redo()), Some(0));
+        assert_eq!(text, "cde");
+        assert!(tree.redo().is_none());
+        assert!(!tree.is_saved());
+    }
+
+    #[test]
+    fn keeps_undone_branches() {
+        let mut tree = UndoTree::new(true);
+        let mut text = String::new();
+        change(&mut tree, &mut text, vec![insert(0, "one")]);
+        change(&mut tree, &mut text, vec![insert(3, " two")]);
+        travel(&mut text, tree.undo());
+        change(&mut tree, &mut text, vec![insert(3, " three")]);
+        assert_eq!((text.as_str(), tree.seq()), ("one three", 3));
+
+        // `:earlier` goes back through change 2 on the other branch.
+        travel(&mut text, tree.earlier(UndoDistance::Steps(1)));
+        assert_eq!((text.as_str(), tree.seq()), ("one two", 2));
+        travel(&mut text, tree.earlier(UndoDistance::Steps(5)));
+        assert_eq!((text.as_str(), tree.seq()), ("", 0));
+        travel(&mut text, tree.later(UndoDistance::Steps(3)));
+        assert_eq!((text.as_str(), tree.seq()), ("one three", 3));
+        assert!(tree.later(UndoDistance::Steps(1)).is_none());
+    }
+
+    #[test]
+    fn tracks_the_saved_state() {
+        let mut tree = UndoTree::new(false);
+        assert!(!tree.is_saved());
+        let mut text = String::new();
+        tree.record(insert(0, "a"), 0);
+        tree.mark_saved();
+        assert!(tree.is_saved());
+        change(&mut tree, &mut text, vec![insert(0, "b")]);
+        assert!(!tree.is_saved());
+        travel(&mut text, tree.undo());
+        assert!(tree.is_saved());
+        tree.forget_saved();
+        assert!(!tree.is_saved());
+    }
+
+    #[test]
+    fn parses_distances() {
+        assert!(matches!(UndoDistance::parse(""), Some(UndoDistance::Steps(1))));
+        assert!(matches!(UndoDistance::parse(" 3 "), Some(UndoDistance::Steps(3))));
+        assert!(matches!(UndoDistance::parse("10s"), Some(UndoDistance::Seconds(10))));
+        assert!(matches!(UndoDistance::parse("2m"), Some(UndoDistance::Seconds(120))));
+        assert!(matches!(UndoDistance::parse("1h"), Some(UndoDistance::Seconds(3600))));
+        assert!(matches!(UndoDistance::parse("1d"), Some(UndoDistance::Seconds(86400))));
+        assert!(UndoDistance::parse("5x").is_none());
+        assert!(UndoDistance::parse("m").is_none());
+    }
+
+    #[test]
+    fn undo_files_only_load_for_the_same_text() {
+        let path = std::env::temp_dir().join(format!("fervim-undo-test-{}", std::process::id()));
+        let mut tree = UndoTree::new(true);
+        let mut text = "a".to_string();
+        change(&mut tree, &mut text, vec![insert(1, "b")]);
+        tree.save(&path, &text).unwrap();
+
+        assert!(UndoTree::load(&path, "a").is_none());
+        let mut loaded = UndoTree::load(&path, "ab").unwrap();
+        assert_eq!(travel(&mut text, loaded.undo()), Some(0));
+        assert_eq!(text, "a");
+        fs::remove_file(&path).unwrap();
+        assert!(UndoTree::load(&path, "ab").is_none());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn names_undo_files_after_the_whole_path() {
+        let name = undo_file_path(Path::new("/undo"), "/no/such/dir/file.txt");
+        assert_eq!(name, Path::new("/undo/%no%such%dir%file.txt"));
+    }
+}
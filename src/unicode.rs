@@ -0,0 +1,196 @@
+use unicode_segmentation::{GraphemeCursor, UnicodeSegmentation};
+use unicode_width::UnicodeWidthStr;
+
+// Columns handed to these helpers are char offsets into `line`, matching the
+// cursor columns the editor keeps.
+
+fn col_to_byte(line: &str, col: usize) -> usize {
+    line.char_indices().nth(col).map_or(line.len(), |(i, _)| i)
+}
+
+fn byte_to_col(line: &str, byte: usize) -> usize {
+    line[..byte].chars().count()
+}
+
+/// Column of the grapheme boundary following `col`, or the line length.
+pub fn next_grapheme_boundary(line: &str, col: usize) -> usize {
+    let byte = col_to_byte(line, col);
+    let mut cursor = GraphemeCursor::new(byte, line.len(), true);
+    match cursor.next_boundary(line, 0) {
+        Ok(Some(next)) => byte_to_col(line, next),
+        _ => byte_to_col(line, line.len()),
+    }
+}
+
+/// Column of the grapheme boundary preceding `col`, or 0.
+pub fn prev_grapheme_boundary(line: &str, col: usize) -> usize {
+    let byte = col_to_byte(line, col);
+    let mut cursor = GraphemeCursor::new(byte, line.len(), true);
+    match cursor.prev_boundary(line, 0) {
+        Ok(Some(prev)) => byte_to_col(line, prev),
+        _ => 0,
+    }
+}
+
+/// Snaps `col` back to the start of the grapheme cluster containing it.
+pub fn floor_grapheme_boundary(line: &str, col: usize) -> usize {
+    let byte = col_to_byte(line, col);
+    let mut cursor = GraphemeCursor::new(byte, line.len(), true);
+    if cursor.is_boundary(line, 0).unwrap_or(true) {
+        byte_to_col(line, byte)
+    } else {
+        prev_grapheme_boundary(line, col)
+    }
+}
+
+/// Returns at most the first `max` grapheme clusters of `text`.
+pub fn truncate_graphemes(text: &str, max: usize) -> &str {
+    match text.grapheme_indices(true).nth(max) {
+        Some((end, _)) => &text[..end],
+        None => text,
+    }
+}
+
+pub fn grapheme_count(text: &str) -> usize {
+    text.graphemes(true).count()
+}
+
+/// How a control character other than tab is shown, the way Vim does: `^M`
+/// for a carriage return, `^?` for DEL. `None` for anything else.
+fn caret_notation(grapheme: &str) -> Option<[char; 2]> {
+    let mut chars = grapheme.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if c != '\t' && (c < ' ' || c == '\x7f') => Some(['^', (c as u8 ^ 0x40) as char]),
+        _ => None,
+    }
+}
+
+/// Number of screen columns `grapheme` takes when it starts at display
+/// column `display_col`. Tabs run to the next multiple of `tabstop`, and
+/// other control characters take two, for their caret notation.
+pub fn grapheme_width(grapheme: &str, display_col: usize, tabstop: usize) -> usize {
+    if grapheme == "\t" {
+        let tabstop = tabstop.max(1);
+        tabstop - display_col % tabstop
+    } else if caret_notation(grapheme).is_some() {
+        2
+    } else {
+        grapheme.width()
+    }
+}
+
+/// Display column at which char column `col` of `line` starts.
+pub fn display_col(line: &str, col: usize, tabstop: usize) -> usize {
+    let byte = col_to_byte(line, col);
+    line[..byte]
+        .graphemes(true)
+        .fold(0, |display, g| display + grapheme_width(g, display, tabstop))
+}
+
+/// Display columns the grapheme at char column `col` of `line` covers, or
+/// the one column after the line if `col` is past its end.
+pub fn display_span(line: &str, col: usize, tabstop: usize) -> std::ops::Range<usize> {
+    let start = display_col(line, col, tabstop);
+    let width = line[col_to_byte(line, col)..].graphemes(true).next().map_or(1, |g| grapheme_width(g, start, tabstop));
+    start..start + width.max(1)
+}
+
+/// Lays out `line` for the screen: tabs are expanded to spaces, other
+/// control characters shown in caret notation and the result is cut to
+/// `width` columns without splitting a wide character. The first `left`
+/// columns are scrolled out of view; a wide character or tab cut by that
+/// edge shows as spaces.
+pub fn render_line(line: &str, left: usize, width: usize, tabstop: usize) -> String {
+    render_cols(line, 0..line.chars().count(), left, width, tabstop).1
+}
+
+/// Adds `grapheme`, `w` columns wide, to `rendered` as it's shown.
+fn push_grapheme(rendered: &mut String, grapheme: &str, w: usize) {
+    if grapheme == "\t" {
+        rendered.extend(std::iter::repeat_n(' ', w));
+    } else if let Some(caret) = caret_notation(grapheme) {
+        rendered.extend(caret);
+    } else {
+        rendered.push_str(grapheme);
+    }
+}
+
+/// Column of the first grapheme of `line` that starts at or after display
+/// column `display`, or the line length if the line is shorter than that.
+pub fn col_at_display(line: &str, display: usize, tabstop: usize) -> usize {
+    let mut current = 0;
+    let mut col = 0;
+    for g in line.graphemes(true) {
+        if current >= display {
+            return col;
+        }
+        current += grapheme_width(g, current, tabstop);
+        col += g.chars().count();
+    }
+    col
+}
+
+/// Renders just the graphemes of `line` whose columns fall in `cols`, laid
+/// out as `render_line` would. Returns the screen column, counted from
+/// `left`, the text starts at along with it. A range reaching past the end
+/// of the line shows the line break as a space.
+pub fn render_cols(line: &str, cols: std::ops::Range<usize>, left: usize, width: usize, tabstop: usize) -> (usize, String) {
+    let right = left + width;
+    let mut rendered = String::new();
+    let mut start = None;
+    let mut display = 0;
+    let mut col = 0;
+    for g in line.graphemes(true) {
+        let w = grapheme_width(g, display, tabstop);
+        if display + w > right {
+            return (start.unwrap_or(display.max(left)) - left, rendered);
+        }
+        if cols.contains(&col) && display + w > left {
+            start.get_or_insert(display.max(left));
+            if display < left {
+                rendered.extend(std::iter::repeat_n(' ', display + w - left));
+            } else {
+                push_grapheme(&mut rendered, g, w);
+            }
+        }
+        display += w;
+        col += g.chars().count();
+    }
+    if cols.end > col && (left..right).contains(&display) {
+        start.get_or_insert(display);
+        rendered.push(' ');
+    }
+    (start.unwrap_or(display.max(left)) - left, rendered)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_lines_scrolled_sideways() {
+        assert_eq!(render_line("a\tb", 0, 80, 4), "a   b");
+        assert_eq!(render_line("abcdef", 2, 3, 8), "cde");
+        // A tab or wide character cut by the left edge shows as spaces; one
+        // that doesn't fit on the right is left out.
+        assert_eq!(render_line("a\tb", 2, 80, 4), "  b");
+        assert_eq!(render_line("漢字x", 1, 80, 8), " 字x");
+        assert_eq!(render_line("漢字x", 0, 3, 8), "漢");
+        assert_eq!(render_line("a\rb", 1, 80, 8), "^Mb");
+    }
+
+    #[test]
+    fn renders_columns_relative_to_the_left_edge() {
+        assert_eq!(render_cols("abcdef", 3..5, 2, 80, 8), (1, "de".to_string()));
+        assert_eq!(render_cols("abcdef", 0..2, 2, 80, 8).1, "");
+        assert_eq!(render_cols("abc", 2..4, 1, 80, 8), (1, "c ".to_string()));
+        assert_eq!(render_cols("abcdef", 4..6, 0, 5, 8), (4, "e".to_string()));
+    }
+
+    #[test]
+    fn display_spans() {
+        assert_eq!(display_span("a\tb", 1, 4), 1..4);
+        assert_eq!(display_span("漢字", 1, 8), 2..4);
+        assert_eq!(display_span("ab", 2, 8), 2..3);
+    }
+}